@@ -0,0 +1,46 @@
+// The modules that don't touch the hardware, built for the host with their
+// tests, see "Host tests" in the README:
+//
+//     rustc --test host_tests.rs && ./host_tests
+//
+// The firmware links `core` and `collections` as crates of their own, here
+// `core` has to be named explicitly and `collections` is a stand-in on top
+// of `std`, so the modules compile unchanged.
+
+// the firmware uses more of the modules than the tests do
+#![allow(dead_code)]
+// the firmware's compiler only knows `a...b` range patterns
+#![allow(ellipsis_inclusive_range_patterns)]
+
+extern crate core;
+
+mod collections {
+    pub use std::boxed;
+    pub use std::string::{self, String};
+    pub use std::vec::{self, Vec};
+}
+
+#[path = "src/classify.rs"]
+mod classify;
+#[path = "src/command.rs"]
+mod command;
+#[path = "src/conntrack.rs"]
+mod conntrack;
+#[path = "src/dhcp_client.rs"]
+mod dhcp_client;
+#[path = "src/fault.rs"]
+mod fault;
+#[path = "src/filter.rs"]
+mod filter;
+#[path = "src/hosts.rs"]
+mod hosts;
+#[path = "src/log_level.rs"]
+mod log_level;
+#[path = "src/neighbors.rs"]
+mod neighbors;
+#[path = "src/pcapng_blocks.rs"]
+mod pcapng_blocks;
+#[path = "src/ring_buffer.rs"]
+mod ring_buffer;
+#[path = "src/synth.rs"]
+mod synth;
@@ -1,12 +1,43 @@
 from __future__ import print_function
+import codecs
+import errno
 import gdb
+import io
+import os
 import re
+import select
 import struct
 import sys
+import time
 
 
 class SemiHostHelper(object):
     SANE_FDS = (1, 2)
+    STDIN_FD = 0
+
+    # SYS_OPEN modes, in the order of the ARM specification
+    OPEN_MODES = ('r', 'rb', 'r+', 'r+b', 'w', 'wb', 'w+', 'w+b',
+                  'a', 'ab', 'a+', 'a+b')
+
+    # SYS_EXIT reason for a normal exit
+    ADP_STOPPED_APPLICATION_EXIT = 0x20026
+
+    # host files opened by the board, by fd
+    files = {}
+    next_fd = 3
+
+    # errno of the last failed call, returned by SYS_ERRNO
+    errno = 0
+
+    # returned by SYS_GET_CMDLINE, set with `pi SemiHostHelper.cmdline = '...'`
+    cmdline = ''
+
+    # stdout and stderr are flushed in chunks that may end in the middle of
+    # a character, the decoders keep the start until the rest arrives
+    decoders = {}
+
+    # SYS_CLOCK counts from here
+    start_time = time.time()
 
     @classmethod
     def on_break(cls):
@@ -36,8 +67,11 @@ class SemiHostHelper(object):
                 call_type = int(r0)
                 arg_addr = int(r1)
 
-                if call_type == 0x05:
-                    cls.handle_write(inf, arg_addr)
+                if call_type == 0x18:
+                    cls.handle_exit(inf, arg_addr)
+                elif call_type in cls.HANDLERS:
+                    result = getattr(cls, cls.HANDLERS[call_type])(inf, arg_addr)
+                    cls.set_result(result)
                     should_continue = True
                 else:
                     raise NotImplementedError(
@@ -54,26 +88,237 @@ class SemiHostHelper(object):
             gdb.execute('set $do_continue = 0');
 
     @classmethod
-    def handle_write(cls, inf, args_addr):
-        # argument struct has three u32 entries: fd, address, len
-        buf = inf.read_memory(args_addr, 12)
+    def set_result(cls, value):
+        # the board reads the result from r0 after the breakpoint
+        gdb.execute('set $r0 = {}'.format(value & 0xffffffff))
+
+    @classmethod
+    def read_args(cls, inf, args_addr, count):
+        # argument structs are `count` u32 entries
+        return struct.unpack('<' + 'L' * count,
+                             bytes(inf.read_memory(args_addr, 4 * count)))
+
+    @classmethod
+    def read_string(cls, inf, addr):
+        # null terminated, read in chunks until the terminator shows up
+        data = b''
+        while b'\0' not in data and len(data) < 4096:
+            data += bytes(inf.read_memory(addr + len(data), 64))
+        return data[:data.index(b'\0')] if b'\0' in data else data
+
+    @classmethod
+    def stdin_ready(cls):
+        try:
+            return bool(select.select([sys.stdin], [], [], 0)[0])
+        except (AttributeError, ValueError, io.UnsupportedOperation):
+            # not a real file (tests, some IDEs), reading won't block
+            return True
+
+    @classmethod
+    def fail(cls, e):
+        cls.errno = e.errno if e.errno is not None else errno.EIO
+        return -1
 
-        fd, addr, l = struct.unpack('<lll', buf)
+    @classmethod
+    def handle_open(cls, inf, args_addr):
+        # name address, mode, len
+        addr, mode, l = cls.read_args(inf, args_addr, 3)
+        name = bytes(inf.read_memory(addr, min(l, 4096))).decode('utf8')
+
+        if mode >= len(cls.OPEN_MODES):
+            cls.errno = errno.EINVAL
+            return -1
+
+        # ":tt" is the debugger console, reading is stdin, writing stdout
+        if name == ':tt':
+            return 0 if mode < 4 else 1
+
+        try:
+            # the board deals in bytes, text modes only matter on windows
+            f = open(name, cls.OPEN_MODES[mode].replace('b', '') + 'b')
+        except (IOError, OSError) as e:
+            gdb.write('semihosting: could not open {}: {}\n'.format(name, e))
+            return cls.fail(e)
+
+        fd = cls.next_fd
+        cls.next_fd += 1
+        cls.files[fd] = f
+        return fd
+
+    @classmethod
+    def handle_close(cls, inf, args_addr):
+        fd, = cls.read_args(inf, args_addr, 1)
+
+        # the console stays open
+        if fd == cls.STDIN_FD or fd in cls.SANE_FDS:
+            return 0
+
+        f = cls.files.pop(fd, None)
+        if f is None:
+            cls.errno = errno.EBADF
+            return -1
+        f.close()
+        return 0
+
+    @classmethod
+    def handle_writec(cls, inf, args_addr):
+        # r1 points to the character itself
+        c = bytes(inf.read_memory(args_addr, 1))
+        gdb.write(c.decode('latin1'))
+        return 0
+
+    @classmethod
+    def handle_write0(cls, inf, args_addr):
+        # r1 points to a null terminated string
+        gdb.write(cls.read_string(inf, args_addr).decode('utf8', 'replace'))
+        return 0
+
+    @classmethod
+    def handle_write(cls, inf, args_addr):
+        # fd, address, len
+        fd, addr, l = cls.read_args(inf, args_addr, 3)
 
         # limit length to 4M to avoid funky behavior
         l = min(l, 4 * 1024 * 1024)
 
         # sanity check file descriptor
-        if fd not in cls.SANE_FDS:
+        if fd not in cls.SANE_FDS and fd not in cls.files:
             raise ValueError(
                 'Refusing to write to file descriptor {}'
-                ' (not in {})'.format(fd, cls.SANE_FDS))
+                ' (not in {} or opened files)'.format(fd, cls.SANE_FDS))
 
         # read the memory
         data = bytes(inf.read_memory(addr, l))
 
+        if fd in cls.files:
+            try:
+                cls.files[fd].write(data)
+            except (IOError, OSError) as e:
+                cls.fail(e)
+                return l
+            return 0
+
         # we manually map FDs. encoding is fixed at the rust-native utf8
-        if fd == 1:
-            gdb.write(data.decode('utf8'))
-        elif fd == 2:
-            gdb.write(data.decode('utf8'))
+        gdb.write(cls.decoder(fd).decode(data))
+        return 0
+
+    @classmethod
+    def decoder(cls, fd):
+        if fd not in cls.decoders:
+            cls.decoders[fd] = codecs.getincrementaldecoder('utf8')(errors='replace')
+        return cls.decoders[fd]
+
+    @classmethod
+    def handle_read(cls, inf, args_addr):
+        # fd, address, len. returns the number of bytes *not* read
+        fd, addr, l = cls.read_args(inf, args_addr, 3)
+        l = min(l, 4 * 1024 * 1024)
+
+        if fd == cls.STDIN_FD:
+            # the board polls its console, so a read only returns lines
+            # that were already typed instead of stopping the board
+            if not cls.stdin_ready():
+                return l
+            # one line at a time, so the board sees input as it is typed
+            data = sys.stdin.readline(l).encode('utf8')[:l]
+        elif fd in cls.files:
+            try:
+                data = cls.files[fd].read(l)
+            except (IOError, OSError) as e:
+                cls.fail(e)
+                return l
+        else:
+            cls.errno = errno.EBADF
+            return -1
+
+        if data:
+            inf.write_memory(addr, data)
+        return l - len(data)
+
+    @classmethod
+    def handle_readc(cls, inf, args_addr):
+        c = sys.stdin.read(1)
+        # there is no way to signal the end of the input, send EOT
+        return ord(c) if c else 0x04
+
+    @classmethod
+    def handle_seek(cls, inf, args_addr):
+        # fd, absolute position
+        fd, pos = cls.read_args(inf, args_addr, 2)
+
+        f = cls.files.get(fd)
+        if f is None:
+            cls.errno = errno.EBADF
+            return -1
+        try:
+            f.seek(pos)
+        except (IOError, OSError) as e:
+            return cls.fail(e)
+        return 0
+
+    @classmethod
+    def handle_flen(cls, inf, args_addr):
+        fd, = cls.read_args(inf, args_addr, 1)
+
+        f = cls.files.get(fd)
+        if f is None:
+            cls.errno = errno.EBADF
+            return -1
+        f.flush()
+        return os.fstat(f.fileno()).st_size
+
+    @classmethod
+    def handle_clock(cls, inf, args_addr):
+        # centiseconds since the helper was loaded
+        return int((time.time() - cls.start_time) * 100)
+
+    @classmethod
+    def handle_time(cls, inf, args_addr):
+        return int(time.time())
+
+    @classmethod
+    def handle_errno(cls, inf, args_addr):
+        return cls.errno
+
+    @classmethod
+    def handle_get_cmdline(cls, inf, args_addr):
+        # buffer address, buffer len. the len is updated with the length
+        # of the command line, without the terminator
+        addr, l = cls.read_args(inf, args_addr, 2)
+
+        data = cls.cmdline.encode('utf8')
+        if len(data) + 1 > l:
+            cls.errno = errno.ENOSPC
+            return -1
+
+        inf.write_memory(addr, data + b'\0')
+        inf.write_memory(args_addr + 4, struct.pack('<L', len(data)))
+        return 0
+
+    @classmethod
+    def handle_exit(cls, inf, args_addr):
+        # on 32 bit targets r1 is the reason itself, not a pointer
+        if args_addr == cls.ADP_STOPPED_APPLICATION_EXIT:
+            gdb.write('semihosting: board exited\n')
+        else:
+            gdb.write('semihosting: board exited with reason 0x{:X}\n'
+                      .format(args_addr))
+        for f in cls.files.values():
+            f.close()
+        cls.files.clear()
+
+    HANDLERS = {
+        0x01: 'handle_open',
+        0x02: 'handle_close',
+        0x03: 'handle_writec',
+        0x04: 'handle_write0',
+        0x05: 'handle_write',
+        0x06: 'handle_read',
+        0x07: 'handle_readc',
+        0x0A: 'handle_seek',
+        0x0C: 'handle_flen',
+        0x10: 'handle_clock',
+        0x11: 'handle_time',
+        0x13: 'handle_errno',
+        0x15: 'handle_get_cmdline',
+    }
@@ -1,16 +1,13 @@
+# Prints the sine table of src/synth.rs: one period in 256 signed 16 bit
+# samples, for `Waveform::Sine`. Paste the output over `SINE`.
 import math
 
-samples = []
+SIZE = 256
+AMPLITUDE = 2**15 - 1
 
-def sin440(x):
-    return int(math.sin(440/(2*math.pi) * x / 48000) * 2**15 + 2**15)
-
-for i in range(48000):
-    samples.append(sin440(i))
-
-output = ""
-for sample in samples:
-    output += "0x{0:04x},".format(sample)
-
-print("[" + output + "]")
+samples = [int(round(math.sin(2 * math.pi * i / SIZE) * AMPLITUDE)) for i in range(SIZE)]
 
+lines = []
+for row in range(0, SIZE, 16):
+    lines.append("    " + ", ".join("{}".format(s) for s in samples[row:row + 16]) + ",")
+print("\n".join(lines))
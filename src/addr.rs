@@ -0,0 +1,79 @@
+// Text representations of addresses, for the LCD and the console.
+
+use core::cmp;
+use core::fmt::Write;
+use collections::string::String;
+
+use conntrack::{Address, Endpoint};
+use dhcp_client;
+
+/// `aa:bb:cc:dd:ee:ff`
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    let mut text = String::with_capacity(17);
+    for (i, byte) in mac.iter().enumerate() {
+        if i > 0 {
+            text.push(':');
+        }
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
+
+/// `10.0.0.1`
+pub fn format_ipv4(ip: &[u8; 4]) -> String {
+    let mut text = String::with_capacity(15);
+    for (i, byte) in ip.iter().enumerate() {
+        if i > 0 {
+            text.push('.');
+        }
+        let _ = write!(text, "{}", byte);
+    }
+    text
+}
+
+/// `10.0.0.23/24`, the address with the prefix length of its netmask
+pub fn format_ipv4_network(ip: &[u8; 4], netmask: &[u8; 4]) -> String {
+    format!("{}/{}", format_ipv4(ip), dhcp_client::netmask_prefix(netmask))
+}
+
+/// `fe80::1`, the longest run of zero groups is shortened to `::`
+pub fn format_ipv6(ip: &[u8; 16]) -> String {
+    let mut groups = [0u16; 8];
+    for (i, group) in groups.iter_mut().enumerate() {
+        *group = (ip[2 * i] as u16) << 8 | ip[2 * i + 1] as u16;
+    }
+    // start and length of the longest run, single zeros are kept
+    let mut zeros = (8, 1);
+    let mut i = 0;
+    while i < 8 {
+        let len = groups[i..].iter().take_while(|&&g| g == 0).count();
+        if len > zeros.1 {
+            zeros = (i, len);
+        }
+        i += cmp::max(len, 1);
+    }
+
+    let mut text = String::with_capacity(39);
+    let mut i = 0;
+    while i < 8 {
+        if i == zeros.0 {
+            text.push_str("::");
+            i += zeros.1;
+            continue;
+        }
+        if i > 0 && i != zeros.0 + zeros.1 {
+            text.push(':');
+        }
+        let _ = write!(text, "{:x}", groups[i]);
+        i += 1;
+    }
+    text
+}
+
+/// `10.0.0.1:80` or `[fe80::1]:80`
+pub fn format_endpoint(endpoint: &Endpoint) -> String {
+    match endpoint.address {
+        Address::V4(ip) => format!("{}.{}.{}.{}:{}", ip[0], ip[1], ip[2], ip[3], endpoint.port),
+        Address::V6(ip) => format!("[{}]:{}", format_ipv6(&ip), endpoint.port),
+    }
+}
@@ -0,0 +1,140 @@
+// Feeds SAI2 block A from a circular buffer with DMA2 stream 4, so the audio
+// keeps playing while the main loop is busy. The DMA interrupts at the half
+// and at the end of the buffer, the handler refills the half that was just
+// played with the callback given to `start`. Needs `Sound::init` first.
+//
+// The DMA registers are written directly, like the ones in `exceptions`.
+
+use core::ptr;
+
+use stm32f7::board::rcc::Rcc;
+use stm32f7::board::sai::Sai;
+
+use exceptions;
+use synth::Frame;
+
+/// Frames per half of the buffer, 5.3 ms at 48 kHz. The callback has that
+/// long to fill the other half.
+pub const HALF_FRAMES: usize = 256;
+
+const DMA2: usize = 0x4002_6400;
+const DMA2_HISR: *const u32 = (DMA2 + 0x04) as *const u32;
+const DMA2_HIFCR: *mut u32 = (DMA2 + 0x0c) as *mut u32;
+// the registers of stream 4
+const S4CR: *mut u32 = (DMA2 + 0x10 + 4 * 0x18) as *mut u32;
+const S4NDTR: *mut u32 = (DMA2 + 0x14 + 4 * 0x18) as *mut u32;
+const S4PAR: *mut u32 = (DMA2 + 0x18 + 4 * 0x18) as *mut u32;
+const S4M0AR: *mut u32 = (DMA2 + 0x1c + 4 * 0x18) as *mut u32;
+
+// stream 4 uses the lowest bits of HISR and HIFCR
+const FEIF4: u32 = 1 << 0;
+const DMEIF4: u32 = 1 << 2;
+const TEIF4: u32 = 1 << 3;
+const HTIF4: u32 = 1 << 4;
+const TCIF4: u32 = 1 << 5;
+const STREAM4_FLAGS: u32 = FEIF4 | DMEIF4 | TEIF4 | HTIF4 | TCIF4;
+
+const CR_EN: u32 = 1 << 0;
+const CR_TEIE: u32 = 1 << 2;
+const CR_HTIE: u32 = 1 << 3;
+const CR_TCIE: u32 = 1 << 4;
+const CR_DIR_MEMORY_TO_PERIPHERAL: u32 = 0b01 << 6;
+const CR_CIRC: u32 = 1 << 8;
+const CR_MINC: u32 = 1 << 10;
+const CR_PSIZE_HALF_WORD: u32 = 0b01 << 11;
+const CR_MSIZE_HALF_WORD: u32 = 0b01 << 13;
+const CR_PL_HIGH: u32 = 0b10 << 16;
+// SAI2_A is request channel 3 of stream 4
+const CR_CHSEL_SAI2_A: u32 = 3 << 25;
+
+// the data register of SAI2 block A, and the underrun flag in its status
+// and clear flag registers
+const SAI2_ADR: usize = 0x4001_5c00 + 0x20;
+const SAI2_ASR: *const u32 = (0x4001_5c00 + 0x18) as *const u32;
+const SAI2_ACLRFR: *mut u32 = (0x4001_5c00 + 0x1c) as *mut u32;
+const SAI_OVRUDR: u32 = 1 << 0;
+
+const DMA2_STREAM4_IRQ: usize = 60;
+
+// the DMA transfers half words, a left and a right one per frame
+static mut BUFFER: [Frame; 2 * HALF_FRAMES] = [Frame { left: 0, right: 0 }; 2 * HALF_FRAMES];
+static mut FILL: Option<fn(&mut [Frame])> = None;
+static mut UNDERRUNS: u32 = 0;
+static mut TRANSFER_ERRORS: u32 = 0;
+
+/// Starts the output. `fill` is called from the interrupt handler with half
+/// of the buffer at a time and has to overwrite all of it.
+pub fn start(sai: &mut Sai, rcc: &mut Rcc, fill: fn(&mut [Frame])) {
+    unsafe {
+        FILL = Some(fill);
+        fill(&mut BUFFER[..]);
+
+        rcc.ahb1enr.update(|r| r.set_dma2en(true));
+
+        ptr::write_volatile(S4CR, 0);
+        while ptr::read_volatile(S4CR) & CR_EN != 0 {}
+        ptr::write_volatile(DMA2_HIFCR, STREAM4_FLAGS);
+
+        ptr::write_volatile(S4PAR, SAI2_ADR as u32);
+        ptr::write_volatile(S4M0AR, BUFFER.as_ptr() as u32);
+        ptr::write_volatile(S4NDTR, (2 * BUFFER.len()) as u32);
+        ptr::write_volatile(S4CR, CR_CHSEL_SAI2_A | CR_PL_HIGH | CR_MSIZE_HALF_WORD | CR_PSIZE_HALF_WORD |
+                                  CR_MINC | CR_CIRC | CR_DIR_MEMORY_TO_PERIPHERAL |
+                                  CR_TCIE | CR_HTIE | CR_TEIE);
+
+        exceptions::set_interrupt_handler(DMA2_STREAM4_IRQ, dma2_stream4_handler);
+
+        let cr = ptr::read_volatile(S4CR);
+        ptr::write_volatile(S4CR, cr | CR_EN);
+    }
+    sai.acr1.update(|r| r.set_dmaen(true));
+}
+
+/// How often the SAI ran out of samples or the callback didn't finish a half
+/// before it was played, since `start`.
+pub fn underruns() -> u32 {
+    unsafe { ptr::read_volatile(&UNDERRUNS) }
+}
+
+/// DMA transfer errors since `start`, there shouldn't be any.
+pub fn transfer_errors() -> u32 {
+    unsafe { ptr::read_volatile(&TRANSFER_ERRORS) }
+}
+
+// the half the DMA doesn't read from at the moment
+fn free_half() -> usize {
+    // counts the half words left down to zero
+    let remaining = unsafe { ptr::read_volatile(S4NDTR) } as usize;
+    if remaining > 2 * HALF_FRAMES { 1 } else { 0 }
+}
+
+extern "C" fn dma2_stream4_handler() {
+    unsafe {
+        let flags = ptr::read_volatile(DMA2_HISR) & STREAM4_FLAGS;
+        ptr::write_volatile(DMA2_HIFCR, flags);
+
+        if flags & TEIF4 != 0 {
+            TRANSFER_ERRORS = TRANSFER_ERRORS.wrapping_add(1);
+        }
+        // both set means a whole half went by without an interrupt
+        if flags & HTIF4 != 0 && flags & TCIF4 != 0 {
+            UNDERRUNS = UNDERRUNS.wrapping_add(1);
+        }
+
+        if flags & (HTIF4 | TCIF4) != 0 {
+            let half = free_half();
+            if let Some(fill) = FILL {
+                fill(&mut BUFFER[half * HALF_FRAMES..(half + 1) * HALF_FRAMES]);
+            }
+            // the DMA already got to the half that was filled
+            if free_half() != half {
+                UNDERRUNS = UNDERRUNS.wrapping_add(1);
+            }
+        }
+
+        if ptr::read_volatile(SAI2_ASR) & SAI_OVRUDR != 0 {
+            ptr::write_volatile(SAI2_ACLRFR, SAI_OVRUDR);
+            UNDERRUNS = UNDERRUNS.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,1721 @@
+// Packet classification, independent of the board and the ethernet driver.
+// Addresses are plain byte arrays, the other modules turn them into what
+// they need themselves.
+
+use core::{cmp, str};
+use collections::{String, Vec};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const ETHERTYPE_LLDP: u16 = 0x88cc;
+// smaller values in the ethertype field are the length of an 802.3 frame
+const ETHERTYPE_MAX_LENGTH: u16 = 1500;
+// a service tag and a customer tag, more are not used in practice
+const VLAN_MAX_TAGS: usize = 2;
+
+const IP_PROTO_ICMP: u8 = 1;
+const IP_PROTO_TCP: u8 = 6;
+const IP_PROTO_UDP: u8 = 17;
+const IP_PROTO_ICMPV6: u8 = 58;
+
+// IPv6 extension headers, the ones not listed end the chain
+const IPV6_HOP_BY_HOP: u8 = 0;
+const IPV6_ROUTING: u8 = 43;
+const IPV6_FRAGMENT: u8 = 44;
+const IPV6_AUTHENTICATION: u8 = 51;
+const IPV6_DESTINATION_OPTIONS: u8 = 60;
+// a chain longer than this is more likely garbage than a real packet
+const IPV6_MAX_EXTENSION_HEADERS: usize = 8;
+
+const PORT_DNS: u16 = 53;
+const PORT_HTTP: u16 = 80;
+const PORT_HTTP_ALT: u16 = 8080;
+const PORT_DHCP_SERVER: u16 = 67;
+const PORT_DHCP_CLIENT: u16 = 68;
+const PORT_DHCPV6_CLIENT: u16 = 546;
+const PORT_DHCPV6_SERVER: u16 = 547;
+
+const DHCP_MAGIC_COOKIE: u32 = 0x63825363;
+const DHCP_OPTION_NETMASK: u8 = 1;
+const DHCP_OPTION_ROUTER: u8 = 3;
+const DHCP_OPTION_HOSTNAME: u8 = 12;
+const DHCP_OPTION_LEASE_TIME: u8 = 51;
+const DHCP_OPTION_SERVER_ID: u8 = 54;
+const DHCP_OPTION_RENEWAL_TIME: u8 = 58;
+const DHCP_OPTION_REBINDING_TIME: u8 = 59;
+const DHCP_OPTION_MESSAGE_TYPE: u8 = 53;
+const DHCP_OPTION_PAD: u8 = 0;
+const DHCP_OPTION_END: u8 = 255;
+const DHCP_DISCOVER: u8 = 1;
+const DHCP_OFFER: u8 = 2;
+const DHCP_REQUEST: u8 = 3;
+const DHCP_DECLINE: u8 = 4;
+const DHCP_ACK: u8 = 5;
+const DHCP_NAK: u8 = 6;
+const DHCP_RELEASE: u8 = 7;
+const DHCP_INFORM: u8 = 8;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_TIMESTAMP_REQUEST: u8 = 13;
+const ICMP_TIMESTAMP_REPLY: u8 = 14;
+
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const NDP_ROUTER_SOLICITATION: u8 = 133;
+const NDP_ROUTER_ADVERTISEMENT: u8 = 134;
+const NDP_NEIGHBOR_SOLICITATION: u8 = 135;
+const NDP_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const NDP_REDIRECT: u8 = 137;
+
+const DHCPV6_ADVERTISE: u8 = 2;
+const DHCPV6_REPLY: u8 = 7;
+const DHCPV6_RECONFIGURE: u8 = 10;
+const DHCPV6_RELAY_REPLY: u8 = 13;
+
+const LLDP_END: u8 = 0;
+const LLDP_PORT_ID: u8 = 2;
+const LLDP_TTL: u8 = 3;
+const LLDP_SYSTEM_NAME: u8 = 5;
+const LLDP_MANAGEMENT_ADDRESS: u8 = 8;
+const LLDP_PORT_ID_MAC: u8 = 3;
+const LLDP_PORT_ID_NETWORK_ADDRESS: u8 = 4;
+// IANA address family numbers, used by the LLDP management address
+const ADDRESS_FAMILY_IPV4: u8 = 1;
+
+// LLC and SNAP header of CDP frames: Cisco's OUI and protocol id 0x2000
+const CDP_SNAP_HEADER: [u8; 8] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x0c, 0x20, 0x00];
+const CDP_DEVICE_ID: u16 = 0x0001;
+const CDP_ADDRESSES: u16 = 0x0002;
+const CDP_PORT_ID: u16 = 0x0003;
+const CDP_MANAGEMENT_ADDRESSES: u16 = 0x0016;
+const CDP_PROTOCOL_NLPID: u8 = 1;
+const CDP_NLPID_IP: u8 = 0xcc;
+
+// longer names and port ids of neighbors are cut off to spare the heap
+const NEIGHBOR_MAX_TEXT_LEN: usize = 64;
+
+const HTTP_METHODS: [&'static [u8]; 9] = [b"GET", b"HEAD", b"POST", b"PUT", b"DELETE",
+                                          b"CONNECT", b"OPTIONS", b"TRACE", b"PATCH"];
+// longer request paths are cut off to spare the heap
+const HTTP_MAX_PATH_LEN: usize = 64;
+
+const DNS_HEADER_LEN: usize = 12;
+const DNS_MAX_NAME_LEN: usize = 255;
+// compression pointers followed per name, guards against pointer loops
+const DNS_MAX_POINTERS: usize = 16;
+// answers kept per packet, the rest are skipped to spare the heap
+const DNS_MAX_ANSWERS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseResultType {
+    Unknown,
+    Malformed(MalformedReason),
+    ARP,
+    DHCP,
+    ICMP,
+    DNS,
+    TCP,
+    HTTP,
+    ICMPv6,
+    /// Router and neighbor solicitations and advertisements, and redirects
+    NDP,
+    DHCPv6,
+    LLDP,
+    /// Cisco Discovery Protocol
+    CDP
+}
+
+/// Why a frame was classified as `ParseResultType::Malformed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedReason {
+    /// The frame ends before a header or payload it announces.
+    Truncated,
+    /// A length field is smaller than the header it belongs to.
+    BadLength,
+    /// An IP header with a version that doesn't match the ethertype.
+    BadIpVersion,
+    /// An IPv6 extension header chain that is too long.
+    BadIpv6Headers,
+    /// The DHCP option list runs past the end of the packet.
+    BadDhcpOptions,
+    /// A DNS name with a reserved label type, too long or with a pointer loop.
+    BadDnsName,
+}
+
+/// Number of distinct `ParseResultType`s, see `ParseResultType::index`.
+pub const TYPE_COUNT: usize = 13;
+
+static TYPE_NAMES: [&'static str; TYPE_COUNT] = ["Unknown", "Malformed", "ARP", "DHCP", "ICMP", "DNS", "TCP", "HTTP",
+                                                 "ICMPv6", "NDP", "DHCPv6", "LLDP", "CDP"];
+static TYPE_COLORS: [u16; TYPE_COUNT] = [0x0, 0xf800, 0xffff, 0xff00, 0xfff0, 0xaacc, 0x07ff, 0xccaa,
+                                         0xfd20, 0x801f, 0xfb56, 0x87f0, 0x041f];
+// the IPv6 variants are the lower case letters of their IPv4 counterparts
+static TYPE_LETTERS: [char; TYPE_COUNT] = ['?', '!', 'A', 'D', 'I', 'N', 'T', 'H', 'i', 'n', 'd', 'L', 'C'];
+
+impl ParseResultType {
+    /// A dense index in `0..TYPE_COUNT` for per type tables. All malformed
+    /// frames share one index.
+    pub fn index(&self) -> usize {
+        match *self {
+            ParseResultType::Unknown => 0,
+            ParseResultType::Malformed(_) => 1,
+            ParseResultType::ARP => 2,
+            ParseResultType::DHCP => 3,
+            ParseResultType::ICMP => 4,
+            ParseResultType::DNS => 5,
+            ParseResultType::TCP => 6,
+            ParseResultType::HTTP => 7,
+            ParseResultType::ICMPv6 => 8,
+            ParseResultType::NDP => 9,
+            ParseResultType::DHCPv6 => 10,
+            ParseResultType::LLDP => 11,
+            ParseResultType::CDP => 12,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        type_name(self.index())
+    }
+
+    /// The RGB565 base color a packet of this type is drawn with.
+    pub fn color(&self) -> u16 {
+        type_color(self.index())
+    }
+}
+
+/// The name of the type with the given `ParseResultType::index`.
+pub fn type_name(index: usize) -> &'static str {
+    TYPE_NAMES[index]
+}
+
+/// The names of all types, by `ParseResultType::index`.
+pub fn type_names() -> &'static [&'static str] {
+    &TYPE_NAMES
+}
+
+/// The base color of the type with the given `ParseResultType::index`.
+pub fn type_color(index: usize) -> u16 {
+    TYPE_COLORS[index]
+}
+
+/// A one letter abbreviation of the type with the given `ParseResultType::index`.
+pub fn type_letter(index: usize) -> char {
+    TYPE_LETTERS[index]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseResultDirection {
+    Request,
+    Response
+}
+
+/// The flags byte of a TCP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub fn fin(&self) -> bool { self.0 & 0x01 != 0 }
+    pub fn syn(&self) -> bool { self.0 & 0x02 != 0 }
+    pub fn rst(&self) -> bool { self.0 & 0x04 != 0 }
+    pub fn psh(&self) -> bool { self.0 & 0x08 != 0 }
+    pub fn ack(&self) -> bool { self.0 & 0x10 != 0 }
+    pub fn urg(&self) -> bool { self.0 & 0x20 != 0 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpInfo {
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: TcpFlags,
+}
+
+/// The type of a DNS question or resource record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsType {
+    A,
+    NS,
+    CNAME,
+    SOA,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    SRV,
+    Other(u16)
+}
+
+impl DnsType {
+    fn from_u16(value: u16) -> Self {
+        match value {
+            1 => DnsType::A,
+            2 => DnsType::NS,
+            5 => DnsType::CNAME,
+            6 => DnsType::SOA,
+            12 => DnsType::PTR,
+            15 => DnsType::MX,
+            16 => DnsType::TXT,
+            28 => DnsType::AAAA,
+            33 => DnsType::SRV,
+            other => DnsType::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsRecordData {
+    A([u8; 4]),
+    AAAA([u8; 16]),
+    /// The target of NS, CNAME and PTR records
+    Name(String),
+    MX { preference: u16, exchange: String },
+    /// The first character string of a TXT record
+    TXT(String),
+    Other
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsAnswer {
+    pub name: String,
+    pub record_type: DnsType,
+    pub ttl: u32,
+    pub data: DnsRecordData
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsInfo {
+    pub id: u16,
+    /// The type asked for in the first question
+    pub query_type: Option<DnsType>,
+    pub answers: Vec<DnsAnswer>
+}
+
+/// What a DHCP message tells a client, the input of `DhcpClient::on_dhcp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpInfo {
+    pub message_type: u8,
+    pub xid: u32,
+    /// The hardware address of the client the message is about
+    pub client_mac: [u8; 6],
+    /// The address offered or acknowledged
+    pub your_address: [u8; 4],
+    /// The source of the frame, the server or a relay agent
+    pub server_mac: [u8; 6],
+    pub server_id: Option<[u8; 4]>,
+    pub netmask: Option<[u8; 4]>,
+    pub router: Option<[u8; 4]>,
+    /// The lease time, T1 and T2, in seconds
+    pub lease_time: Option<u32>,
+    pub renewal_time: Option<u32>,
+    pub rebinding_time: Option<u32>
+}
+
+/// What a switch announces about itself and the port we are connected to,
+/// with LLDP or CDP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborInfo {
+    /// The system name (LLDP) or device id (CDP)
+    pub name: Option<String>,
+    pub port: Option<String>,
+    /// The first IPv4 management address
+    pub management: Option<[u8; 4]>,
+    /// How long the announcement is valid, in seconds
+    pub ttl: u16
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseResult {
+    /// Length of the whole frame in bytes
+    pub len: usize,
+    pub mac_src: Option<[u8; 6]>,
+    pub mac_dst: Option<[u8; 6]>,
+    /// The id of the outer 802.1Q tag, the tags are stripped before the
+    /// payload is classified
+    pub vlan_id: Option<u16>,
+    pub ipv4_src: Option<[u8; 4]>,
+    pub ipv4_dst: Option<[u8; 4]>,
+    pub ipv6_src: Option<[u8; 16]>,
+    pub ipv6_dst: Option<[u8; 16]>,
+    pub port_src: Option<u16>,
+    pub port_dst: Option<u16>,
+    pub pkt_type: ParseResultType,
+    pub direction: Option<ParseResultDirection>,
+    /// The name asked for in the first DNS question, or the host name a
+    /// DHCP client sends along
+    pub hostname: Option<String>,
+    pub dns: Option<DnsInfo>,
+    pub tcp: Option<TcpInfo>,
+    /// The request path of HTTP requests
+    pub path: Option<String>,
+    /// The contents of LLDP and CDP announcements
+    pub neighbor: Option<NeighborInfo>,
+    /// The sender hardware address of ARP packets, not necessarily the
+    /// source of the frame
+    pub arp_sender_mac: Option<[u8; 6]>,
+    /// What the DHCP client needs of DHCP messages
+    pub dhcp: Option<DhcpInfo>
+}
+
+impl ParseResult {
+    pub fn new() -> Self {
+        ParseResult {
+            len: 0,
+            mac_src: None,
+            mac_dst: None,
+            vlan_id: None,
+            ipv4_src: None,
+            ipv4_dst: None,
+            ipv6_src: None,
+            ipv6_dst: None,
+            port_src: None,
+            port_dst: None,
+            pkt_type: ParseResultType::Unknown,
+            direction: None,
+            hostname: None,
+            dns: None,
+            tcp: None,
+            path: None,
+            neighbor: None,
+            arp_sender_mac: None,
+            dhcp: None
+        }
+    }
+}
+
+/// Classifies a raw ethernet frame. Protocols we don't know end up as
+/// `ParseResultType::Unknown`, broken frames as `ParseResultType::Malformed`.
+/// In both cases everything that could be extracted before that point is
+/// still filled in. This never panics, whatever `data` contains.
+pub fn classify(data: &[u8]) -> ParseResult {
+    let mut parse_result = ParseResult::new();
+    parse_result.len = data.len();
+    if let Err(reason) = parse_ethernet(data, &mut parse_result) {
+        parse_result.pkt_type = ParseResultType::Malformed(reason);
+    }
+    parse_result
+}
+
+// The parsers below leave `pkt_type` untouched for protocols they don't know
+// and bail out with an error as soon as a frame contradicts itself.
+
+fn parse_ethernet(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let header = slice(data, 0, 14)?;
+    parse_result.mac_dst = Some(mac_address(&header[0..6]));
+    parse_result.mac_src = Some(mac_address(&header[6..12]));
+
+    let mut ethertype = read_u16(header, 12)?;
+    let mut offset = 14;
+    for _ in 0..VLAN_MAX_TAGS {
+        if ethertype != ETHERTYPE_VLAN && ethertype != ETHERTYPE_QINQ {
+            break;
+        }
+        // priority and drop eligible bit in the top nibble, then the id
+        let tag = read_u16(data, offset)?;
+        if parse_result.vlan_id.is_none() {
+            parse_result.vlan_id = Some(tag & 0x0fff);
+        }
+        ethertype = read_u16(data, offset + 2)?;
+        offset += 4;
+    }
+
+    let payload = &data[offset..];
+    match ethertype {
+        ETHERTYPE_IPV4 => parse_ipv4(payload, parse_result),
+        ETHERTYPE_ARP => parse_arp(payload, parse_result),
+        ETHERTYPE_IPV6 => parse_ipv6(payload, parse_result),
+        ETHERTYPE_LLDP => parse_lldp(payload, parse_result),
+        len if len <= ETHERTYPE_MAX_LENGTH => parse_llc(slice(payload, 0, len as usize)?, parse_result),
+        _ => Ok(()),
+    }
+}
+
+fn parse_lldp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    parse_result.pkt_type = ParseResultType::LLDP;
+    let mut neighbor = NeighborInfo { name: None, port: None, management: None, ttl: 0 };
+
+    // 7 bits type and 9 bits length per TLV, up to the end TLV
+    let mut offset = 0;
+    loop {
+        let type_len = read_u16(data, offset)?;
+        let len = (type_len & 0x01ff) as usize;
+        let value = slice(data, offset + 2, offset + 2 + len)?;
+        offset += 2 + len;
+        match (type_len >> 9) as u8 {
+            LLDP_END => break,
+            LLDP_PORT_ID => {
+                let subtype = read_u8(value, 0)?;
+                let id = &value[1..];
+                neighbor.port = Some(match subtype {
+                    LLDP_PORT_ID_MAC if id.len() == 6 => {
+                        format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", id[0], id[1], id[2], id[3], id[4], id[5])
+                    }
+                    LLDP_PORT_ID_NETWORK_ADDRESS if id.len() == 5 && id[0] == ADDRESS_FAMILY_IPV4 => {
+                        format!("{}.{}.{}.{}", id[1], id[2], id[3], id[4])
+                    }
+                    // interface names and aliases, and locally assigned ids
+                    _ => neighbor_text(id),
+                });
+            }
+            LLDP_TTL => neighbor.ttl = read_u16(value, 0)?,
+            LLDP_SYSTEM_NAME => neighbor.name = Some(neighbor_text(value)),
+            LLDP_MANAGEMENT_ADDRESS => {
+                // the address length counts the address family as well
+                let address_len = read_u8(value, 0)?;
+                let family = read_u8(value, 1)?;
+                if address_len == 5 && family == ADDRESS_FAMILY_IPV4 && neighbor.management.is_none() {
+                    neighbor.management = Some(ipv4_address(slice(value, 2, 6)?));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    parse_result.neighbor = Some(neighbor);
+    Ok(())
+}
+
+/// 802.3 frames with LLC instead of an ethertype. Of those only CDP is
+/// recognized, spanning tree and the rest stay unknown.
+fn parse_llc(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    if data.starts_with(&CDP_SNAP_HEADER) {
+        parse_cdp(&data[CDP_SNAP_HEADER.len()..], parse_result)
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_cdp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    // version, TTL and checksum
+    let ttl = read_u8(data, 1)?;
+    parse_result.pkt_type = ParseResultType::CDP;
+    let mut neighbor = NeighborInfo { name: None, port: None, management: None, ttl: ttl as u16 };
+    // the management addresses win over the interface addresses
+    let mut interface_address = None;
+
+    // 16 bits type and 16 bits length per TLV, the length includes both
+    let mut offset = 4;
+    while offset < data.len() {
+        let tlv_type = read_u16(data, offset)?;
+        let len = read_u16(data, offset + 2)? as usize;
+        if len < 4 {
+            return Err(MalformedReason::BadLength);
+        }
+        let value = slice(data, offset + 4, offset + len)?;
+        offset += len;
+        match tlv_type {
+            CDP_DEVICE_ID => neighbor.name = Some(neighbor_text(value)),
+            CDP_PORT_ID => neighbor.port = Some(neighbor_text(value)),
+            CDP_ADDRESSES => interface_address = cdp_ipv4_address(value)?,
+            CDP_MANAGEMENT_ADDRESSES => neighbor.management = cdp_ipv4_address(value)?,
+            _ => {}
+        }
+    }
+    if neighbor.management.is_none() {
+        neighbor.management = interface_address;
+    }
+
+    parse_result.neighbor = Some(neighbor);
+    Ok(())
+}
+
+/// The first IPv4 address in a CDP address list.
+fn cdp_ipv4_address(data: &[u8]) -> Result<Option<[u8; 4]>, MalformedReason> {
+    let count = read_u32(data, 0)?;
+    let mut offset = 4;
+    for _ in 0..count {
+        // the protocol is identified by its NLPID, or an 802.2 header for
+        // everything that doesn't have one
+        let protocol_type = read_u8(data, offset)?;
+        let protocol_len = read_u8(data, offset + 1)? as usize;
+        let protocol = slice(data, offset + 2, offset + 2 + protocol_len)?;
+        let address_len = read_u16(data, offset + 2 + protocol_len)? as usize;
+        let address_start = offset + 4 + protocol_len;
+        let address = slice(data, address_start, address_start + address_len)?;
+        if protocol_type == CDP_PROTOCOL_NLPID && protocol == &[CDP_NLPID_IP][..] && address_len == 4 {
+            return Ok(Some(ipv4_address(address)));
+        }
+        offset = address_start + address_len;
+    }
+    Ok(None)
+}
+
+fn neighbor_text(data: &[u8]) -> String {
+    String::from_utf8_lossy(&data[..cmp::min(data.len(), NEIGHBOR_MAX_TEXT_LEN)]).into_owned()
+}
+
+fn parse_arp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let hardware_len = read_u8(data, 4)? as usize;
+    let protocol_len = read_u8(data, 5)? as usize;
+    let operation = read_u16(data, 6)?;
+    parse_result.pkt_type = ParseResultType::ARP;
+    parse_result.direction = match operation {
+        ARP_REQUEST => Some(ParseResultDirection::Request),
+        ARP_REPLY => Some(ParseResultDirection::Response),
+        _ => None,
+    };
+
+    // sender and target addresses, if this is IPv4 over ethernet
+    if read_u16(data, 2)? == ETHERTYPE_IPV4 && hardware_len == 6 && protocol_len == 4 {
+        let addresses = slice(data, 8, 28)?;
+        parse_result.arp_sender_mac = Some(mac_address(&addresses[0..6]));
+        parse_result.ipv4_src = Some(ipv4_address(&addresses[6..10]));
+        parse_result.ipv4_dst = Some(ipv4_address(&addresses[16..20]));
+    }
+    Ok(())
+}
+
+fn parse_ipv4(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let version_ihl = read_u8(data, 0)?;
+    if version_ihl >> 4 != 4 {
+        return Err(MalformedReason::BadIpVersion);
+    }
+    let header_len = ((version_ihl & 0x0f) as usize) * 4;
+    let total_len = read_u16(data, 2)? as usize;
+    if header_len < 20 || total_len < header_len {
+        return Err(MalformedReason::BadLength);
+    }
+    // ethernet pads short frames, so only trust the length from the header
+    let packet = slice(data, 0, total_len)?;
+
+    parse_result.ipv4_src = Some(ipv4_address(&packet[12..16]));
+    parse_result.ipv4_dst = Some(ipv4_address(&packet[16..20]));
+
+    let payload = &packet[header_len..];
+    match packet[9] {
+        IP_PROTO_UDP => parse_udp(payload, parse_result),
+        IP_PROTO_TCP => parse_tcp(payload, parse_result),
+        IP_PROTO_ICMP => parse_icmp(payload, parse_result),
+        _ => Ok(()),
+    }
+}
+
+fn parse_ipv6(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let header = slice(data, 0, 40)?;
+    if header[0] >> 4 != 6 {
+        return Err(MalformedReason::BadIpVersion);
+    }
+    let payload_len = read_u16(header, 4)? as usize;
+    // as with IPv4, the rest is ethernet padding
+    let packet = slice(data, 0, 40 + payload_len)?;
+
+    let mut src = [0; 16];
+    src.copy_from_slice(&header[8..24]);
+    let mut dst = [0; 16];
+    dst.copy_from_slice(&header[24..40]);
+    parse_result.ipv6_src = Some(src);
+    parse_result.ipv6_dst = Some(dst);
+
+    let mut next_header = header[6];
+    let mut offset = 40;
+    // the extension headers and the upper layer header after them
+    for _ in 0..IPV6_MAX_EXTENSION_HEADERS + 1 {
+        let len = match next_header {
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DESTINATION_OPTIONS => (read_u8(packet, offset + 1)? as usize + 1) * 8,
+            IPV6_AUTHENTICATION => (read_u8(packet, offset + 1)? as usize + 2) * 4,
+            IPV6_FRAGMENT => {
+                // only the first fragment starts with the upper layer header
+                if read_u16(packet, offset + 2)? & 0xfff8 != 0 {
+                    return Ok(());
+                }
+                8
+            }
+            _ => {
+                let payload = &packet[offset..];
+                return match next_header {
+                    IP_PROTO_UDP => parse_udp(payload, parse_result),
+                    IP_PROTO_TCP => parse_tcp(payload, parse_result),
+                    IP_PROTO_ICMPV6 => parse_icmpv6(payload, parse_result),
+                    // ESP, "no next header" and protocols we don't know
+                    _ => Ok(()),
+                };
+            }
+        };
+        next_header = read_u8(packet, offset)?;
+        if offset + len > packet.len() {
+            return Err(MalformedReason::Truncated);
+        }
+        offset += len;
+    }
+    Err(MalformedReason::BadIpv6Headers)
+}
+
+fn parse_icmpv6(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let message_type = read_u8(data, 0)?;
+    parse_result.pkt_type = match message_type {
+        NDP_ROUTER_SOLICITATION...NDP_REDIRECT => ParseResultType::NDP,
+        _ => ParseResultType::ICMPv6,
+    };
+    parse_result.direction = match message_type {
+        ICMPV6_ECHO_REQUEST | NDP_ROUTER_SOLICITATION | NDP_NEIGHBOR_SOLICITATION => Some(ParseResultDirection::Request),
+        ICMPV6_ECHO_REPLY | NDP_ROUTER_ADVERTISEMENT | NDP_NEIGHBOR_ADVERTISEMENT => Some(ParseResultDirection::Response),
+        _ => None,
+    };
+    Ok(())
+}
+
+fn parse_icmp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    parse_result.pkt_type = ParseResultType::ICMP;
+    parse_result.direction = match read_u8(data, 0)? {
+        ICMP_ECHO_REQUEST | ICMP_TIMESTAMP_REQUEST => Some(ParseResultDirection::Request),
+        ICMP_ECHO_REPLY | ICMP_TIMESTAMP_REPLY => Some(ParseResultDirection::Response),
+        _ => None,
+    };
+    Ok(())
+}
+
+fn parse_udp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let src_port = read_u16(data, 0)?;
+    let dst_port = read_u16(data, 2)?;
+    let len = read_u16(data, 4)? as usize;
+    if len < 8 {
+        return Err(MalformedReason::BadLength);
+    }
+    let payload = slice(data, 8, len)?;
+
+    parse_result.port_src = Some(src_port);
+    parse_result.port_dst = Some(dst_port);
+
+    let is_port = |port| src_port == port || dst_port == port;
+    if is_port(PORT_DHCP_SERVER) && is_port(PORT_DHCP_CLIENT) {
+        return parse_dhcp(payload, parse_result);
+    } else if is_port(PORT_DHCPV6_SERVER) && (is_port(PORT_DHCPV6_CLIENT) || src_port == dst_port) {
+        return parse_dhcpv6(payload, parse_result);
+    } else if is_port(PORT_DNS) {
+        return parse_dns(payload, parse_result);
+    }
+    Ok(())
+}
+
+fn parse_dns(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let id = read_u16(data, 0)?;
+    let flags = read_u16(data, 2)?;
+    let question_count = read_u16(data, 4)?;
+    let answer_count = read_u16(data, 6)?;
+    parse_result.pkt_type = ParseResultType::DNS;
+    parse_result.direction = if flags & 0x8000 == 0 {
+        Some(ParseResultDirection::Request)
+    } else {
+        Some(ParseResultDirection::Response)
+    };
+
+    let mut dns = DnsInfo { id: id, query_type: None, answers: Vec::new() };
+    let mut offset = DNS_HEADER_LEN;
+
+    for i in 0..question_count {
+        let (name, name_len) = dns_name(data, offset)?;
+        let query_type = DnsType::from_u16(read_u16(data, offset + name_len)?);
+        // name, type and class
+        offset += name_len + 4;
+        if i == 0 {
+            parse_result.hostname = Some(name);
+            dns.query_type = Some(query_type);
+        }
+    }
+
+    for _ in 0..answer_count {
+        let (name, name_len) = dns_name(data, offset)?;
+        offset += name_len;
+        let record_type = DnsType::from_u16(read_u16(data, offset)?);
+        let ttl = read_u32(data, offset + 4)?;
+        let data_len = read_u16(data, offset + 8)? as usize;
+        offset += 10;
+        let data_start = offset;
+        let record = slice(data, data_start, data_start + data_len)?;
+        offset += data_len;
+
+        if dns.answers.len() >= DNS_MAX_ANSWERS {
+            continue;
+        }
+        let record_data = match record_type {
+            DnsType::A if data_len == 4 => DnsRecordData::A(ipv4_address(record)),
+            DnsType::AAAA if data_len == 16 => {
+                let mut address = [0; 16];
+                address.copy_from_slice(record);
+                DnsRecordData::AAAA(address)
+            }
+            DnsType::NS | DnsType::CNAME | DnsType::PTR => {
+                DnsRecordData::Name(dns_name(data, data_start)?.0)
+            }
+            DnsType::MX => DnsRecordData::MX {
+                preference: read_u16(record, 0)?,
+                exchange: dns_name(data, data_start + 2)?.0,
+            },
+            DnsType::TXT => {
+                let len = read_u8(record, 0)? as usize;
+                DnsRecordData::TXT(String::from_utf8_lossy(slice(record, 1, 1 + len)?).into_owned())
+            }
+            _ => DnsRecordData::Other,
+        };
+        dns.answers.push(DnsAnswer { name: name, record_type: record_type, ttl: ttl, data: record_data });
+    }
+
+    parse_result.dns = Some(dns);
+    Ok(())
+}
+
+/// Decodes the (possibly compressed) domain name starting at `offset` of the
+/// DNS message `data`. Returns the dotted name and the number of bytes the
+/// name occupies at `offset`.
+fn dns_name(data: &[u8], offset: usize) -> Result<(String, usize), MalformedReason> {
+    let mut name = String::new();
+    let mut position = offset;
+    // length of the name at `offset`, known as soon as the first pointer is followed
+    let mut len = None;
+    let mut pointers = 0;
+
+    loop {
+        let label_len = read_u8(data, position)? as usize;
+        match label_len & 0xc0 {
+            0x00 if label_len == 0 => break,
+            0x00 => {
+                let label = slice(data, position + 1, position + 1 + label_len)?;
+                if name.len() + label_len + 1 > DNS_MAX_NAME_LEN {
+                    return Err(MalformedReason::BadDnsName);
+                }
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                position += 1 + label_len;
+            }
+            0xc0 => {
+                let target = (read_u16(data, position)? & 0x3fff) as usize;
+                if len.is_none() {
+                    len = Some(position + 2 - offset);
+                }
+                pointers += 1;
+                if pointers > DNS_MAX_POINTERS {
+                    return Err(MalformedReason::BadDnsName);
+                }
+                position = target;
+            }
+            // 0x40 and 0x80 are reserved label types
+            _ => return Err(MalformedReason::BadDnsName),
+        }
+    }
+
+    // after a pointer `position` may be anywhere before `offset`
+    Ok((name, len.unwrap_or_else(|| position + 1 - offset)))
+}
+
+fn parse_tcp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let src_port = read_u16(data, 0)?;
+    let dst_port = read_u16(data, 2)?;
+    let seq = read_u32(data, 4)?;
+    let ack = read_u32(data, 8)?;
+    let header_len = ((read_u8(data, 12)? >> 4) as usize) * 4;
+    let flags = TcpFlags(read_u8(data, 13)?);
+    if header_len < 20 {
+        return Err(MalformedReason::BadLength);
+    }
+    let payload = slice(data, header_len, data.len())?;
+
+    parse_result.port_src = Some(src_port);
+    parse_result.port_dst = Some(dst_port);
+    parse_result.tcp = Some(TcpInfo { seq: seq, ack: ack, flags: flags });
+    parse_result.pkt_type = ParseResultType::TCP;
+
+    let is_port = |port| src_port == port || dst_port == port;
+    if is_port(PORT_HTTP) || is_port(PORT_HTTP_ALT) {
+        parse_http(payload, parse_result);
+    }
+    Ok(())
+}
+
+/// Recognizes the first segment of an HTTP request or response. Anything else
+/// on the HTTP ports (continuation segments, bare ACKs) stays plain TCP.
+fn parse_http(data: &[u8], parse_result: &mut ParseResult) {
+    if data.starts_with(b"HTTP/1.") {
+        parse_result.pkt_type = ParseResultType::HTTP;
+        parse_result.direction = Some(ParseResultDirection::Response);
+        return;
+    }
+
+    for method in HTTP_METHODS.iter() {
+        if data.starts_with(method) && data.get(method.len()) == Some(&b' ') {
+            parse_result.pkt_type = ParseResultType::HTTP;
+            parse_result.direction = Some(ParseResultDirection::Request);
+
+            let target = &data[method.len() + 1..];
+            let end = target.iter()
+                .position(|&b| b == b' ' || b == b'\r' || b == b'\n')
+                .unwrap_or(target.len());
+            parse_result.path = str::from_utf8(&target[..end]).ok().map(|path| {
+                // cut long paths between two characters
+                let mut len = cmp::min(path.len(), HTTP_MAX_PATH_LEN);
+                while !path.is_char_boundary(len) {
+                    len -= 1;
+                }
+                String::from(&path[..len])
+            });
+            return;
+        }
+    }
+}
+
+fn parse_dhcp(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    // plain BOOTP without the magic cookie is not something we care about
+    if read_u32(data, 236)? != DHCP_MAGIC_COOKIE {
+        return Ok(());
+    }
+    parse_result.pkt_type = ParseResultType::DHCP;
+
+    // the address the server hands out ("your IP address")
+    let yiaddr = ipv4_address(&data[16..20]);
+    let options = &data[240..];
+    let message_type = dhcp_option(options, DHCP_OPTION_MESSAGE_TYPE)
+        .map_err(|_| MalformedReason::BadDhcpOptions)?;
+    if let Some(hostname) = dhcp_option(options, DHCP_OPTION_HOSTNAME).map_err(|_| MalformedReason::BadDhcpOptions)? {
+        parse_result.hostname = Some(String::from_utf8_lossy(hostname).into_owned());
+    }
+    match message_type.and_then(|o| o.first()) {
+        Some(&DHCP_DISCOVER) | Some(&DHCP_REQUEST) | Some(&DHCP_DECLINE) |
+        Some(&DHCP_RELEASE) | Some(&DHCP_INFORM) => {
+            parse_result.direction = Some(ParseResultDirection::Request);
+        }
+        Some(&DHCP_OFFER) => {
+            parse_result.ipv4_dst = Some(yiaddr);
+            parse_result.direction = Some(ParseResultDirection::Response);
+        }
+        Some(&DHCP_ACK) => {
+            parse_result.ipv4_src = Some(yiaddr);
+            parse_result.direction = Some(ParseResultDirection::Response);
+        }
+        Some(&DHCP_NAK) => parse_result.direction = Some(ParseResultDirection::Response),
+        _ => {}
+    }
+
+    if let Some(&message_type) = message_type.and_then(|o| o.first()) {
+        parse_result.dhcp = Some(DhcpInfo {
+            message_type: message_type,
+            xid: read_u32(data, 4)?,
+            client_mac: mac_address(&data[28..34]),
+            your_address: yiaddr,
+            server_mac: parse_result.mac_src.unwrap_or([0; 6]),
+            server_id: dhcp_address_option(options, DHCP_OPTION_SERVER_ID)?,
+            netmask: dhcp_address_option(options, DHCP_OPTION_NETMASK)?,
+            router: dhcp_address_option(options, DHCP_OPTION_ROUTER)?,
+            lease_time: dhcp_seconds_option(options, DHCP_OPTION_LEASE_TIME)?,
+            renewal_time: dhcp_seconds_option(options, DHCP_OPTION_RENEWAL_TIME)?,
+            rebinding_time: dhcp_seconds_option(options, DHCP_OPTION_REBINDING_TIME)?
+        });
+    }
+    Ok(())
+}
+
+fn parse_dhcpv6(data: &[u8], parse_result: &mut ParseResult) -> Result<(), MalformedReason> {
+    let message_type = read_u8(data, 0)?;
+    parse_result.pkt_type = ParseResultType::DHCPv6;
+    parse_result.direction = match message_type {
+        DHCPV6_ADVERTISE | DHCPV6_REPLY | DHCPV6_RECONFIGURE | DHCPV6_RELAY_REPLY => Some(ParseResultDirection::Response),
+        // client messages (solicit, request, renew, ...) and relay forwards
+        _ => Some(ParseResultDirection::Request),
+    };
+    Ok(())
+}
+
+/// Returns the value of the first DHCP option with the given code, or `None`
+/// if the option is not present.
+fn dhcp_option(mut options: &[u8], code: u8) -> Result<Option<&[u8]>, MalformedReason> {
+    loop {
+        match read_u8(options, 0)? {
+            DHCP_OPTION_END => return Ok(None),
+            DHCP_OPTION_PAD => options = &options[1..],
+            option => {
+                let len = read_u8(options, 1)? as usize;
+                let value = slice(options, 2, 2 + len)?;
+                if option == code {
+                    return Ok(Some(value));
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+}
+
+/// The first address of an option holding a list of them, like the routers.
+fn dhcp_address_option(options: &[u8], code: u8) -> Result<Option<[u8; 4]>, MalformedReason> {
+    match dhcp_option(options, code).map_err(|_| MalformedReason::BadDhcpOptions)? {
+        Some(value) => Ok(Some(ipv4_address(slice(value, 0, 4).map_err(|_| MalformedReason::BadDhcpOptions)?))),
+        None => Ok(None),
+    }
+}
+
+fn dhcp_seconds_option(options: &[u8], code: u8) -> Result<Option<u32>, MalformedReason> {
+    match dhcp_option(options, code).map_err(|_| MalformedReason::BadDhcpOptions)? {
+        Some(value) => read_u32(value, 0).map(Some).map_err(|_| MalformedReason::BadDhcpOptions),
+        None => Ok(None),
+    }
+}
+
+fn slice(data: &[u8], from: usize, to: usize) -> Result<&[u8], MalformedReason> {
+    if from <= to && to <= data.len() {
+        Ok(&data[from..to])
+    } else {
+        Err(MalformedReason::Truncated)
+    }
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, MalformedReason> {
+    slice(data, offset, offset + 1).map(|bytes| bytes[0])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, MalformedReason> {
+    let bytes = slice(data, offset, offset + 2)?;
+    Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, MalformedReason> {
+    let bytes = slice(data, offset, offset + 4)?;
+    Ok((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+}
+
+// the callers hand in slices of the right length
+
+fn mac_address(bytes: &[u8]) -> [u8; 6] {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(bytes);
+    mac
+}
+
+fn ipv4_address(bytes: &[u8]) -> [u8; 4] {
+    let mut ip = [0; 4];
+    ip.copy_from_slice(bytes);
+    ip
+}
+
+#[cfg(test)]
+mod tests {
+    use collections::Vec;
+    use super::*;
+
+    // captured frames, frames/README.md says where they come from
+    mod frames {
+        pub const ARP_REQUEST: &'static [u8] = include_bytes!("../frames/arp_request.bin");
+        pub const ARP_REPLY: &'static [u8] = include_bytes!("../frames/arp_reply.bin");
+        pub const ICMP_ECHO_REQUEST: &'static [u8] = include_bytes!("../frames/icmp_echo_request.bin");
+        pub const ICMP_ECHO_REPLY: &'static [u8] = include_bytes!("../frames/icmp_echo_reply.bin");
+        pub const DNS_QUERY: &'static [u8] = include_bytes!("../frames/dns_query.bin");
+        pub const DNS_RESPONSE: &'static [u8] = include_bytes!("../frames/dns_response.bin");
+        pub const DHCP_DISCOVER: &'static [u8] = include_bytes!("../frames/dhcp_discover.bin");
+        pub const DHCP_OFFER: &'static [u8] = include_bytes!("../frames/dhcp_offer.bin");
+        pub const DHCP_REQUEST: &'static [u8] = include_bytes!("../frames/dhcp_request.bin");
+        pub const DHCP_ACK: &'static [u8] = include_bytes!("../frames/dhcp_ack.bin");
+        pub const TCP_SYN: &'static [u8] = include_bytes!("../frames/tcp_syn.bin");
+        pub const TCP_SYN_ACK: &'static [u8] = include_bytes!("../frames/tcp_syn_ack.bin");
+        pub const HTTP_REQUEST: &'static [u8] = include_bytes!("../frames/http_request.bin");
+        pub const HTTP_RESPONSE: &'static [u8] = include_bytes!("../frames/http_response.bin");
+        pub const MLD_REPORT: &'static [u8] = include_bytes!("../frames/mld_report.bin");
+        pub const NEIGHBOR_SOLICITATION: &'static [u8] = include_bytes!("../frames/neighbor_solicitation.bin");
+        pub const LLDP: &'static [u8] = include_bytes!("../frames/lldp.bin");
+        pub const VLAN_ARP_REQUEST: &'static [u8] = include_bytes!("../frames/vlan_arp_request.bin");
+    }
+
+    // the two ends of the capture
+    const HOST_MAC: [u8; 6] = [0xde, 0x75, 0x81, 0x0e, 0x5d, 0x4b];
+    const ROUTER_MAC: [u8; 6] = [0x3a, 0x29, 0x08, 0x38, 0xf9, 0xe7];
+    const HOST: [u8; 4] = [192, 168, 7, 100];
+    const ROUTER: [u8; 4] = [192, 168, 7, 1];
+    const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+    fn assert_addresses(result: &ParseResult, mac: ([u8; 6], [u8; 6]), ipv4: Option<([u8; 4], [u8; 4])>) {
+        assert_eq!((result.mac_src, result.mac_dst), (Some(mac.0), Some(mac.1)));
+        assert_eq!((result.ipv4_src, result.ipv4_dst), (ipv4.map(|ip| ip.0), ipv4.map(|ip| ip.1)));
+    }
+
+    #[test]
+    fn captured_arp() {
+        let result = classify(frames::ARP_REQUEST);
+        assert_eq!(result.pkt_type, ParseResultType::ARP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        // the sender and target addresses
+        assert_addresses(&result, (HOST_MAC, BROADCAST_MAC), Some((HOST, ROUTER)));
+
+        let result = classify(frames::ARP_REPLY);
+        assert_eq!(result.pkt_type, ParseResultType::ARP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((ROUTER, HOST)));
+    }
+
+    #[test]
+    fn captured_icmp() {
+        let result = classify(frames::ICMP_ECHO_REQUEST);
+        assert_eq!(result.pkt_type, ParseResultType::ICMP);
+        assert_eq!(result.len, 74);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (HOST_MAC, ROUTER_MAC), Some((HOST, ROUTER)));
+        assert_eq!((result.port_src, result.port_dst), (None, None));
+
+        let result = classify(frames::ICMP_ECHO_REPLY);
+        assert_eq!(result.pkt_type, ParseResultType::ICMP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((ROUTER, HOST)));
+    }
+
+    #[test]
+    fn captured_dns() {
+        let result = classify(frames::DNS_QUERY);
+        assert_eq!(result.pkt_type, ParseResultType::DNS);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (HOST_MAC, ROUTER_MAC), Some((HOST, ROUTER)));
+        assert_eq!((result.port_src, result.port_dst), (Some(32865), Some(PORT_DNS)));
+        assert_eq!(result.hostname, Some(String::from("www.skype.com")));
+        let dns = result.dns.unwrap();
+        assert_eq!((dns.id, dns.query_type), (0x24c5, Some(DnsType::A)));
+        assert!(dns.answers.is_empty());
+
+        let result = classify(frames::DNS_RESPONSE);
+        assert_eq!(result.pkt_type, ParseResultType::DNS);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((ROUTER, HOST)));
+        assert_eq!((result.port_src, result.port_dst), (Some(PORT_DNS), Some(32865)));
+        assert_eq!(result.hostname, Some(String::from("www.skype.com")));
+        // the name of the answer is a pointer back to the question
+        let dns = result.dns.unwrap();
+        assert_eq!((dns.id, dns.query_type), (0x4af0, Some(DnsType::A)));
+        assert_eq!(dns.answers, vec![DnsAnswer {
+            name: String::from("www.skype.com"),
+            record_type: DnsType::CNAME,
+            ttl: 3600,
+            data: DnsRecordData::Name(String::from("livecms.trafficmanager.net"))
+        }]);
+    }
+
+    #[test]
+    fn captured_dhcp() {
+        // the client has no address yet and broadcasts
+        let result = classify(frames::DHCP_DISCOVER);
+        assert_eq!(result.pkt_type, ParseResultType::DHCP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (HOST_MAC, BROADCAST_MAC), Some(([0; 4], [255; 4])));
+        assert_eq!((result.port_src, result.port_dst), (Some(PORT_DHCP_CLIENT), Some(PORT_DHCP_SERVER)));
+        // the client sends its host name along
+        assert_eq!(result.hostname, Some(String::from("vm")));
+        let result = classify(frames::DHCP_REQUEST);
+        assert_eq!(result.pkt_type, ParseResultType::DHCP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (HOST_MAC, BROADCAST_MAC), Some(([0; 4], [255; 4])));
+        assert_eq!(result.hostname, Some(String::from("vm")));
+
+        // the offered address is the destination, the acknowledged one the
+        // source
+        let result = classify(frames::DHCP_OFFER);
+        assert_eq!(result.pkt_type, ParseResultType::DHCP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((ROUTER, HOST)));
+        assert_eq!((result.port_src, result.port_dst), (Some(PORT_DHCP_SERVER), Some(PORT_DHCP_CLIENT)));
+        assert_eq!(result.hostname, None);
+        let result = classify(frames::DHCP_ACK);
+        assert_eq!(result.pkt_type, ParseResultType::DHCP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((HOST, HOST)));
+    }
+
+    #[test]
+    fn captured_dhcp_info() {
+        let offer = DhcpInfo {
+            message_type: DHCP_OFFER,
+            xid: 0x0dbafc96,
+            client_mac: HOST_MAC,
+            your_address: HOST,
+            server_mac: ROUTER_MAC,
+            server_id: Some(ROUTER),
+            netmask: Some([255, 255, 255, 0]),
+            router: Some(ROUTER),
+            // without T1 and T2, the client derives them from the lease time
+            lease_time: Some(3600),
+            renewal_time: None,
+            rebinding_time: None
+        };
+        assert_eq!(classify(frames::DHCP_OFFER).dhcp, Some(offer));
+        // the DNS server and time zone options are skipped
+        assert_eq!(classify(frames::DHCP_ACK).dhcp, Some(DhcpInfo { message_type: DHCP_ACK, ..offer }));
+
+        // the client's own messages, with no address yet
+        let discover = classify(frames::DHCP_DISCOVER).dhcp.unwrap();
+        assert_eq!((discover.message_type, discover.xid), (DHCP_DISCOVER, 0x0dbafc96));
+        assert_eq!((discover.client_mac, discover.server_mac, discover.your_address), (HOST_MAC, HOST_MAC, [0; 4]));
+        assert_eq!((discover.server_id, discover.lease_time), (None, None));
+        let request = classify(frames::DHCP_REQUEST).dhcp.unwrap();
+        assert_eq!((request.message_type, request.server_id), (DHCP_REQUEST, Some(ROUTER)));
+
+        assert_eq!(classify(frames::ARP_REQUEST).dhcp, None);
+    }
+
+    #[test]
+    fn captured_tcp() {
+        let result = classify(frames::TCP_SYN);
+        assert_eq!(result.pkt_type, ParseResultType::TCP);
+        assert_eq!(result.direction, None);
+        assert_addresses(&result, (HOST_MAC, ROUTER_MAC), Some((HOST, ROUTER)));
+        assert_eq!((result.port_src, result.port_dst), (Some(44894), Some(PORT_HTTP_ALT)));
+        assert_eq!(result.tcp, Some(TcpInfo { seq: 3368781132, ack: 0, flags: TcpFlags(0x02) }));
+
+        // no payload yet, so not HTTP even on its port
+        let result = classify(frames::TCP_SYN_ACK);
+        assert_eq!(result.pkt_type, ParseResultType::TCP);
+        assert_addresses(&result, (ROUTER_MAC, HOST_MAC), Some((ROUTER, HOST)));
+        let tcp = result.tcp.unwrap();
+        assert!(tcp.flags.syn() && tcp.flags.ack() && !tcp.flags.fin());
+        assert_eq!((tcp.seq, tcp.ack), (1417110088, 3368781133));
+    }
+
+    #[test]
+    fn captured_http() {
+        let result = classify(frames::HTTP_REQUEST);
+        assert_eq!(result.pkt_type, ParseResultType::HTTP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (HOST_MAC, ROUTER_MAC), Some((HOST, ROUTER)));
+        assert_eq!(result.path, Some(String::from("/")));
+        assert_eq!(result.tcp.unwrap().flags, TcpFlags(0x18));
+
+        let result = classify(frames::HTTP_RESPONSE);
+        assert_eq!(result.pkt_type, ParseResultType::HTTP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+        assert_eq!((result.port_src, result.port_dst), (Some(PORT_HTTP_ALT), Some(44894)));
+        assert_eq!(result.path, None);
+    }
+
+    #[test]
+    fn captured_ipv6() {
+        const LINK_LOCAL: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x38, 0x29, 0x08, 0xff, 0xfe, 0x38, 0xf9, 0xe7];
+
+        // an MLDv2 report behind a hop-by-hop header with the router alert
+        let result = classify(frames::MLD_REPORT);
+        assert_eq!(result.pkt_type, ParseResultType::ICMPv6);
+        assert_eq!(result.direction, None);
+        assert_addresses(&result, (ROUTER_MAC, [0x33, 0x33, 0, 0, 0, 0x16]), None);
+        assert_eq!(result.ipv6_src, Some(LINK_LOCAL));
+        assert_eq!(result.ipv6_dst, Some([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x16]));
+
+        // duplicate address detection, from the unspecified address to the
+        // solicited-node multicast address
+        let result = classify(frames::NEIGHBOR_SOLICITATION);
+        assert_eq!(result.pkt_type, ParseResultType::NDP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_addresses(&result, (ROUTER_MAC, [0x33, 0x33, 0xff, 0x38, 0xf9, 0xe7]), None);
+        assert_eq!(result.ipv6_src, Some([0; 16]));
+        assert_eq!(result.ipv6_dst, Some([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, 0x38, 0xf9, 0xe7]));
+
+        // a hop-by-hop header longer than the packet
+        let mut frame = Vec::from(frames::MLD_REPORT);
+        frame[14 + 40 + 1] = 10;
+        assert_eq!(classify(&frame).pkt_type, ParseResultType::Malformed(MalformedReason::Truncated));
+    }
+
+    #[test]
+    fn captured_vlan() {
+        // the ARP request with a tag for VLAN 42 after the addresses
+        let result = classify(frames::VLAN_ARP_REQUEST);
+        assert_eq!(result.pkt_type, ParseResultType::ARP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_eq!(result.vlan_id, Some(42));
+        assert_addresses(&result, (HOST_MAC, BROADCAST_MAC), Some((HOST, ROUTER)));
+
+        assert_eq!(classify(frames::ARP_REQUEST).vlan_id, None);
+        assert_eq!(classify(&frames::VLAN_ARP_REQUEST[..14 + 2]).pkt_type,
+                   ParseResultType::Malformed(MalformedReason::Truncated));
+    }
+
+    #[test]
+    fn captured_lldp() {
+        let result = classify(frames::LLDP);
+        assert_eq!(result.pkt_type, ParseResultType::LLDP);
+        assert_eq!(result.direction, None);
+        assert_addresses(&result, (ROUTER_MAC, [0x01, 0x80, 0xc2, 0, 0, 0x0e]), None);
+        // the port id is an interface name, there is no management address
+        assert_eq!(result.neighbor, Some(NeighborInfo {
+            name: Some(String::from("vm")),
+            port: Some(String::from("fxs")),
+            management: None,
+            ttl: 120
+        }));
+    }
+
+    #[test]
+    fn captured_truncated() {
+        // the addresses before the cut are still there
+        let result = classify(&frames::ICMP_ECHO_REQUEST[..14 + 10]);
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::Truncated));
+        assert_eq!(result.mac_src, Some(HOST_MAC));
+        assert_eq!(result.ipv4_src, None);
+
+        let result = classify(&frames::ARP_REQUEST[..10]);
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::Truncated));
+        assert_eq!(result.mac_src, None);
+    }
+
+    #[test]
+    fn captured_malformed() {
+        // an IPv4 header of another version
+        let mut frame = Vec::from(frames::ICMP_ECHO_REQUEST);
+        frame[14] = 0x65;
+        assert_eq!(classify(&frame).pkt_type, ParseResultType::Malformed(MalformedReason::BadIpVersion));
+
+        // a total length below the header length
+        let mut frame = Vec::from(frames::ICMP_ECHO_REQUEST);
+        frame[16..18].copy_from_slice(&[0, 19]);
+        assert_eq!(classify(&frame).pkt_type, ParseResultType::Malformed(MalformedReason::BadLength));
+
+        // the DHCP message type option running past the end
+        let mut frame = Vec::from(frames::DHCP_OFFER);
+        frame[14 + 20 + 8 + 241] = 200;
+        assert_eq!(classify(&frame).pkt_type, ParseResultType::Malformed(MalformedReason::BadDhcpOptions));
+    }
+
+    // built frames, for the broken and unusual ones no capture has
+    const CLIENT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x05];
+    const SERVER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+    const CLIENT: [u8; 4] = [10, 0, 0, 5];
+    const SERVER: [u8; 4] = [10, 0, 0, 1];
+
+    fn ethernet(src: [u8; 6], dst: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&[(ethertype >> 8) as u8, ethertype as u8]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    // an IPv4 frame from the client to the server
+    fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let total_len = 20 + payload.len();
+        let mut packet = vec![0x45, 0, (total_len >> 8) as u8, total_len as u8, 0, 0, 0, 0, 64, protocol, 0, 0];
+        packet.extend_from_slice(&CLIENT);
+        packet.extend_from_slice(&SERVER);
+        packet.extend_from_slice(payload);
+        ethernet(CLIENT_MAC, SERVER_MAC, ETHERTYPE_IPV4, &packet)
+    }
+
+    fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let len = 8 + payload.len();
+        let mut segment = vec![(src_port >> 8) as u8, src_port as u8, (dst_port >> 8) as u8, dst_port as u8,
+                               (len >> 8) as u8, len as u8, 0, 0];
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![(src_port >> 8) as u8, src_port as u8, (dst_port >> 8) as u8, dst_port as u8,
+                               0, 0, 0x10, 0, 0, 0, 0x20, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0];
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn arp_request() -> Vec<u8> {
+        let mut arp = vec![0, 1, 0x08, 0x00, 6, 4, 0, 1];
+        arp.extend_from_slice(&CLIENT_MAC);
+        arp.extend_from_slice(&CLIENT);
+        arp.extend_from_slice(&[0; 6]);
+        arp.extend_from_slice(&SERVER);
+        ethernet(CLIENT_MAC, [0xff; 6], ETHERTYPE_ARP, &arp)
+    }
+
+    fn icmp_echo_request() -> Vec<u8> {
+        ipv4(IP_PROTO_ICMP, &[ICMP_ECHO_REQUEST, 0, 0xf7, 0xfe, 0, 1, 0, 1])
+    }
+
+    // example.com, type A, class IN
+    const DNS_QUESTION: [u8; 17] = [7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+                                    0, 1, 0, 1];
+
+    fn dns_query() -> Vec<u8> {
+        let mut dns = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        dns.extend_from_slice(&DNS_QUESTION);
+        ipv4(IP_PROTO_UDP, &udp(40000, PORT_DNS, &dns))
+    }
+
+    fn dns_response() -> Vec<u8> {
+        let mut dns = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        dns.extend_from_slice(&DNS_QUESTION);
+        // a pointer to the name of the question, A, IN, TTL 300
+        dns.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0x01, 0x2c, 0, 4, 93, 184, 216, 34]);
+        ipv4(IP_PROTO_UDP, &udp(PORT_DNS, 40000, &dns))
+    }
+
+    fn dhcp_offer() -> Vec<u8> {
+        let mut dhcp = vec![0; 240];
+        dhcp[0] = 2;
+        dhcp[1] = 1;
+        dhcp[2] = 6;
+        dhcp[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        dhcp[16..20].copy_from_slice(&CLIENT);
+        dhcp[28..34].copy_from_slice(&CLIENT_MAC);
+        dhcp[236..240].copy_from_slice(&[0x63, 0x82, 0x53, 0x63]);
+        dhcp.extend_from_slice(&[DHCP_OPTION_MESSAGE_TYPE, 1, DHCP_OFFER,
+                                 DHCP_OPTION_SERVER_ID, 4, 10, 0, 0, 1,
+                                 DHCP_OPTION_NETMASK, 4, 255, 255, 255, 0,
+                                 DHCP_OPTION_ROUTER, 4, 10, 0, 0, 1,
+                                 DHCP_OPTION_LEASE_TIME, 4, 0, 0, 0x0e, 0x10,
+                                 DHCP_OPTION_END]);
+        let mut frame = ipv4(IP_PROTO_UDP, &udp(PORT_DHCP_SERVER, PORT_DHCP_CLIENT, &dhcp));
+        // sent by the server
+        frame[0..6].copy_from_slice(&CLIENT_MAC);
+        frame[6..12].copy_from_slice(&SERVER_MAC);
+        frame
+    }
+
+    fn tcp_syn() -> Vec<u8> {
+        ipv4(IP_PROTO_TCP, &tcp(40000, 443, 0x02, &[]))
+    }
+
+    #[test]
+    fn arp() {
+        let result = classify(&arp_request());
+        assert_eq!(result.pkt_type, ParseResultType::ARP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_eq!(result.mac_src, Some(CLIENT_MAC));
+        assert_eq!(result.mac_dst, Some([0xff; 6]));
+        assert_eq!(result.ipv4_src, Some(CLIENT));
+        assert_eq!(result.ipv4_dst, Some(SERVER));
+        assert_eq!(result.arp_sender_mac, Some(CLIENT_MAC));
+
+        // forwarded by someone else, the sender stays the same
+        let mut frame = arp_request();
+        frame[6..12].copy_from_slice(&SERVER_MAC);
+        let result = classify(&frame);
+        assert_eq!(result.mac_src, Some(SERVER_MAC));
+        assert_eq!(result.arp_sender_mac, Some(CLIENT_MAC));
+    }
+
+    const CLIENT_V6: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5];
+    const SERVER_V6: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+    // an IPv6 frame from the client to the server
+    fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let len = payload.len();
+        let mut packet = vec![0x60, 0, 0, 0, (len >> 8) as u8, len as u8, next_header, 64];
+        packet.extend_from_slice(&CLIENT_V6);
+        packet.extend_from_slice(&SERVER_V6);
+        packet.extend_from_slice(payload);
+        ethernet(CLIENT_MAC, SERVER_MAC, ETHERTYPE_IPV6, &packet)
+    }
+
+    const ICMPV6_ECHO: [u8; 8] = [ICMPV6_ECHO_REQUEST, 0, 0, 0, 0, 1, 0, 1];
+
+    // an echo request behind `count` destination options headers
+    fn ipv6_extension_headers(count: usize) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for i in 0..count {
+            let next_header = if i + 1 == count { IP_PROTO_ICMPV6 } else { IPV6_DESTINATION_OPTIONS };
+            // 8 bytes, padded with a PadN option
+            payload.extend_from_slice(&[next_header, 0, 1, 4, 0, 0, 0, 0]);
+        }
+        payload.extend_from_slice(&ICMPV6_ECHO);
+        ipv6(if count == 0 { IP_PROTO_ICMPV6 } else { IPV6_DESTINATION_OPTIONS }, &payload)
+    }
+
+    #[test]
+    fn ipv6_extension_header_chain() {
+        for count in 0..IPV6_MAX_EXTENSION_HEADERS + 1 {
+            let result = classify(&ipv6_extension_headers(count));
+            assert_eq!(result.pkt_type, ParseResultType::ICMPv6, "{} headers", count);
+            assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        }
+
+        let result = classify(&ipv6_extension_headers(9));
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::BadIpv6Headers));
+        assert_eq!(result.ipv6_src, Some(CLIENT_V6));
+        assert_eq!(result.ipv6_dst, Some(SERVER_V6));
+    }
+
+    #[test]
+    fn qinq() {
+        // a service tag with id 100 and a customer tag with id 200
+        let frame = icmp_echo_request();
+        let mut tagged = Vec::from(&frame[..12]);
+        tagged.extend_from_slice(&[0x88, 0xa8, 0x00, 100, 0x81, 0x00, 0x20, 200]);
+        tagged.extend_from_slice(&frame[12..]);
+        let result = classify(&tagged);
+        assert_eq!(result.pkt_type, ParseResultType::ICMP);
+        assert_eq!(result.vlan_id, Some(100));
+        assert_eq!(result.ipv4_src, Some(CLIENT));
+
+        // a third tag is not looked into
+        let mut tagged = Vec::from(&frame[..12]);
+        tagged.extend_from_slice(&[0x88, 0xa8, 0x00, 100, 0x81, 0x00, 0x00, 200, 0x81, 0x00, 0x01, 0x2c]);
+        tagged.extend_from_slice(&frame[12..]);
+        let result = classify(&tagged);
+        assert_eq!(result.pkt_type, ParseResultType::Unknown);
+        assert_eq!(result.vlan_id, Some(100));
+    }
+
+    fn lldp_tlv(tlv_type: u8, value: &[u8]) -> Vec<u8> {
+        let type_len = (tlv_type as u16) << 9 | value.len() as u16;
+        let mut tlv = vec![(type_len >> 8) as u8, type_len as u8];
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    fn lldp(tlvs: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for tlv in tlvs {
+            payload.extend_from_slice(tlv);
+        }
+        ethernet(SERVER_MAC, [0x01, 0x80, 0xc2, 0, 0, 0x0e], ETHERTYPE_LLDP, &payload)
+    }
+
+    fn lldp_announcement() -> Vec<u8> {
+        lldp(&[lldp_tlv(1, &[4, 0x02, 0, 0, 0, 0, 0x01]),
+               lldp_tlv(LLDP_PORT_ID, &[5, b'g', b'e', b'-', b'0', b'/', b'1']),
+               lldp_tlv(LLDP_TTL, &[0, 120]),
+               lldp_tlv(LLDP_SYSTEM_NAME, b"switch1"),
+               lldp_tlv(LLDP_MANAGEMENT_ADDRESS, &[5, ADDRESS_FAMILY_IPV4, 10, 0, 0, 1, 2, 0, 0, 0, 1, 0]),
+               lldp_tlv(LLDP_END, &[])])
+    }
+
+    #[test]
+    fn lldp_tlvs() {
+        let result = classify(&lldp_announcement());
+        assert_eq!(result.pkt_type, ParseResultType::LLDP);
+        assert_eq!(result.neighbor, Some(NeighborInfo {
+            name: Some(String::from("switch1")),
+            port: Some(String::from("ge-0/1")),
+            management: Some(SERVER),
+            ttl: 120
+        }));
+
+        // an empty name is still a name, an empty TTL is missing its value
+        let result = classify(&lldp(&[lldp_tlv(LLDP_SYSTEM_NAME, &[]), lldp_tlv(LLDP_END, &[])]));
+        assert_eq!(result.neighbor.unwrap().name, Some(String::new()));
+        let result = classify(&lldp(&[lldp_tlv(LLDP_TTL, &[]), lldp_tlv(LLDP_END, &[])]));
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::Truncated));
+        let result = classify(&lldp(&[lldp_tlv(LLDP_PORT_ID, &[]), lldp_tlv(LLDP_END, &[])]));
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::Truncated));
+
+        // a length past the end of the frame, and no end TLV
+        let mut frame = lldp(&[lldp_tlv(LLDP_SYSTEM_NAME, b"switch1"), lldp_tlv(LLDP_END, &[])]);
+        frame[15] = 100;
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+        let frame = lldp(&[lldp_tlv(LLDP_SYSTEM_NAME, b"switch1")]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+    }
+
+    fn cdp_tlv(tlv_type: u16, value: &[u8]) -> Vec<u8> {
+        let len = 4 + value.len();
+        let mut tlv = vec![(tlv_type >> 8) as u8, tlv_type as u8, (len >> 8) as u8, len as u8];
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    // 802.3 with the length instead of an ethertype
+    fn cdp(tlvs: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = Vec::from(&CDP_SNAP_HEADER[..]);
+        // version 2, TTL 180 seconds, no checksum
+        payload.extend_from_slice(&[2, 180, 0, 0]);
+        for tlv in tlvs {
+            payload.extend_from_slice(tlv);
+        }
+        ethernet(SERVER_MAC, [0x01, 0x00, 0x0c, 0xcc, 0xcc, 0xcc], payload.len() as u16, &payload)
+    }
+
+    fn cdp_announcement() -> Vec<u8> {
+        cdp(&[cdp_tlv(CDP_DEVICE_ID, b"switch1"),
+              cdp_tlv(CDP_PORT_ID, b"Gi0/1"),
+              cdp_tlv(CDP_MANAGEMENT_ADDRESSES, &[0, 0, 0, 1, CDP_PROTOCOL_NLPID, 1, CDP_NLPID_IP, 0, 4, 10, 0, 0, 1])])
+    }
+
+    #[test]
+    fn cdp_tlvs() {
+        let result = classify(&cdp_announcement());
+        assert_eq!(result.pkt_type, ParseResultType::CDP);
+        assert_eq!(result.neighbor, Some(NeighborInfo {
+            name: Some(String::from("switch1")),
+            port: Some(String::from("Gi0/1")),
+            management: Some(SERVER),
+            ttl: 180
+        }));
+
+        // the length includes the type and length fields, so less than 4 is
+        // wrong and 4 an empty value
+        let mut frame = cdp(&[cdp_tlv(CDP_DEVICE_ID, &[])]);
+        assert_eq!(classify(&frame).neighbor.unwrap().name, Some(String::new()));
+        let len = frame.len();
+        frame[len - 1] = 0;
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadLength));
+
+        // a length past the end of the frame
+        let mut frame = cdp(&[cdp_tlv(CDP_DEVICE_ID, b"switch1")]);
+        frame[14 + 8 + 4 + 3] = 100;
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+    }
+
+    #[test]
+    fn dns_pointer_loop() {
+        // a name pointing at itself
+        let mut dns = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        dns.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        let result = classify(&ipv4(IP_PROTO_UDP, &udp(40000, PORT_DNS, &dns)));
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::BadDnsName));
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+
+        // "foo" and then two pointers pointing at each other
+        let mut dns = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        dns.extend_from_slice(&[3, b'f', b'o', b'o', 0xc0, 18, 0xc0, 16, 0, 1, 0, 1]);
+        let result = classify(&ipv4(IP_PROTO_UDP, &udp(40000, PORT_DNS, &dns)));
+        assert_eq!(result.pkt_type, ParseResultType::Malformed(MalformedReason::BadDnsName));
+        assert_eq!(result.hostname, None);
+    }
+
+    fn http_get(port: u16, path: &str) -> Vec<u8> {
+        let request = format!("GET {} HTTP/1.1\r\nHost: example.com\r\n\r\n", path);
+        ipv4(IP_PROTO_TCP, &tcp(40000, port, 0x18, request.as_bytes()))
+    }
+
+    #[test]
+    fn http_ports() {
+        let result = classify(&http_get(PORT_HTTP, "/index.html"));
+        assert_eq!(result.pkt_type, ParseResultType::HTTP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Request));
+        assert_eq!(result.path, Some(String::from("/index.html")));
+        assert_eq!(classify(&http_get(PORT_HTTP_ALT, "/")).pkt_type, ParseResultType::HTTP);
+
+        // a response from the alternative port
+        let response = ipv4(IP_PROTO_TCP, &tcp(PORT_HTTP_ALT, 40000, 0x18, b"HTTP/1.1 200 OK\r\n\r\n"));
+        let result = classify(&response);
+        assert_eq!(result.pkt_type, ParseResultType::HTTP);
+        assert_eq!(result.direction, Some(ParseResultDirection::Response));
+
+        // the same request on any other port is just TCP
+        let result = classify(&http_get(8000, "/index.html"));
+        assert_eq!(result.pkt_type, ParseResultType::TCP);
+        assert_eq!(result.path, None);
+    }
+
+    #[test]
+    fn long_http_path() {
+        let path: String = (0..100).map(|i| (b'a' + i % 26) as char).collect();
+        let result = classify(&http_get(PORT_HTTP, &path));
+        assert_eq!(result.path, Some(String::from(&path[..HTTP_MAX_PATH_LEN])));
+
+        // a two byte character across the limit is left out as a whole
+        let mut path: String = (0..HTTP_MAX_PATH_LEN - 1).map(|_| 'a').collect();
+        path.push_str("\u{fc}b");
+        let result = classify(&http_get(PORT_HTTP, &path));
+        assert_eq!(result.pkt_type, ParseResultType::HTTP);
+        assert_eq!(result.path, Some(String::from(&path[..HTTP_MAX_PATH_LEN - 1])));
+
+        let result = classify(&http_get(PORT_HTTP, "/gr\u{fc}n"));
+        assert_eq!(result.path, Some(String::from("/gr\u{fc}n")));
+    }
+
+    fn fixtures() -> Vec<Vec<u8>> {
+        vec![arp_request(), icmp_echo_request(), dns_query(), dns_response(), dhcp_offer(), tcp_syn(),
+             http_get(PORT_HTTP, "/index.html"), ipv6_extension_headers(2), lldp_announcement(),
+             cdp_announcement()]
+    }
+
+    fn malformed(frame: &[u8]) -> Option<MalformedReason> {
+        match classify(frame).pkt_type {
+            ParseResultType::Malformed(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    // xorshift, the same frames on every run
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    #[test]
+    fn every_prefix_is_truncated() {
+        for frame in fixtures() {
+            assert_eq!(malformed(&frame), None);
+            for len in 0..frame.len() {
+                assert_eq!(malformed(&frame[..len]), Some(MalformedReason::Truncated), "{:?}", &frame[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_length_fields() {
+        // IPv4 header length below 20 bytes, total length below the header
+        // length and past the end of the frame
+        let mut frame = icmp_echo_request();
+        frame[14] = 0x44;
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadLength));
+        let mut frame = icmp_echo_request();
+        frame[16..18].copy_from_slice(&[0, 19]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadLength));
+        frame[16..18].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+
+        // UDP length below its header and past the IP packet
+        let mut frame = dns_query();
+        frame[38..40].copy_from_slice(&[0, 7]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadLength));
+        frame[38..40].copy_from_slice(&[0x10, 0]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+
+        // TCP data offset below 5 words and past the segment
+        let mut frame = tcp_syn();
+        frame[46] = 0x40;
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadLength));
+        frame[46] = 0xf0;
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+
+        // a DNS answer longer than the message
+        let mut frame = dns_response();
+        frame[81..83].copy_from_slice(&[0, 5]);
+        assert_eq!(malformed(&frame), Some(MalformedReason::Truncated));
+
+        // a DHCP option running past the end, the lease time here
+        let mut frame = dhcp_offer();
+        let len = frame.len();
+        frame[len - 6] = 200;
+        assert_eq!(malformed(&frame), Some(MalformedReason::BadDhcpOptions));
+    }
+
+    // the point of these two is that `classify` returns at all
+    #[test]
+    fn random_frames() {
+        let mut random = Random(0x2545f491);
+        let ethertypes = [ETHERTYPE_IPV4, ETHERTYPE_ARP, ETHERTYPE_IPV6, ETHERTYPE_VLAN, ETHERTYPE_LLDP, 60];
+        for _ in 0..20000 {
+            let len = (random.next() % 200) as usize;
+            let mut frame: Vec<u8> = (0..len).map(|_| random.next() as u8).collect();
+            // mostly protocols we know, to get past the ethernet header
+            if len >= 15 {
+                let ethertype = ethertypes[(random.next() % ethertypes.len() as u32) as usize];
+                frame[12..14].copy_from_slice(&[(ethertype >> 8) as u8, ethertype as u8]);
+                frame[14] = match ethertype {
+                    ETHERTYPE_IPV4 => 0x45,
+                    ETHERTYPE_IPV6 => 0x60,
+                    _ => frame[14],
+                };
+            }
+            let result = classify(&frame);
+            assert_eq!(result.len, len);
+            assert!(result.pkt_type.index() < TYPE_COUNT);
+        }
+    }
+
+    #[test]
+    fn mutated_fixtures() {
+        let mut random = Random(0x9e3779b9);
+        for frame in fixtures() {
+            for _ in 0..2000 {
+                let mut frame = frame.clone();
+                for _ in 0..1 + random.next() % 4 {
+                    let i = (random.next() as usize) % frame.len();
+                    frame[i] = random.next() as u8;
+                }
+                assert!(classify(&frame).pkt_type.index() < TYPE_COUNT);
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+// Parses the lines typed into the console.
+
+use collections::String;
+
+use dhcp_client::{self, StaticConfig};
+use filter;
+use log_level::Level;
+
+/// A console command, see `HELP` for what they do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Stats,
+    Hosts,
+    Arp,
+    Screen(String),
+    Capture(bool),
+    /// `None` removes the filter
+    Filter(Option<String>),
+    Sound(bool),
+    /// Prints our address and the DHCP state
+    Ip,
+    /// Sets the address used while no DHCP server answers, `None` goes
+    /// back to a link-local one
+    StaticIp(Option<StaticConfig>),
+    /// Sets the log level of a module, or of all modules for `None`
+    Log(Option<String>, Level)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The line was blank
+    Empty,
+    UnknownCommand(String),
+    /// The command needs an argument, this describes it
+    MissingArgument(&'static str),
+    BadArgument(String),
+    /// The command takes no (or fewer) arguments
+    TooManyArguments,
+    /// The line isn't valid UTF-8
+    NotUtf8
+}
+
+pub const HELP: &'static str = "\
+commands:
+  help              this text
+  stats             frame and byte counts per type
+  hosts             the host table
+  arp               the driver's ARP cache
+  screen <name>     switch to a screen
+  capture on|off    write frames to the pcapng file on the host
+  filter <expr>     only count and draw matching frames, e.g.
+                    proto == dns && ip.src == 10.0.0.5, port 53, not arp,
+                    ip fe80::/10, vlan 42, tcp.state == syn_sent,
+                    tcp.rtt > 100
+  filter off        show everything again
+  sound on|off      play a tone per frame
+  ip                our address and the DHCP lease
+  ip static <address/prefix> [gateway]
+                    the address to use if no DHCP server answers, e.g.
+                    ip static 10.0.0.50/24 10.0.0.1
+  ip static off     use a link-local 169.254.x.x address instead
+  log [module] <level>
+                    set the log level (off, error, warn, info, debug, trace)
+                    of a module like screen::grid, or of all modules";
+
+/// Parses one line, surrounding whitespace is ignored.
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let line = line.trim();
+    let (name, rest) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+
+    match name {
+        "" => Err(ParseError::Empty),
+        "help" | "?" => no_argument(rest, Command::Help),
+        "stats" => no_argument(rest, Command::Stats),
+        "hosts" => no_argument(rest, Command::Hosts),
+        "arp" => no_argument(rest, Command::Arp),
+        "screen" => match single_argument(rest, "a screen name") {
+            Ok(screen) => Ok(Command::Screen(String::from(screen))),
+            Err(e) => Err(e),
+        },
+        "capture" => on_off(rest).map(Command::Capture),
+        // the expression is everything after the command, spaces included
+        "filter" => match rest {
+            "" => Err(ParseError::MissingArgument("a filter expression or off")),
+            "off" => Ok(Command::Filter(None)),
+            expression => Ok(Command::Filter(Some(String::from(expression)))),
+        },
+        "sound" => on_off(rest).map(Command::Sound),
+        "ip" => {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next(), words.next(), words.next()) {
+                (None, _, _, _) => Ok(Command::Ip),
+                (Some("static"), None, _, _) => Err(ParseError::MissingArgument("an address like 10.0.0.50/24, or off")),
+                (Some("static"), Some("off"), None, _) => Ok(Command::StaticIp(None)),
+                (Some("static"), Some(address), gateway, None) => {
+                    static_argument(address, gateway).map(|c| Command::StaticIp(Some(c)))
+                }
+                (Some("static"), _, _, _) => Err(ParseError::TooManyArguments),
+                (Some(other), _, _, _) => Err(ParseError::BadArgument(String::from(other))),
+            }
+        }
+        "log" => {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (None, _, _) => Err(ParseError::MissingArgument("a log level")),
+                (Some(level), None, _) => level_argument(level).map(|l| Command::Log(None, l)),
+                (Some(module), Some(level), None) => {
+                    level_argument(level).map(|l| Command::Log(Some(String::from(module)), l))
+                }
+                _ => Err(ParseError::TooManyArguments),
+            }
+        }
+        other => Err(ParseError::UnknownCommand(String::from(other))),
+    }
+}
+
+fn no_argument(rest: &str, command: Command) -> Result<Command, ParseError> {
+    if rest.is_empty() { Ok(command) } else { Err(ParseError::TooManyArguments) }
+}
+
+fn single_argument<'a>(rest: &'a str, description: &'static str) -> Result<&'a str, ParseError> {
+    if rest.is_empty() {
+        Err(ParseError::MissingArgument(description))
+    } else if rest.contains(char::is_whitespace) {
+        Err(ParseError::TooManyArguments)
+    } else {
+        Ok(rest)
+    }
+}
+
+fn on_off(rest: &str) -> Result<bool, ParseError> {
+    match single_argument(rest, "on or off") {
+        Ok("on") => Ok(true),
+        Ok("off") => Ok(false),
+        Ok(other) => Err(ParseError::BadArgument(String::from(other))),
+        Err(e) => Err(e),
+    }
+}
+
+// the prefix length is needed for the netmask, a missing one is more likely
+// forgotten than meant to be /32
+fn static_argument(address: &str, gateway: Option<&str>) -> Result<StaticConfig, ParseError> {
+    let (address, prefix) = match filter::parse_ip(address) {
+        Some(ip) if address.contains('/') => ip,
+        _ => return Err(ParseError::BadArgument(String::from(address))),
+    };
+    let gateway = match gateway {
+        Some(gateway) => match filter::parse_ip(gateway) {
+            Some((ip, 32)) if !gateway.contains('/') => Some(ip),
+            _ => return Err(ParseError::BadArgument(String::from(gateway))),
+        },
+        None => None,
+    };
+    Ok(StaticConfig {
+        address: address,
+        netmask: dhcp_client::prefix_netmask(prefix),
+        gateway: gateway
+    })
+}
+
+fn level_argument(word: &str) -> Result<Level, ParseError> {
+    Level::from_name(word).ok_or_else(|| ParseError::BadArgument(String::from(word)))
+}
+
+#[cfg(test)]
+mod tests {
+    use collections::String;
+    use dhcp_client::StaticConfig;
+    use log_level::Level;
+    use super::{parse, Command, ParseError};
+
+    fn bad(argument: &str) -> Result<Command, ParseError> {
+        Err(ParseError::BadArgument(String::from(argument)))
+    }
+
+    #[test]
+    fn commands_without_arguments() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("?"), Ok(Command::Help));
+        assert_eq!(parse("stats"), Ok(Command::Stats));
+        assert_eq!(parse("  hosts \r\n"), Ok(Command::Hosts));
+        assert_eq!(parse("arp"), Ok(Command::Arp));
+        assert_eq!(parse("ip"), Ok(Command::Ip));
+        assert_eq!(parse("stats now"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("arp -a"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn empty_and_unknown() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse(" \t\n"), Err(ParseError::Empty));
+        assert_eq!(parse("reboot now"), Err(ParseError::UnknownCommand(String::from("reboot"))));
+        // commands are case sensitive
+        assert_eq!(parse("Help"), Err(ParseError::UnknownCommand(String::from("Help"))));
+    }
+
+    #[test]
+    fn screen() {
+        assert_eq!(parse("screen hosts"), Ok(Command::Screen(String::from("hosts"))));
+        assert_eq!(parse("screen"), Err(ParseError::MissingArgument("a screen name")));
+        assert_eq!(parse("screen a b"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn on_off() {
+        assert_eq!(parse("capture on"), Ok(Command::Capture(true)));
+        assert_eq!(parse("capture off"), Ok(Command::Capture(false)));
+        assert_eq!(parse("sound on"), Ok(Command::Sound(true)));
+        assert_eq!(parse("sound off"), Ok(Command::Sound(false)));
+        assert_eq!(parse("sound"), Err(ParseError::MissingArgument("on or off")));
+        assert_eq!(parse("capture yes"), bad("yes"));
+        assert_eq!(parse("sound on off"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(parse("filter proto == dns && port 53"),
+                   Ok(Command::Filter(Some(String::from("proto == dns && port 53")))));
+        assert_eq!(parse("filter off"), Ok(Command::Filter(None)));
+        assert_eq!(parse("filter"), Err(ParseError::MissingArgument("a filter expression or off")));
+    }
+
+    #[test]
+    fn static_ip() {
+        assert_eq!(parse("ip static 10.0.0.50/24 10.0.0.1"), Ok(Command::StaticIp(Some(StaticConfig {
+            address: [10, 0, 0, 50],
+            netmask: [255, 255, 255, 0],
+            gateway: Some([10, 0, 0, 1])
+        }))));
+        assert_eq!(parse("ip static 192.168.1.2/16"), Ok(Command::StaticIp(Some(StaticConfig {
+            address: [192, 168, 1, 2],
+            netmask: [255, 255, 0, 0],
+            gateway: None
+        }))));
+        assert_eq!(parse("ip static off"), Ok(Command::StaticIp(None)));
+
+        assert_eq!(parse("ip static"), Err(ParseError::MissingArgument("an address like 10.0.0.50/24, or off")));
+        // the prefix length is required, the gateway is a single address
+        assert_eq!(parse("ip static 10.0.0.50"), bad("10.0.0.50"));
+        assert_eq!(parse("ip static 10.0.0.256/24"), bad("10.0.0.256/24"));
+        assert_eq!(parse("ip static 10.0.0.50/24 10.0.0.1/24"), bad("10.0.0.1/24"));
+        assert_eq!(parse("ip static 10.0.0.50/24 10.0.0.1 x"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("ip lease"), bad("lease"));
+    }
+
+    #[test]
+    fn log() {
+        assert_eq!(parse("log debug"), Ok(Command::Log(None, Level::Debug)));
+        assert_eq!(parse("log off"), Ok(Command::Log(None, Level::Off)));
+        assert_eq!(parse("log screen::grid trace"), Ok(Command::Log(Some(String::from("screen::grid")), Level::Trace)));
+        assert_eq!(parse("log"), Err(ParseError::MissingArgument("a log level")));
+        assert_eq!(parse("log verbose"), bad("verbose"));
+        assert_eq!(parse("log screen::grid loud"), bad("loud"));
+        assert_eq!(parse("log a b c"), Err(ParseError::TooManyArguments));
+    }
+}
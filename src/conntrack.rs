@@ -0,0 +1,462 @@
+// Follows TCP connections through the handshake and the teardown, keyed on
+// the addresses and ports of both ends. Connections that were already
+// running when we first saw them are picked up in the middle.
+
+use collections::Vec;
+
+// the table lives on the small heap, like the host table
+const MAX_CONNECTIONS: usize = 32;
+
+// in ticks, which are milliseconds
+const HANDSHAKE_TIMEOUT: usize = 30 * 1000;
+const IDLE_TIMEOUT: usize = 10 * 60 * 1000;
+// closed connections stay in the table for a moment, so they can be seen
+const CLOSED_TIMEOUT: usize = 10 * 1000;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    V4([u8; 4]),
+    V6([u8; 16])
+}
+
+/// One end of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoint {
+    pub address: Address,
+    pub port: u16
+}
+
+/// What the tracker needs to know of a TCP frame, see `context::tcp_segment`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub src: Endpoint,
+    pub dst: Endpoint,
+    /// The flags byte of the TCP header
+    pub flags: u8,
+    /// Length of the whole frame
+    pub len: usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// The client sent a SYN
+    SynSent,
+    /// The server answered with a SYN-ACK
+    SynReceived,
+    Established,
+    /// One side sent a FIN
+    Closing,
+    /// Both sides sent a FIN
+    Closed,
+    Reset
+}
+
+/// The names of the states by `State::index`, as used by the display filter.
+pub static STATE_NAMES: [&'static str; 6] = ["syn_sent", "syn_received", "established", "closing", "closed", "reset"];
+
+impl State {
+    pub fn index(&self) -> usize {
+        match *self {
+            State::SynSent => 0,
+            State::SynReceived => 1,
+            State::Established => 2,
+            State::Closing => 3,
+            State::Closed => 4,
+            State::Reset => 5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        STATE_NAMES[self.index()]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        *self == State::Closed || *self == State::Reset
+    }
+}
+
+/// What one end sent, the bytes are whole frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub packets: u32,
+    pub bytes: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    /// The end that sent the SYN. For connections picked up in the middle
+    /// it's the one with the higher port, usually the ephemeral one.
+    pub client: Endpoint,
+    pub server: Endpoint,
+    pub state: State,
+    pub from_client: Counter,
+    pub from_server: Counter,
+    /// Ticks from the (last) SYN to the ACK of the SYN-ACK, `None` until the
+    /// handshake is complete or if we missed the SYN
+    pub handshake_rtt: Option<usize>,
+    pub first_seen: usize,
+    pub last_seen: usize,
+    syn_time: Option<usize>,
+    client_fin: bool,
+    server_fin: bool
+}
+
+impl Connection {
+    fn new(client: Endpoint, server: Endpoint, state: State, ticks: usize) -> Self {
+        Connection {
+            client: client,
+            server: server,
+            state: state,
+            from_client: Counter::default(),
+            from_server: Counter::default(),
+            handshake_rtt: None,
+            first_seen: ticks,
+            last_seen: ticks,
+            syn_time: None,
+            client_fin: false,
+            server_fin: false
+        }
+    }
+
+    fn connects(&self, a: &Endpoint, b: &Endpoint) -> bool {
+        (self.client == *a && self.server == *b) || (self.client == *b && self.server == *a)
+    }
+
+    fn timeout(&self) -> usize {
+        match self.state {
+            State::SynSent | State::SynReceived => HANDSHAKE_TIMEOUT,
+            State::Established | State::Closing => IDLE_TIMEOUT,
+            State::Closed | State::Reset => CLOSED_TIMEOUT,
+        }
+    }
+
+    fn update(&mut self, from_client: bool, flags: u8, len: usize, ticks: usize) {
+        {
+            let counter = if from_client { &mut self.from_client } else { &mut self.from_server };
+            counter.packets = counter.packets.wrapping_add(1);
+            counter.bytes += len as u64;
+        }
+        self.last_seen = ticks;
+
+        if flags & RST != 0 {
+            self.state = State::Reset;
+            return;
+        }
+        if self.state == State::Reset {
+            return;
+        }
+
+        let syn = flags & SYN != 0;
+        let ack = flags & ACK != 0;
+        match (self.state, syn, ack, from_client) {
+            // a retransmitted SYN restarts the clock
+            (State::SynSent, true, false, true) => self.syn_time = Some(ticks),
+            (State::SynSent, true, true, false) => self.state = State::SynReceived,
+            (State::SynReceived, false, true, true) => {
+                self.state = State::Established;
+                self.handshake_rtt = self.syn_time.map(|syn_time| ticks.wrapping_sub(syn_time));
+            }
+            _ => {}
+        }
+
+        if flags & FIN != 0 {
+            if from_client {
+                self.client_fin = true;
+            } else {
+                self.server_fin = true;
+            }
+            self.state = if self.client_fin && self.server_fin { State::Closed } else { State::Closing };
+        }
+    }
+}
+
+/// The TCP connections seen recently, at most `MAX_CONNECTIONS` of them.
+pub struct ConnectionTable {
+    // in the order they were first seen
+    connections: Vec<Connection>,
+    evicted: u32
+}
+
+impl ConnectionTable {
+    pub fn new() -> Self {
+        ConnectionTable { connections: Vec::with_capacity(MAX_CONNECTIONS), evicted: 0 }
+    }
+
+    /// Oldest first.
+    pub fn connections(&self) -> &[Connection] {
+        &self.connections
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Connections dropped to make room for new ones.
+    pub fn evicted(&self) -> u32 {
+        self.evicted
+    }
+
+    /// The connection between two ends, in either direction.
+    pub fn find(&self, a: &Endpoint, b: &Endpoint) -> Option<&Connection> {
+        self.connections.iter().find(|c| c.connects(a, b))
+    }
+
+    pub fn on_segment(&mut self, segment: &Segment, ticks: usize) {
+        let syn = segment.flags & SYN != 0;
+        let ack = segment.flags & ACK != 0;
+
+        if let Some(index) = self.connections.iter().position(|c| c.connects(&segment.src, &segment.dst)) {
+            // the ports are reused for a new connection
+            if syn && !ack && self.connections[index].state.is_finished() {
+                self.connections.remove(index);
+            } else {
+                let from_client = self.connections[index].client == segment.src;
+                self.connections[index].update(from_client, segment.flags, segment.len, ticks);
+                return;
+            }
+        }
+
+        let mut connection = if syn && !ack {
+            let mut connection = Connection::new(segment.src, segment.dst, State::SynSent, ticks);
+            connection.syn_time = Some(ticks);
+            connection
+        } else if syn {
+            // we missed the SYN, the SYN-ACK comes from the server
+            Connection::new(segment.dst, segment.src, State::SynReceived, ticks)
+        } else if segment.flags & RST != 0 {
+            // the end of something we never saw, no need to keep it
+            return;
+        } else if segment.src.port >= segment.dst.port {
+            Connection::new(segment.src, segment.dst, State::Established, ticks)
+        } else {
+            Connection::new(segment.dst, segment.src, State::Established, ticks)
+        };
+        let from_client = connection.client == segment.src;
+        connection.update(from_client, segment.flags, segment.len, ticks);
+
+        if self.connections.len() >= MAX_CONNECTIONS {
+            self.evict();
+        }
+        self.connections.push(connection);
+    }
+
+    /// Removes the connections that were quiet for too long. Half open ones
+    /// time out quickly, closed ones even more so.
+    pub fn expire(&mut self, ticks: usize) {
+        self.connections.retain(|c| ticks.wrapping_sub(c.last_seen) < c.timeout());
+    }
+
+    // drops the least recently active connection, finished ones go first
+    fn evict(&mut self) {
+        let finished = self.connections.iter().enumerate()
+            .filter(|&(_, c)| c.state.is_finished())
+            .min_by_key(|&(_, c)| c.last_seen)
+            .map(|(i, _)| i);
+        let victim = match finished {
+            Some(index) => Some(index),
+            None => self.connections.iter().enumerate().min_by_key(|&(_, c)| c.last_seen).map(|(i, _)| i),
+        };
+        if let Some(index) = victim {
+            self.connections.remove(index);
+            self.evicted = self.evicted.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Address, ConnectionTable, Endpoint, Segment, State, MAX_CONNECTIONS};
+    use super::{ACK, FIN, RST, SYN};
+
+    const PSH: u8 = 0x08;
+
+    fn client() -> Endpoint {
+        Endpoint { address: Address::V4([10, 0, 0, 5]), port: 40000 }
+    }
+
+    fn server() -> Endpoint {
+        Endpoint { address: Address::V4([10, 0, 0, 1]), port: 80 }
+    }
+
+    fn to_server(flags: u8, len: usize) -> Segment {
+        Segment { src: client(), dst: server(), flags: flags, len: len }
+    }
+
+    fn to_client(flags: u8, len: usize) -> Segment {
+        Segment { src: server(), dst: client(), flags: flags, len: len }
+    }
+
+    fn state(table: &ConnectionTable) -> State {
+        table.find(&client(), &server()).unwrap().state
+    }
+
+    #[test]
+    fn handshake_data_and_teardown() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_server(SYN, 74), 1000);
+        assert_eq!(state(&table), State::SynSent);
+        table.on_segment(&to_client(SYN | ACK, 74), 1012);
+        assert_eq!(state(&table), State::SynReceived);
+        table.on_segment(&to_server(ACK, 66), 1025);
+        assert_eq!(state(&table), State::Established);
+
+        table.on_segment(&to_server(PSH | ACK, 200), 1030);
+        table.on_segment(&to_client(PSH | ACK, 1500), 1050);
+        table.on_segment(&to_client(PSH | ACK, 900), 1051);
+        table.on_segment(&to_server(FIN | ACK, 66), 1100);
+        assert_eq!(state(&table), State::Closing);
+        table.on_segment(&to_client(FIN | ACK, 66), 1110);
+        assert_eq!(state(&table), State::Closed);
+        table.on_segment(&to_server(ACK, 66), 1120);
+
+        assert_eq!(table.len(), 1);
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state, State::Closed);
+        assert_eq!(connection.client, client());
+        assert_eq!(connection.handshake_rtt, Some(25));
+        assert_eq!((connection.from_client.packets, connection.from_client.bytes), (5, 74 + 66 + 200 + 66 + 66));
+        assert_eq!((connection.from_server.packets, connection.from_server.bytes), (4, 74 + 1500 + 900 + 66));
+        assert_eq!((connection.first_seen, connection.last_seen), (1000, 1120));
+    }
+
+    #[test]
+    fn refused_connection() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_server(SYN, 74), 0);
+        table.on_segment(&to_client(RST | ACK, 60), 1);
+        assert_eq!(state(&table), State::Reset);
+        assert_eq!(table.connections()[0].handshake_rtt, None);
+        // nothing revives it but a new SYN
+        table.on_segment(&to_server(ACK, 60), 2);
+        assert_eq!(state(&table), State::Reset);
+    }
+
+    #[test]
+    fn retransmitted_syn_restarts_the_rtt() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_server(SYN, 74), 0);
+        table.on_segment(&to_server(SYN, 74), 1000);
+        table.on_segment(&to_client(SYN | ACK, 74), 1010);
+        table.on_segment(&to_server(ACK, 66), 1020);
+        assert_eq!(table.connections()[0].handshake_rtt, Some(20));
+        assert_eq!(table.connections()[0].from_client.packets, 3);
+    }
+
+    #[test]
+    fn picked_up_in_the_middle() {
+        let mut table = ConnectionTable::new();
+        // the server talks first, the client is still the one with the high port
+        table.on_segment(&to_client(PSH | ACK, 500), 0);
+        table.on_segment(&to_server(ACK, 60), 1);
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state, State::Established);
+        assert_eq!(connection.client, client());
+        assert_eq!(connection.handshake_rtt, None);
+        assert_eq!(connection.from_server.packets, 1);
+        assert_eq!(connection.from_client.packets, 1);
+    }
+
+    #[test]
+    fn missed_syn() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_client(SYN | ACK, 74), 0);
+        assert_eq!(state(&table), State::SynReceived);
+        assert_eq!(table.connections()[0].client, client());
+        table.on_segment(&to_server(ACK, 66), 5);
+        assert_eq!(state(&table), State::Established);
+        assert_eq!(table.connections()[0].handshake_rtt, None);
+    }
+
+    #[test]
+    fn unknown_resets_are_ignored() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_client(RST, 60), 0);
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn ports_are_reused() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_server(SYN, 74), 0);
+        table.on_segment(&to_client(RST | ACK, 60), 1);
+        table.on_segment(&to_server(SYN, 74), 100);
+        assert_eq!(table.len(), 1);
+        assert_eq!(state(&table), State::SynSent);
+        assert_eq!(table.connections()[0].first_seen, 100);
+        assert_eq!(table.connections()[0].from_client.packets, 1);
+    }
+
+    #[test]
+    fn ipv4_and_ipv6_are_different_connections() {
+        let mut table = ConnectionTable::new();
+        table.on_segment(&to_server(SYN, 74), 0);
+        let v6 = |last, port| Endpoint { address: Address::V6([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, last]), port: port };
+        table.on_segment(&Segment { src: v6(5, 40000), dst: v6(1, 80), flags: SYN, len: 94 }, 1);
+        assert_eq!(table.len(), 2);
+        assert!(table.find(&v6(1, 80), &v6(5, 40000)).is_some());
+    }
+
+    fn open(table: &mut ConnectionTable, port: u16, ticks: usize) {
+        let src = Endpoint { address: Address::V4([10, 0, 0, 5]), port: port };
+        table.on_segment(&Segment { src: src, dst: server(), flags: SYN, len: 74 }, ticks);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_active() {
+        let mut table = ConnectionTable::new();
+        for i in 0..MAX_CONNECTIONS {
+            open(&mut table, 50000 + i as u16, i);
+        }
+        // the first one is active again, the second one is the oldest now
+        open(&mut table, 50000, 100);
+        open(&mut table, 60000, 101);
+        assert_eq!(table.len(), MAX_CONNECTIONS);
+        assert_eq!(table.evicted(), 1);
+        assert!(table.connections().iter().any(|c| c.client.port == 50000));
+        assert!(!table.connections().iter().any(|c| c.client.port == 50001));
+    }
+
+    #[test]
+    fn evicts_finished_connections_first() {
+        let mut table = ConnectionTable::new();
+        for i in 0..MAX_CONNECTIONS {
+            open(&mut table, 50000 + i as u16, i);
+        }
+        let src = Endpoint { address: Address::V4([10, 0, 0, 5]), port: 50010 };
+        table.on_segment(&Segment { src: server(), dst: src, flags: RST | ACK, len: 60 }, 200);
+        open(&mut table, 60000, 201);
+        assert_eq!(table.evicted(), 1);
+        assert!(table.connections().iter().any(|c| c.client.port == 50000));
+        assert!(!table.connections().iter().any(|c| c.client.port == 50010));
+    }
+
+    #[test]
+    fn timeouts_depend_on_the_state() {
+        let mut table = ConnectionTable::new();
+        // half open
+        open(&mut table, 50000, 0);
+        // established
+        open(&mut table, 50001, 0);
+        let src = Endpoint { address: Address::V4([10, 0, 0, 5]), port: 50001 };
+        table.on_segment(&Segment { src: server(), dst: src, flags: SYN | ACK, len: 74 }, 1);
+        table.on_segment(&Segment { src: src, dst: server(), flags: ACK, len: 66 }, 2);
+        // reset
+        table.on_segment(&to_server(PSH | ACK, 100), 0);
+        table.on_segment(&to_client(RST, 60), 0);
+
+        table.expire(9 * 1000);
+        assert_eq!(table.len(), 3);
+        table.expire(11 * 1000);
+        assert_eq!(table.len(), 2);
+        table.expire(31 * 1000);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.connections()[0].state, State::Established);
+        table.expire(11 * 60 * 1000);
+        assert_eq!(table.len(), 0);
+    }
+}
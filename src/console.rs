@@ -0,0 +1,203 @@
+// Reads commands from the debugger's stdin, see `command` for the syntax.
+
+use core::str;
+use collections::string::String;
+use collections::vec::Vec;
+
+use addr;
+use audio_dma;
+use classify;
+use command::{self, Command, ParseError};
+use context::{format_age, Context, TICKS_PER_SECOND};
+use graphics::Graphics;
+use log;
+use screen::{self, Screens};
+use semi_hosting::{self, File, Read};
+
+// every poll stops the board until gdb answered, so don't do it too often
+const POLL_TICKS: usize = TICKS_PER_SECOND / 2;
+const MAX_LINE_LEN: usize = 128;
+const READ_LEN: usize = 32;
+
+/// Collects the typed characters into lines and parses them.
+pub struct Console {
+    stdin: Option<File>,
+    line: Vec<u8>,
+    // read from stdin but not looked at yet, one read may hold several lines
+    pending: [u8; READ_LEN],
+    pending_start: usize,
+    pending_end: usize,
+    last_poll: usize
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let stdin = match File::stdin() {
+            Ok(stdin) => Some(stdin),
+            Err(e) => {
+                warn!("no console, could not open stdin (errno {})", e.errno);
+                None
+            }
+        };
+        Console {
+            stdin: stdin,
+            line: Vec::with_capacity(MAX_LINE_LEN),
+            pending: [0; READ_LEN],
+            pending_start: 0,
+            pending_end: 0,
+            last_poll: 0
+        }
+    }
+
+    /// Returns the next complete line, if one was typed since the last call.
+    /// Lines that arrive together are returned one per call. `semihosting.py`
+    /// answers reads from stdin with nothing if no line is waiting, so this
+    /// doesn't block.
+    pub fn poll(&mut self, ticks: usize) -> Option<Result<Command, ParseError>> {
+        if self.pending_start == self.pending_end {
+            if ticks.wrapping_sub(self.last_poll) < POLL_TICKS {
+                return None;
+            }
+            self.last_poll = ticks;
+
+            let read = match self.stdin {
+                Some(ref mut stdin) => stdin.read(&mut self.pending).unwrap_or(0),
+                None => return None,
+            };
+            self.pending_start = 0;
+            self.pending_end = read;
+        }
+
+        while self.pending_start < self.pending_end {
+            let byte = self.pending[self.pending_start];
+            self.pending_start += 1;
+            match byte {
+                b'\n' | b'\r' => {
+                    let result = match str::from_utf8(&self.line) {
+                        Ok(line) => command::parse(line),
+                        Err(_) => Err(ParseError::NotUtf8),
+                    };
+                    self.line.clear();
+                    if result != Err(ParseError::Empty) {
+                        return Some(result);
+                    }
+                }
+                // a line that is too long can't be a valid command anyway
+                _ if self.line.len() < MAX_LINE_LEN => self.line.push(byte),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Runs a command.
+pub fn execute(command: Command,
+               graphics: &mut Graphics,
+               context: &mut Context,
+               screens: &mut Screens) {
+    match command {
+        Command::Help => println!("{}", command::HELP),
+        Command::Stats => {
+            let stats = &context.stats;
+            println!("{} frames, {} bytes, {} frames/s, {} bytes/s",
+                     stats.total_frames(), stats.total_bytes(),
+                     stats.frames_per_second(), stats.bytes_per_second());
+            for index in 0..classify::TYPE_COUNT {
+                println!("  {:10} {:>8} frames {:>10} bytes",
+                         classify::type_name(index), stats.frames(index), stats.bytes(index));
+            }
+            if let Some(ref filter) = context.settings.filter {
+                println!("{} frames hidden by the filter {}", context.filtered_count, filter);
+            }
+            println!("{} TCP connections, {} evicted", context.connections.len(), context.connections.evicted());
+            println!("{} audio underruns, {} audio DMA errors", audio_dma::underruns(), audio_dma::transfer_errors());
+            println!("{} bytes of output dropped", semi_hosting::dropped());
+        }
+        Command::Hosts => {
+            println!("{} hosts, sorted by {}", context.hosts.len(), context.hosts.order().name());
+            for host in context.hosts.sorted() {
+                let ip = host.ipv4.as_ref().map(addr::format_ipv4).unwrap_or_else(String::new);
+                println!("  {:17} {:15} {:>6} {}", addr::format_mac(&host.mac), ip, host.frames,
+                         host.name.as_ref().map(|n| n.as_str()).unwrap_or(""));
+            }
+        }
+        Command::Arp => {
+            println!("{} ARP cache entries", context.arp_cache.len());
+            for (ip, mac) in context.arp_cache.iter() {
+                println!("  {:15} {}", addr::format_ipv4(ip), addr::format_mac(mac));
+            }
+        }
+        Command::Screen(name) => {
+            if !screens.switch_to_name(graphics, context, &name) {
+                println!("no screen named {}", name);
+            }
+        }
+        Command::Capture(on) => {
+            context.settings.capture = on;
+            // the settings screen shows the new value
+            screens.draw(graphics, context);
+        }
+        Command::Filter(expression) => {
+            let text = expression.clone();
+            match (context.set_filter(expression), text) {
+                (Ok(()), Some(text)) => println!("filter: {}", text),
+                (Ok(()), None) => println!("filter removed"),
+                (Err(error), text) => {
+                    // a caret below the part that's wrong
+                    println!("  {}", text.unwrap_or_default());
+                    println!("  {:>width$}", "^", width = error.position + 1);
+                    println!("bad filter, {}", error);
+                }
+            }
+        }
+        Command::Sound(on) => {
+            context.settings.sound = on;
+            screens.draw(graphics, context);
+        }
+        Command::Ip => {
+            println!("{}", screen::status_text(context));
+            if let Some(lease) = context.dhcp.lease() {
+                println!("  lease from {}, {} of {} left, renewing after {}, rebinding after {}",
+                         addr::format_ipv4(&lease.server), format_age(lease.remaining(context.ticks)),
+                         format_age(lease.duration as usize * TICKS_PER_SECOND),
+                         format_age(lease.renewal_time as usize * TICKS_PER_SECOND),
+                         format_age(lease.rebinding_time as usize * TICKS_PER_SECOND));
+            }
+            print_fallback(context);
+        }
+        Command::StaticIp(config) => {
+            context.dhcp.set_static(config);
+            print_fallback(context);
+        }
+        Command::Log(None, level) => log::set_default_level(level),
+        Command::Log(Some(module), level) => {
+            if log::set_level(&module, level).is_err() {
+                println!("can't set the level of {}, too long or too many modules", module);
+            }
+        }
+    }
+}
+
+// the address used while no DHCP server answers
+fn print_fallback(context: &Context) {
+    match context.dhcp.static_config() {
+        Some(config) => {
+            let gateway = config.gateway.map(|g| format!(" via {}", addr::format_ipv4(&g))).unwrap_or_default();
+            println!("  fallback {}{}", addr::format_ipv4_network(&config.address, &config.netmask), gateway);
+        }
+        None => println!("  fallback link-local"),
+    }
+}
+
+/// Prints why a line wasn't understood.
+pub fn print_error(error: ParseError) {
+    match error {
+        ParseError::Empty => {}
+        ParseError::UnknownCommand(name) => println!("unknown command {}, try help", name),
+        ParseError::MissingArgument(description) => println!("missing argument, expected {}", description),
+        ParseError::BadArgument(argument) => println!("bad argument {}", argument),
+        ParseError::TooManyArguments => println!("too many arguments"),
+        ParseError::NotUtf8 => println!("the line isn't valid UTF-8"),
+    }
+}
@@ -0,0 +1,239 @@
+use collections::BTreeMap;
+use collections::string::String;
+
+use addr;
+use classify::{self, ParseResult, ParseResultType, ParseResultDirection, TYPE_COUNT};
+use conntrack::{self, ConnectionTable, Endpoint, Segment};
+use dhcp_client::DhcpClient;
+use filter::{self, Fields, Filter};
+use hosts::HostTable;
+use lookups::RecentLookups;
+use neighbors::NeighborTable;
+use palette::ColorMode;
+use pcapng::{self, Capture};
+use stats::Stats;
+
+/// `system_clock::ticks()` counts milliseconds.
+pub const TICKS_PER_SECOND: usize = 1000;
+
+/// `42s`, `5m` or `3h`, for a number of ticks
+pub fn format_age(ticks: usize) -> String {
+    let seconds = ticks / TICKS_PER_SECOND;
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else if seconds < 60 * 60 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}h", seconds / (60 * 60))
+    }
+}
+
+/// Runtime settings, changed on the settings screen.
+pub struct Settings {
+    /// Stops adding squares to the packet grid
+    pub paused: bool,
+    pub large_font: bool,
+    /// How the squares of the packet grid are colored
+    pub color_mode: ColorMode,
+    /// Writes the frames to the host, started and stopped in `Context::tick`
+    pub capture: bool,
+    /// Plays a tone per frame
+    pub sound: bool,
+    /// In percent, one of `sonify::VOLUMES`
+    pub volume: u8,
+    /// Per `ParseResultType::index`, muted types make no sound
+    pub muted: [bool; TYPE_COUNT],
+    /// The display filter as typed into the console, see `Context::set_filter`
+    pub filter: Option<String>
+}
+
+/// Everything the firmware learns from the packet stream, shared by all
+/// screens.
+pub struct Context {
+    /// `system_clock::ticks()` at the last `tick`
+    pub ticks: usize,
+    pub stats: Stats,
+    pub hosts: HostTable,
+    /// The TCP connections, followed for all frames, filtered or not
+    pub connections: ConnectionTable,
+    /// The switches announcing themselves with LLDP or CDP
+    pub neighbors: NeighborTable,
+    /// Our own address, fed by the packet handler
+    pub dhcp: DhcpClient,
+    /// A copy of the ethernet driver's ARP cache, which only the packet
+    /// handler can reach
+    pub arp_cache: BTreeMap<[u8; 4], [u8; 6]>,
+    pub recent_lookups: RecentLookups,
+    /// Malformed frames, filtered or not
+    pub malformed_count: u32,
+    pub settings: Settings,
+    pub capture: Capture,
+    /// Frames dropped by the display filter
+    pub filtered_count: u32,
+    // compiled from `settings.filter`
+    filter: Option<Filter>,
+    // the address at the last `tick`, changes are logged
+    address: Option<[u8; 4]>
+}
+
+impl Context {
+    pub fn new(ticks: usize, mac: [u8; 6]) -> Self {
+        Context {
+            ticks: ticks,
+            stats: Stats::new(ticks),
+            hosts: HostTable::new(Some(5 * 60 * TICKS_PER_SECOND)),
+            connections: ConnectionTable::new(),
+            neighbors: NeighborTable::new(),
+            dhcp: DhcpClient::new(mac),
+            arp_cache: BTreeMap::new(),
+            recent_lookups: RecentLookups::new(10),
+            malformed_count: 0,
+            settings: Settings {
+                paused: false,
+                large_font: false,
+                color_mode: ColorMode::MacSource,
+                capture: false,
+                sound: false,
+                volume: 50,
+                muted: [false; TYPE_COUNT],
+                filter: None
+            },
+            capture: Capture::new(),
+            filtered_count: 0,
+            filter: None,
+            address: None
+        }
+    }
+
+    pub fn tick(&mut self, ticks: usize) {
+        self.ticks = ticks;
+        self.stats.tick(ticks);
+        self.hosts.expire(ticks);
+        self.connections.expire(ticks);
+        self.neighbors.expire(ticks);
+
+        self.dhcp.tick(ticks);
+        let address = self.dhcp.address();
+        if address != self.address {
+            self.address = address;
+            match (address, self.dhcp.source()) {
+                (Some(address), Some(source)) => {
+                    info!("using {} ({:?})", addr::format_ipv4(&address), source);
+                }
+                _ => warn!("no address, DHCP {}", self.dhcp.state().name()),
+            }
+        }
+
+        if self.settings.capture && !self.capture.is_running() {
+            match self.capture.start() {
+                Ok(()) => info!("capturing to {}", pcapng::CAPTURE_FILE),
+                Err(e) => {
+                    error!("could not write {} on the host (errno {})", pcapng::CAPTURE_FILE, e.errno);
+                    self.settings.capture = false;
+                }
+            }
+        } else if !self.settings.capture && self.capture.is_running() {
+            self.capture.stop();
+        }
+    }
+
+    /// Compiles and applies a display filter, `None` removes it. A bad
+    /// expression leaves the current filter in place.
+    pub fn set_filter(&mut self, expression: Option<String>) -> Result<(), filter::Error> {
+        self.filter = match expression {
+            Some(ref expression) => Some(Filter::parse(expression, classify::type_names(), &conntrack::STATE_NAMES)?),
+            None => None,
+        };
+        self.settings.filter = expression;
+        self.filtered_count = 0;
+        Ok(())
+    }
+
+    /// Called for every frame, before the display filter. Connections are
+    /// followed, and malformed frames counted, even while some of the frames
+    /// are hidden.
+    pub fn track(&mut self, parse_result: &ParseResult) {
+        if let Some(segment) = tcp_segment(parse_result) {
+            self.connections.on_segment(&segment, self.ticks);
+        }
+        if let ParseResultType::Malformed(reason) = parse_result.pkt_type {
+            self.malformed_count += 1;
+            debug!("malformed frame ({:?}), {} so far", reason, self.malformed_count);
+        }
+    }
+
+    /// Whether a frame passes the display filter. Only frames that do are
+    /// handed to `on_packet` and the screens.
+    pub fn passes_filter(&mut self, parse_result: &ParseResult) -> bool {
+        let passes = match self.filter {
+            Some(ref filter) => {
+                let mut fields = filter_fields(parse_result);
+                if let Some(segment) = tcp_segment(parse_result) {
+                    if let Some(connection) = self.connections.find(&segment.src, &segment.dst) {
+                        fields.tcp_state = Some(connection.state.index());
+                        fields.tcp_rtt = connection.handshake_rtt.map(|rtt| (rtt * 1000 / TICKS_PER_SECOND) as u32);
+                    }
+                }
+                filter.matches(&fields)
+            }
+            None => true,
+        };
+        if !passes {
+            self.filtered_count = self.filtered_count.wrapping_add(1);
+        }
+        passes
+    }
+
+    pub fn on_packet(&mut self, parse_result: &ParseResult) {
+        self.stats.count(parse_result);
+        self.hosts.on_packet(parse_result, self.ticks);
+        self.neighbors.on_packet(parse_result, self.ticks);
+
+        if parse_result.pkt_type == ParseResultType::DNS && parse_result.direction == Some(ParseResultDirection::Request) {
+            if let Some(ref hostname) = parse_result.hostname {
+                self.recent_lookups.push(hostname);
+                info!("dns lookup: {}", hostname);
+            }
+        }
+    }
+}
+
+/// What the display filters get to see of a frame.
+fn filter_fields(parse_result: &ParseResult) -> Fields {
+    Fields {
+        protocol: parse_result.pkt_type.index(),
+        len: parse_result.len,
+        mac_src: parse_result.mac_src,
+        mac_dst: parse_result.mac_dst,
+        ipv4_src: parse_result.ipv4_src,
+        ipv4_dst: parse_result.ipv4_dst,
+        ipv6_src: parse_result.ipv6_src,
+        ipv6_dst: parse_result.ipv6_dst,
+        port_src: parse_result.port_src,
+        port_dst: parse_result.port_dst,
+        vlan: parse_result.vlan_id,
+        // known to the connection table only, see `Context::passes_filter`
+        tcp_state: None,
+        tcp_rtt: None
+    }
+}
+
+/// The parts of a TCP frame the connection table follows the connections
+/// with, `None` for everything else.
+fn tcp_segment(parse_result: &ParseResult) -> Option<Segment> {
+    let (tcp, src_port, dst_port) = match (parse_result.tcp, parse_result.port_src, parse_result.port_dst) {
+        (Some(tcp), Some(src_port), Some(dst_port)) => (tcp, src_port, dst_port),
+        _ => return None,
+    };
+    let (src, dst) = match (parse_result.ipv4_src, parse_result.ipv4_dst, parse_result.ipv6_src, parse_result.ipv6_dst) {
+        (Some(src), Some(dst), _, _) => (conntrack::Address::V4(src), conntrack::Address::V4(dst)),
+        (_, _, Some(src), Some(dst)) => (conntrack::Address::V6(src), conntrack::Address::V6(dst)),
+        _ => return None,
+    };
+    Some(Segment {
+        src: Endpoint { address: src, port: src_port },
+        dst: Endpoint { address: dst, port: dst_port },
+        flags: tcp.flags.0,
+        len: parse_result.len
+    })
+}
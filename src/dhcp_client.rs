@@ -0,0 +1,979 @@
+// A DHCP client (RFC 2131) for the board's own address, with a static and a
+// link-local (RFC 3927) fallback for networks without a DHCP server.
+//
+// The state machine does no I/O. Received DHCP replies and ARP packets are
+// fed in, `tick` runs the timers, and the frames to send are queued until
+// the main loop sends them.
+
+use collections::Vec;
+use core::cmp;
+
+use classify::DhcpInfo;
+
+pub type Ipv4 = [u8; 4];
+
+// times are in ticks, which are milliseconds
+
+// DISCOVER and REQUEST are retransmitted after 4, 8, 16, ... seconds
+const RETRANSMIT_BASE: usize = 4 * 1000;
+const RETRANSMIT_MAX: usize = 64 * 1000;
+// unanswered DISCOVERs until the fallback address is used, about half a minute
+const FALLBACK_ATTEMPTS: u32 = 3;
+// a REQUEST nobody answers sends us back to discovering
+const REQUEST_ATTEMPTS: u32 = 4;
+// renewing and rebinding retransmit after half the time that's left, but
+// not more often than this
+const RENEW_RETRANSMIT_MIN: usize = 60 * 1000;
+// longer times overflow the tick arithmetic, renewing early doesn't hurt
+const MAX_LEASE_SECONDS: u32 = 24 * 24 * 60 * 60;
+// for servers that don't send a lease time
+const DEFAULT_LEASE_SECONDS: u32 = 60 * 60;
+
+// the timing constants of RFC 3927, without the random parts
+const PROBE_WAIT: usize = 1000;
+const PROBE_NUM: u32 = 3;
+const PROBE_INTERVAL: usize = 1500;
+const ANNOUNCE_WAIT: usize = 2000;
+const ANNOUNCE_NUM: u32 = 2;
+const ANNOUNCE_INTERVAL: usize = 2000;
+const MAX_CONFLICTS: u32 = 10;
+const RATE_LIMIT_INTERVAL: usize = 60 * 1000;
+const DEFEND_INTERVAL: usize = 10 * 1000;
+
+// the frames waiting to be sent, the oldest are dropped
+const MAX_QUEUED_FRAMES: usize = 4;
+
+pub const DHCP_DISCOVER: u8 = 1;
+pub const DHCP_OFFER: u8 = 2;
+pub const DHCP_REQUEST: u8 = 3;
+pub const DHCP_ACK: u8 = 5;
+pub const DHCP_NAK: u8 = 6;
+
+const DHCP_MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+// the smallest message relay agents have to accept (RFC 1542)
+const BOOTP_MIN_LEN: usize = 300;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+const BROADCAST_IP: Ipv4 = [255; 4];
+const NO_IP: Ipv4 = [0; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Init,
+    /// Sent a DISCOVER, waiting for offers
+    Selecting,
+    /// Asked for the offered address
+    Requesting,
+    Bound,
+    /// Past T1, asking the server that gave us the lease for more time
+    Renewing,
+    /// Past T2, asking any server
+    Rebinding
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Init => "init",
+            State::Selecting => "selecting",
+            State::Requesting => "requesting",
+            State::Bound => "bound",
+            State::Renewing => "renewing",
+            State::Rebinding => "rebinding",
+        }
+    }
+}
+
+/// Where the address in use comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Dhcp,
+    Static,
+    LinkLocal
+}
+
+/// The address used when no DHCP server answers, instead of a link-local one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticConfig {
+    pub address: Ipv4,
+    pub netmask: Ipv4,
+    pub gateway: Option<Ipv4>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    pub address: Ipv4,
+    pub netmask: Option<Ipv4>,
+    pub router: Option<Ipv4>,
+    pub server: Ipv4,
+    server_mac: [u8; 6],
+    /// When the REQUEST that got the lease was sent, the times count from there
+    pub start: usize,
+    /// In seconds
+    pub duration: u32,
+    pub renewal_time: u32,
+    pub rebinding_time: u32
+}
+
+impl Lease {
+    fn at(&self, seconds: u32) -> usize {
+        self.start.wrapping_add(seconds as usize * 1000)
+    }
+
+    /// Ticks until the lease runs out.
+    pub fn remaining(&self, now: usize) -> usize {
+        let end = self.at(self.duration);
+        if due(now, end) { 0 } else { end.wrapping_sub(now) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkLocalState {
+    Off,
+    /// Asking whether anybody uses the candidate, `sent` probes so far
+    Probing { sent: u32 },
+    /// Nobody answered, telling everyone the address is ours now
+    Announcing { sent: u32 },
+    Claimed
+}
+
+struct LinkLocal {
+    state: LinkLocalState,
+    address: Ipv4,
+    conflicts: u32,
+    next: usize,
+    last_defense: Option<usize>
+}
+
+impl LinkLocal {
+    fn is_usable(&self) -> bool {
+        match self.state {
+            LinkLocalState::Announcing { .. } | LinkLocalState::Claimed => true,
+            _ => false,
+        }
+    }
+}
+
+pub struct DhcpClient {
+    mac: [u8; 6],
+    state: State,
+    xid: u32,
+    // when the next message of the current state goes out
+    next: usize,
+    attempts: u32,
+    // when the last REQUEST was sent
+    request_time: usize,
+    offer: Option<DhcpInfo>,
+    lease: Option<Lease>,
+    static_config: Option<StaticConfig>,
+    // no server answered, the static or link-local address is in use
+    fallback: bool,
+    link_local: LinkLocal,
+    frames: Vec<Vec<u8>>
+}
+
+impl DhcpClient {
+    pub fn new(mac: [u8; 6]) -> Self {
+        DhcpClient {
+            mac: mac,
+            state: State::Init,
+            xid: 0,
+            next: 0,
+            attempts: 0,
+            request_time: 0,
+            offer: None,
+            lease: None,
+            static_config: None,
+            fallback: false,
+            link_local: LinkLocal {
+                state: LinkLocalState::Off,
+                address: NO_IP,
+                conflicts: 0,
+                next: 0,
+                last_defense: None
+            },
+            frames: Vec::new()
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn lease(&self) -> Option<&Lease> {
+        self.lease.as_ref()
+    }
+
+    /// Where the current address comes from, `None` while there is none.
+    pub fn source(&self) -> Option<Source> {
+        if self.lease.is_some() {
+            Some(Source::Dhcp)
+        } else if !self.fallback {
+            None
+        } else if self.static_config.is_some() {
+            Some(Source::Static)
+        } else if self.link_local.is_usable() {
+            Some(Source::LinkLocal)
+        } else {
+            None
+        }
+    }
+
+    /// The address to answer ARP and ICMP for.
+    pub fn address(&self) -> Option<Ipv4> {
+        match self.source() {
+            Some(Source::Dhcp) => self.lease.map(|l| l.address),
+            Some(Source::Static) => self.static_config.map(|c| c.address),
+            Some(Source::LinkLocal) => Some(self.link_local.address),
+            None => None,
+        }
+    }
+
+    pub fn netmask(&self) -> Option<Ipv4> {
+        match self.source() {
+            Some(Source::Dhcp) => self.lease.and_then(|l| l.netmask),
+            Some(Source::Static) => self.static_config.map(|c| c.netmask),
+            Some(Source::LinkLocal) => Some([255, 255, 0, 0]),
+            None => None,
+        }
+    }
+
+    pub fn gateway(&self) -> Option<Ipv4> {
+        match self.source() {
+            Some(Source::Dhcp) => self.lease.and_then(|l| l.router),
+            Some(Source::Static) => self.static_config.and_then(|c| c.gateway),
+            _ => None,
+        }
+    }
+
+    /// The address asked for in the REQUEST that is waiting for an answer.
+    pub fn requested_address(&self) -> Option<Ipv4> {
+        match self.state {
+            State::Requesting => self.offer.map(|o| o.your_address),
+            State::Renewing | State::Rebinding => self.lease.map(|l| l.address),
+            _ => None,
+        }
+    }
+
+    pub fn static_config(&self) -> Option<StaticConfig> {
+        self.static_config
+    }
+
+    /// Sets the fallback address, `None` falls back to a link-local one.
+    pub fn set_static(&mut self, config: Option<StaticConfig>) {
+        self.static_config = config;
+        if config.is_some() {
+            self.link_local.state = LinkLocalState::Off;
+        }
+    }
+
+    /// The next frame to send, if any.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        if self.frames.is_empty() { None } else { Some(self.frames.remove(0)) }
+    }
+
+    /// Sends what's due.
+    pub fn tick(&mut self, now: usize) {
+        match self.state {
+            State::Init => {
+                self.xid = self.new_xid(now);
+                self.offer = None;
+                self.attempts = 0;
+                self.state = State::Selecting;
+                self.send_discover();
+                self.next = now.wrapping_add(retransmit_delay(0));
+            }
+            State::Selecting => {
+                if due(now, self.next) {
+                    self.attempts += 1;
+                    if self.attempts >= FALLBACK_ATTEMPTS {
+                        self.fallback = true;
+                    }
+                    self.send_discover();
+                    self.next = now.wrapping_add(retransmit_delay(self.attempts));
+                }
+            }
+            State::Requesting => {
+                if due(now, self.next) {
+                    self.attempts += 1;
+                    if self.attempts >= REQUEST_ATTEMPTS {
+                        self.state = State::Init;
+                    } else {
+                        self.send_request(now);
+                        self.next = now.wrapping_add(retransmit_delay(self.attempts));
+                    }
+                }
+            }
+            State::Bound | State::Renewing | State::Rebinding => self.tick_lease(now),
+        }
+
+        if self.fallback && self.static_config.is_none() {
+            self.tick_link_local(now);
+        }
+    }
+
+    fn tick_lease(&mut self, now: usize) {
+        let lease = match self.lease {
+            Some(lease) => lease,
+            None => {
+                self.state = State::Init;
+                return;
+            }
+        };
+        let t1 = lease.at(lease.renewal_time);
+        let t2 = lease.at(lease.rebinding_time);
+        let end = lease.at(lease.duration);
+
+        if due(now, end) {
+            self.lease = None;
+            self.state = State::Init;
+        } else if self.state != State::Rebinding && due(now, t2) {
+            self.state = State::Rebinding;
+            self.send_request(now);
+            self.next = renew_retransmit(now, end);
+        } else if self.state == State::Bound && due(now, t1) {
+            self.state = State::Renewing;
+            self.send_request(now);
+            self.next = renew_retransmit(now, t2);
+        } else if self.state != State::Bound && due(now, self.next) {
+            self.send_request(now);
+            let deadline = if self.state == State::Renewing { t2 } else { end };
+            self.next = renew_retransmit(now, deadline);
+        }
+    }
+
+    pub fn on_dhcp(&mut self, info: &DhcpInfo, now: usize) {
+        if info.xid != self.xid || info.client_mac != self.mac {
+            return;
+        }
+        match (self.state, info.message_type) {
+            (State::Selecting, DHCP_OFFER) if info.your_address != NO_IP => {
+                self.offer = Some(*info);
+                self.state = State::Requesting;
+                self.attempts = 0;
+                self.send_request(now);
+                self.next = now.wrapping_add(retransmit_delay(0));
+            }
+            (State::Requesting, DHCP_ACK) | (State::Renewing, DHCP_ACK) | (State::Rebinding, DHCP_ACK) => {
+                self.bind(info);
+            }
+            (State::Requesting, DHCP_NAK) | (State::Renewing, DHCP_NAK) | (State::Rebinding, DHCP_NAK) => {
+                self.lease = None;
+                self.state = State::Init;
+            }
+            _ => {}
+        }
+    }
+
+    fn bind(&mut self, ack: &DhcpInfo) {
+        let offer = self.offer;
+        let previous = self.lease;
+        let server = ack.server_id
+            .or_else(|| offer.and_then(|o| o.server_id))
+            .or_else(|| previous.map(|l| l.server))
+            .unwrap_or(NO_IP);
+        let duration = cmp::min(ack.lease_time.unwrap_or(DEFAULT_LEASE_SECONDS), MAX_LEASE_SECONDS);
+        let renewal_time = cmp::min(ack.renewal_time.unwrap_or(duration / 2), duration);
+        let rebinding_time = cmp::min(ack.rebinding_time.unwrap_or(duration / 8 * 7), duration);
+
+        self.lease = Some(Lease {
+            address: ack.your_address,
+            netmask: ack.netmask,
+            router: ack.router,
+            server: server,
+            server_mac: ack.server_mac,
+            start: self.request_time,
+            duration: duration,
+            // T1 can't be after T2
+            renewal_time: cmp::min(renewal_time, rebinding_time),
+            rebinding_time: rebinding_time
+        });
+        self.state = State::Bound;
+        self.fallback = false;
+        self.link_local.state = LinkLocalState::Off;
+    }
+
+    /// Checks ARP packets of other hosts for conflicts with the link-local
+    /// address.
+    pub fn on_arp(&mut self, sender_mac: [u8; 6], sender_ip: Ipv4, target_ip: Ipv4, now: usize) {
+        if sender_mac == self.mac {
+            return;
+        }
+        let candidate = self.link_local.address;
+        match self.link_local.state {
+            LinkLocalState::Off => {}
+            LinkLocalState::Probing { .. } => {
+                // someone uses it, or probes for it at the same time
+                if sender_ip == candidate || (sender_ip == NO_IP && target_ip == candidate) {
+                    self.next_link_local_candidate(now);
+                }
+            }
+            LinkLocalState::Announcing { .. } | LinkLocalState::Claimed => {
+                if sender_ip == candidate {
+                    let recently = match self.link_local.last_defense {
+                        Some(last) => !due(now, last.wrapping_add(DEFEND_INTERVAL)),
+                        None => false,
+                    };
+                    if recently {
+                        // the other host insists, leave the address to it
+                        self.next_link_local_candidate(now);
+                    } else {
+                        self.link_local.last_defense = Some(now);
+                        let frame = self.arp_frame(candidate, candidate);
+                        self.queue(frame);
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick_link_local(&mut self, now: usize) {
+        match self.link_local.state {
+            LinkLocalState::Off => {
+                self.link_local.address = link_local_address(&self.mac, self.link_local.conflicts);
+                self.link_local.state = LinkLocalState::Probing { sent: 0 };
+                self.link_local.next = now.wrapping_add(PROBE_WAIT);
+            }
+            LinkLocalState::Probing { sent } if due(now, self.link_local.next) => {
+                if sent < PROBE_NUM {
+                    let frame = self.arp_frame(NO_IP, self.link_local.address);
+                    self.queue(frame);
+                    self.link_local.state = LinkLocalState::Probing { sent: sent + 1 };
+                    let wait = if sent + 1 == PROBE_NUM { ANNOUNCE_WAIT } else { PROBE_INTERVAL };
+                    self.link_local.next = now.wrapping_add(wait);
+                } else {
+                    self.link_local.state = LinkLocalState::Announcing { sent: 0 };
+                    self.link_local.next = now;
+                    self.tick_link_local(now);
+                }
+            }
+            LinkLocalState::Announcing { sent } if due(now, self.link_local.next) => {
+                let address = self.link_local.address;
+                let frame = self.arp_frame(address, address);
+                self.queue(frame);
+                self.link_local.state = if sent + 1 == ANNOUNCE_NUM {
+                    LinkLocalState::Claimed
+                } else {
+                    LinkLocalState::Announcing { sent: sent + 1 }
+                };
+                self.link_local.next = now.wrapping_add(ANNOUNCE_INTERVAL);
+            }
+            _ => {}
+        }
+    }
+
+    fn next_link_local_candidate(&mut self, now: usize) {
+        self.link_local.conflicts += 1;
+        self.link_local.address = link_local_address(&self.mac, self.link_local.conflicts);
+        self.link_local.state = LinkLocalState::Probing { sent: 0 };
+        self.link_local.last_defense = None;
+        // after many conflicts slow down, maybe someone is attacking us
+        let wait = if self.link_local.conflicts >= MAX_CONFLICTS { RATE_LIMIT_INTERVAL } else { PROBE_WAIT };
+        self.link_local.next = now.wrapping_add(wait);
+    }
+
+    fn new_xid(&self, now: usize) -> u32 {
+        fnv(&self.mac, 0) ^ now as u32 ^ self.xid.rotate_left(7)
+    }
+
+    fn send_discover(&mut self) {
+        let frame = self.dhcp_frame(DHCP_DISCOVER, BROADCAST_MAC, NO_IP, BROADCAST_IP, NO_IP, None, None);
+        self.queue(frame);
+    }
+
+    // the REQUEST of the current state
+    fn send_request(&mut self, now: usize) {
+        self.request_time = now;
+        let frame = match (self.state, self.offer, self.lease) {
+            (State::Requesting, Some(offer), _) => {
+                self.dhcp_frame(DHCP_REQUEST, BROADCAST_MAC, NO_IP, BROADCAST_IP, NO_IP,
+                                Some(offer.your_address), offer.server_id)
+            }
+            // unicast to the server that gave us the lease
+            (State::Renewing, _, Some(lease)) => {
+                self.dhcp_frame(DHCP_REQUEST, lease.server_mac, lease.address, lease.server, lease.address, None, None)
+            }
+            (State::Rebinding, _, Some(lease)) => {
+                self.dhcp_frame(DHCP_REQUEST, BROADCAST_MAC, lease.address, BROADCAST_IP, lease.address, None, None)
+            }
+            _ => return,
+        };
+        self.queue(frame);
+    }
+
+    fn queue(&mut self, frame: Vec<u8>) {
+        if self.frames.len() >= MAX_QUEUED_FRAMES {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // a whole ethernet frame with a DHCP message from the client port
+    fn dhcp_frame(&self, message_type: u8, dst_mac: [u8; 6], src_ip: Ipv4, dst_ip: Ipv4, client_ip: Ipv4,
+                  requested: Option<Ipv4>, server: Option<Ipv4>) -> Vec<u8> {
+        let mut dhcp = Vec::with_capacity(BOOTP_MIN_LEN);
+        // op, htype, hlen, hops
+        dhcp.extend_from_slice(&[1, 1, 6, 0]);
+        dhcp.extend_from_slice(&be32(self.xid));
+        // secs, and the broadcast flag as long as we can't receive unicasts
+        let flags = if client_ip == NO_IP { 0x8000 } else { 0 };
+        dhcp.extend_from_slice(&[0, 0, (flags >> 8) as u8, 0]);
+        dhcp.extend_from_slice(&client_ip);
+        // yiaddr, siaddr and giaddr
+        dhcp.extend_from_slice(&[0; 12]);
+        dhcp.extend_from_slice(&self.mac);
+        // the rest of chaddr, sname and file
+        dhcp.extend_from_slice(&[0; 10 + 64 + 128]);
+        dhcp.extend_from_slice(&DHCP_MAGIC_COOKIE);
+
+        dhcp.extend_from_slice(&[53, 1, message_type]);
+        // client identifier, hardware type ethernet
+        dhcp.extend_from_slice(&[61, 7, 1]);
+        dhcp.extend_from_slice(&self.mac);
+        if let Some(requested) = requested {
+            dhcp.extend_from_slice(&[50, 4]);
+            dhcp.extend_from_slice(&requested);
+        }
+        if let Some(server) = server {
+            dhcp.extend_from_slice(&[54, 4]);
+            dhcp.extend_from_slice(&server);
+        }
+        // netmask, router, DNS servers, lease time, T1 and T2
+        dhcp.extend_from_slice(&[55, 6, 1, 3, 6, 51, 58, 59]);
+        dhcp.push(255);
+        while dhcp.len() < BOOTP_MIN_LEN {
+            dhcp.push(0);
+        }
+
+        let udp_len = 8 + dhcp.len();
+        let ip_len = 20 + udp_len;
+        let mut frame = Vec::with_capacity(14 + ip_len);
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&self.mac);
+        frame.extend_from_slice(&[0x08, 0x00]);
+
+        let mut ip = [0x45, 0, (ip_len >> 8) as u8, ip_len as u8, 0, 0, 0, 0, 64, 17, 0, 0,
+                      src_ip[0], src_ip[1], src_ip[2], src_ip[3], dst_ip[0], dst_ip[1], dst_ip[2], dst_ip[3]];
+        let checksum = ipv4_checksum(&ip);
+        ip[10] = (checksum >> 8) as u8;
+        ip[11] = checksum as u8;
+        frame.extend_from_slice(&ip);
+
+        // from port 68 to 67, the checksum is optional with IPv4
+        frame.extend_from_slice(&[0, 68, 0, 67, (udp_len >> 8) as u8, udp_len as u8, 0, 0]);
+        frame.extend_from_slice(&dhcp);
+        frame
+    }
+
+    // a broadcast ARP request, a probe if `sender_ip` is 0.0.0.0, an
+    // announcement if it's `target_ip`
+    fn arp_frame(&self, sender_ip: Ipv4, target_ip: Ipv4) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(60);
+        frame.extend_from_slice(&BROADCAST_MAC);
+        frame.extend_from_slice(&self.mac);
+        frame.extend_from_slice(&[0x08, 0x06, 0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        frame.extend_from_slice(&self.mac);
+        frame.extend_from_slice(&sender_ip);
+        frame.extend_from_slice(&[0; 6]);
+        frame.extend_from_slice(&target_ip);
+        // the minimum ethernet frame, without the CRC
+        while frame.len() < 60 {
+            frame.push(0);
+        }
+        frame
+    }
+}
+
+/// The netmask of a prefix length, `24` is `255.255.255.0`.
+pub fn prefix_netmask(prefix: u8) -> Ipv4 {
+    let mask = if prefix == 0 { 0 } else { !0u32 << (32 - cmp::min(prefix, 32) as u32) };
+    be32(mask)
+}
+
+/// The prefix length of a netmask, counting the leading ones.
+pub fn netmask_prefix(netmask: &Ipv4) -> u8 {
+    let mask = (netmask[0] as u32) << 24 | (netmask[1] as u32) << 16 | (netmask[2] as u32) << 8 | netmask[3] as u32;
+    (!mask).leading_zeros() as u8
+}
+
+// whether `deadline` has passed, across the wrap around of the ticks
+fn due(now: usize, deadline: usize) -> bool {
+    now.wrapping_sub(deadline) as isize >= 0
+}
+
+fn retransmit_delay(attempts: u32) -> usize {
+    cmp::min(RETRANSMIT_BASE << cmp::min(attempts, 4), RETRANSMIT_MAX)
+}
+
+fn renew_retransmit(now: usize, deadline: usize) -> usize {
+    let remaining = if due(now, deadline) { 0 } else { deadline.wrapping_sub(now) };
+    now.wrapping_add(cmp::max(remaining / 2, RENEW_RETRANSMIT_MIN))
+}
+
+// 169.254.1.0 to 169.254.254.255, the same for the same MAC address, a
+// different one after every conflict
+fn link_local_address(mac: &[u8; 6], conflicts: u32) -> Ipv4 {
+    let hash = fnv(mac, conflicts);
+    [169, 254, 1 + (hash % 254) as u8, (hash >> 8) as u8]
+}
+
+// FNV-1a over the bytes and the seed
+fn fnv(bytes: &[u8], seed: u32) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for &byte in bytes.iter().chain(be32(seed).iter()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+fn be32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for pair in header.chunks(2) {
+        sum += (pair[0] as u32) << 8 | pair[1] as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x00, 0x08, 0xdc, 0xab, 0xcd, 0xef];
+    const SERVER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const SERVER: Ipv4 = [10, 0, 0, 1];
+    const OFFERED: Ipv4 = [10, 0, 0, 23];
+
+    fn frames(client: &mut DhcpClient) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        while let Some(frame) = client.next_frame() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    // the DHCP message type of a frame built by the client
+    fn message_type(frame: &[u8]) -> u8 {
+        assert_eq!(&frame[12..14], &[0x08, 0x00]);
+        frame[14 + 20 + 8 + 240 + 2]
+    }
+
+    fn ip_dst(frame: &[u8]) -> Ipv4 {
+        [frame[30], frame[31], frame[32], frame[33]]
+    }
+
+    fn reply(client: &DhcpClient, message_type: u8) -> DhcpInfo {
+        DhcpInfo {
+            message_type: message_type,
+            xid: client.xid,
+            client_mac: MAC,
+            your_address: OFFERED,
+            server_mac: SERVER_MAC,
+            server_id: Some(SERVER),
+            netmask: Some([255, 255, 255, 0]),
+            router: Some(SERVER),
+            lease_time: Some(3600),
+            renewal_time: None,
+            rebinding_time: None
+        }
+    }
+
+    fn bound(client: &mut DhcpClient) {
+        client.tick(0);
+        let offer = reply(client, DHCP_OFFER);
+        client.on_dhcp(&offer, 100);
+        let ack = reply(client, DHCP_ACK);
+        client.on_dhcp(&ack, 150);
+        frames(client);
+    }
+
+    #[test]
+    fn discover_offer_request_ack() {
+        let mut client = DhcpClient::new(MAC);
+        assert_eq!(client.state(), State::Init);
+        client.tick(0);
+        assert_eq!(client.state(), State::Selecting);
+        let sent = frames(&mut client);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(message_type(&sent[0]), DHCP_DISCOVER);
+        assert_eq!(&sent[0][0..6], &[0xff; 6]);
+        assert_eq!(ip_dst(&sent[0]), [255; 4]);
+        // at least the BOOTP minimum
+        assert!(sent[0].len() >= 14 + 20 + 8 + 300);
+
+        let offer = reply(&client, DHCP_OFFER);
+        client.on_dhcp(&offer, 100);
+        assert_eq!(client.state(), State::Requesting);
+        assert_eq!(client.requested_address(), Some(OFFERED));
+        assert_eq!(client.address(), None);
+        let sent = frames(&mut client);
+        assert_eq!(message_type(&sent[0]), DHCP_REQUEST);
+
+        let ack = reply(&client, DHCP_ACK);
+        client.on_dhcp(&ack, 150);
+        assert_eq!(client.state(), State::Bound);
+        assert_eq!(client.address(), Some(OFFERED));
+        assert_eq!(client.netmask(), Some([255, 255, 255, 0]));
+        assert_eq!(client.gateway(), Some(SERVER));
+        assert_eq!(client.source(), Some(Source::Dhcp));
+        // counted from the REQUEST
+        assert_eq!(client.lease().unwrap().remaining(100), 3600 * 1000);
+    }
+
+    #[test]
+    fn ignores_other_transactions() {
+        let mut client = DhcpClient::new(MAC);
+        client.tick(0);
+        let mut offer = reply(&client, DHCP_OFFER);
+        offer.xid ^= 1;
+        client.on_dhcp(&offer, 10);
+        let mut offer = reply(&client, DHCP_OFFER);
+        offer.client_mac = SERVER_MAC;
+        client.on_dhcp(&offer, 10);
+        assert_eq!(client.state(), State::Selecting);
+    }
+
+    #[test]
+    fn discover_backs_off() {
+        let mut client = DhcpClient::new(MAC);
+        client.tick(0);
+        frames(&mut client);
+        client.tick(3999);
+        assert!(frames(&mut client).is_empty());
+        client.tick(4000);
+        assert_eq!(frames(&mut client).len(), 1);
+        client.tick(4000 + 7999);
+        assert!(frames(&mut client).is_empty());
+        client.tick(4000 + 8000);
+        assert_eq!(frames(&mut client).len(), 1);
+    }
+
+    #[test]
+    fn renews_at_t1_and_rebinds_at_t2() {
+        let mut client = DhcpClient::new(MAC);
+        bound(&mut client);
+        let start = 100;
+
+        client.tick(start + 1799 * 1000);
+        assert_eq!(client.state(), State::Bound);
+        client.tick(start + 1800 * 1000);
+        assert_eq!(client.state(), State::Renewing);
+        let sent = frames(&mut client);
+        assert_eq!(message_type(&sent[0]), DHCP_REQUEST);
+        // unicast to the server
+        assert_eq!(&sent[0][0..6], &SERVER_MAC);
+        assert_eq!(ip_dst(&sent[0]), SERVER);
+
+        // half of the 1350 seconds until T2
+        client.tick(start + (1800 + 674) * 1000);
+        assert!(frames(&mut client).is_empty());
+        client.tick(start + (1800 + 675) * 1000);
+        assert_eq!(frames(&mut client).len(), 1);
+
+        client.tick(start + 3150 * 1000);
+        assert_eq!(client.state(), State::Rebinding);
+        let sent = frames(&mut client);
+        assert_eq!(ip_dst(&sent[0]), [255; 4]);
+        assert_eq!(client.address(), Some(OFFERED));
+
+        // a new lease, counted from the last REQUEST
+        let ack = reply(&client, DHCP_ACK);
+        client.on_dhcp(&ack, start + 3151 * 1000);
+        assert_eq!(client.state(), State::Bound);
+        assert_eq!(client.lease().unwrap().start, start + 3150 * 1000);
+    }
+
+    #[test]
+    fn lease_expires() {
+        let mut client = DhcpClient::new(MAC);
+        bound(&mut client);
+        client.tick(100 + 3150 * 1000);
+        client.tick(100 + 3600 * 1000);
+        assert_eq!(client.address(), None);
+        assert_eq!(client.state(), State::Init);
+        client.tick(100 + 3600 * 1000 + 1);
+        assert_eq!(client.state(), State::Selecting);
+        let sent = frames(&mut client);
+        assert_eq!(message_type(&sent[sent.len() - 1]), DHCP_DISCOVER);
+    }
+
+    #[test]
+    fn server_times_are_honored() {
+        let mut client = DhcpClient::new(MAC);
+        client.tick(0);
+        let offer = reply(&client, DHCP_OFFER);
+        client.on_dhcp(&offer, 0);
+        let mut ack = reply(&client, DHCP_ACK);
+        ack.lease_time = Some(600);
+        ack.renewal_time = Some(60);
+        ack.rebinding_time = Some(120);
+        client.on_dhcp(&ack, 10);
+        client.tick(59 * 1000);
+        assert_eq!(client.state(), State::Bound);
+        client.tick(60 * 1000);
+        assert_eq!(client.state(), State::Renewing);
+        client.tick(120 * 1000);
+        assert_eq!(client.state(), State::Rebinding);
+    }
+
+    #[test]
+    fn nak_starts_over() {
+        let mut client = DhcpClient::new(MAC);
+        bound(&mut client);
+        client.tick(100 + 1800 * 1000);
+        let nak = reply(&client, DHCP_NAK);
+        client.on_dhcp(&nak, 100 + 1801 * 1000);
+        assert_eq!(client.state(), State::Init);
+        assert_eq!(client.address(), None);
+    }
+
+    #[test]
+    fn unanswered_requests_start_over() {
+        let mut client = DhcpClient::new(MAC);
+        client.tick(0);
+        let offer = reply(&client, DHCP_OFFER);
+        client.on_dhcp(&offer, 0);
+        let mut now = 0;
+        for _ in 0..REQUEST_ATTEMPTS {
+            now += 64 * 1000;
+            client.tick(now);
+        }
+        assert_eq!(client.state(), State::Init);
+    }
+
+    // no server, the discovers go unanswered until the fallback kicks in
+    fn without_server(client: &mut DhcpClient) -> usize {
+        let mut now = 0;
+        client.tick(now);
+        while client.source().is_none() && now < 120 * 1000 {
+            now += 100;
+            client.tick(now);
+        }
+        now
+    }
+
+    #[test]
+    fn static_fallback() {
+        let mut client = DhcpClient::new(MAC);
+        let config = StaticConfig { address: [10, 0, 0, 50], netmask: prefix_netmask(24), gateway: Some(SERVER) };
+        client.set_static(Some(config));
+        let now = without_server(&mut client);
+        // the fourth discover went unanswered too
+        assert_eq!(now, (4 + 8 + 16) * 1000);
+        assert_eq!(client.source(), Some(Source::Static));
+        assert_eq!(client.address(), Some([10, 0, 0, 50]));
+        assert_eq!(client.gateway(), Some(SERVER));
+        assert_eq!(client.state(), State::Selecting);
+
+        // a late server still wins
+        let offer = reply(&client, DHCP_OFFER);
+        client.on_dhcp(&offer, now);
+        let ack = reply(&client, DHCP_ACK);
+        client.on_dhcp(&ack, now);
+        assert_eq!(client.source(), Some(Source::Dhcp));
+        assert_eq!(client.address(), Some(OFFERED));
+    }
+
+    fn arp_frames(client: &mut DhcpClient) -> Vec<Vec<u8>> {
+        frames(client).into_iter().filter(|f| f[12..14] == [0x08, 0x06]).collect()
+    }
+
+    #[test]
+    fn link_local_fallback() {
+        let mut client = DhcpClient::new(MAC);
+        let mut now = without_server(&mut client);
+        assert_eq!(client.source(), Some(Source::LinkLocal));
+        let address = client.address().unwrap();
+        assert_eq!(&address[0..2], &[169, 254]);
+        assert!(address[2] >= 1 && address[2] <= 254);
+        assert_eq!(client.netmask(), Some([255, 255, 0, 0]));
+
+        now += 10 * 1000;
+        client.tick(now);
+        frames(&mut client);
+        client.tick(now);
+        assert!(arp_frames(&mut client).is_empty());
+        assert_eq!(client.address(), Some(address));
+    }
+
+    #[test]
+    fn link_local_probes_then_announces() {
+        let mut client = DhcpClient::new(MAC);
+        let mut now = 0;
+        let mut arp = Vec::new();
+        while now < 45 * 1000 {
+            client.tick(now);
+            arp.extend(arp_frames(&mut client));
+            now += 100;
+        }
+        assert_eq!(arp.len(), (PROBE_NUM + ANNOUNCE_NUM) as usize);
+        let address = client.address().unwrap();
+        for probe in &arp[..PROBE_NUM as usize] {
+            // sender 0.0.0.0, target the candidate
+            assert_eq!(&probe[28..32], &[0, 0, 0, 0]);
+            assert_eq!(&probe[38..42], &address);
+        }
+        for announcement in &arp[PROBE_NUM as usize..] {
+            assert_eq!(&announcement[28..32], &address);
+            assert_eq!(&announcement[38..42], &address);
+        }
+    }
+
+    #[test]
+    fn link_local_conflicts() {
+        let mut client = DhcpClient::new(MAC);
+        let mut now = 0;
+        // until the first probe is out
+        while client.link_local.state != (LinkLocalState::Probing { sent: 1 }) {
+            client.tick(now);
+            now += 100;
+        }
+        let first = client.link_local.address;
+        // our own probes don't count
+        client.on_arp(MAC, [0; 4], first, now);
+        assert_eq!(client.link_local.address, first);
+        client.on_arp(SERVER_MAC, first, [10, 0, 0, 1], now);
+        let second = client.link_local.address;
+        assert!(second != first);
+        assert_eq!(client.link_local.state, LinkLocalState::Probing { sent: 0 });
+
+        // claimed, defended once, then given up
+        while client.source().is_none() {
+            client.tick(now);
+            now += 100;
+        }
+        frames(&mut client);
+        client.on_arp(SERVER_MAC, second, [10, 0, 0, 1], now);
+        assert_eq!(client.address(), Some(second));
+        assert_eq!(arp_frames(&mut client).len(), 1);
+        client.on_arp(SERVER_MAC, second, [10, 0, 0, 1], now + 1000);
+        assert_eq!(client.address(), None);
+        assert!(client.link_local.address != second);
+    }
+
+    #[test]
+    fn netmasks() {
+        assert_eq!(prefix_netmask(24), [255, 255, 255, 0]);
+        assert_eq!(prefix_netmask(0), [0, 0, 0, 0]);
+        assert_eq!(prefix_netmask(32), [255; 4]);
+        assert_eq!(prefix_netmask(20), [255, 255, 240, 0]);
+        assert_eq!(netmask_prefix(&[255, 255, 240, 0]), 20);
+        assert_eq!(netmask_prefix(&[0; 4]), 0);
+        assert_eq!(netmask_prefix(&[255; 4]), 32);
+    }
+
+    #[test]
+    fn header_checksum() {
+        let mut client = DhcpClient::new(MAC);
+        client.tick(0);
+        let frame = client.next_frame().unwrap();
+        // summing a header including its checksum gives all ones
+        assert_eq!(ipv4_checksum(&frame[14..34]), 0);
+    }
+}
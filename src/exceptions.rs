@@ -0,0 +1,114 @@
+// Handlers for HardFault, MemManage, BusFault and UsageFault. The vector
+// table belongs to the board support crate, so `install` copies it to RAM,
+// replaces the fault entries and points the VTOR at the copy. Interrupt
+// handlers are added to the copy with `set_interrupt_handler`.
+
+use core::ptr;
+
+use fault::{self, ExceptionFrame, FaultReport, FaultStatus};
+use panic;
+
+const SCB_ICSR: *const u32 = 0xe000_ed04 as *const u32;
+const SCB_VTOR: *mut u32 = 0xe000_ed08 as *mut u32;
+const SCB_SHCSR: *mut u32 = 0xe000_ed24 as *mut u32;
+const SCB_CFSR: *const u32 = 0xe000_ed28 as *const u32;
+const SCB_HFSR: *const u32 = 0xe000_ed2c as *const u32;
+const SCB_MMFAR: *const u32 = 0xe000_ed34 as *const u32;
+const SCB_BFAR: *const u32 = 0xe000_ed38 as *const u32;
+// interrupt set-enable, one bit per interrupt, 32 per register
+const NVIC_ISER: *mut u32 = 0xe000_e100 as *mut u32;
+
+// without these the configurable faults all end up as HardFault
+const SHCSR_MEMFAULTENA: u32 = 1 << 16;
+const SHCSR_BUSFAULTENA: u32 = 1 << 17;
+const SHCSR_USGFAULTENA: u32 = 1 << 18;
+
+const ICSR_VECTACTIVE_MASK: u32 = 0x1ff;
+
+const HARD_FAULT: usize = 3;
+const MEM_MANAGE: usize = 4;
+const BUS_FAULT: usize = 5;
+const USAGE_FAULT: usize = 6;
+
+// the 16 system exceptions and the 98 interrupts of the STM32F746
+const VECTOR_COUNT: usize = 16 + 98;
+// the VTOR needs the table aligned to its size, rounded up to a power of two
+const VECTOR_ALIGN: usize = 512;
+
+// large enough to contain an aligned table wherever the linker puts it
+static mut VECTOR_RAM: [u32; VECTOR_COUNT + VECTOR_ALIGN / 4] = [0; VECTOR_COUNT + VECTOR_ALIGN / 4];
+// the aligned table in `VECTOR_RAM`, null until `install`
+static mut TABLE: *mut u32 = 0 as *mut u32;
+
+/// Installs the fault handlers, call it before anything can fault.
+pub fn install() {
+    unsafe {
+        let offset = {
+            let address = VECTOR_RAM.as_ptr() as usize;
+            ((VECTOR_ALIGN - address % VECTOR_ALIGN) % VECTOR_ALIGN) / 4
+        };
+        let table = &mut VECTOR_RAM[offset..offset + VECTOR_COUNT];
+
+        let current = ptr::read_volatile(SCB_VTOR) as *const u32;
+        for (index, entry) in table.iter_mut().enumerate() {
+            *entry = ptr::read_volatile(current.offset(index as isize));
+        }
+        for &index in &[HARD_FAULT, MEM_MANAGE, BUS_FAULT, USAGE_FAULT] {
+            // function pointers already have the thumb bit set
+            table[index] = fault_trampoline as usize as u32;
+        }
+
+        TABLE = table.as_mut_ptr();
+        ptr::write_volatile(SCB_VTOR, table.as_ptr() as u32);
+        asm!("dsb\n\tisb" :::: "volatile");
+
+        let shcsr = ptr::read_volatile(SCB_SHCSR);
+        ptr::write_volatile(SCB_SHCSR, shcsr | SHCSR_MEMFAULTENA | SHCSR_BUSFAULTENA | SHCSR_USGFAULTENA);
+    }
+}
+
+/// Makes `handler` the handler of interrupt `irq` (the position in the
+/// vector table minus 16) and enables the interrupt in the NVIC. Panics if
+/// `install` wasn't called.
+pub fn set_interrupt_handler(irq: usize, handler: extern "C" fn()) {
+    assert!(irq < VECTOR_COUNT - 16, "no interrupt {}", irq);
+    unsafe {
+        assert!(!TABLE.is_null(), "exceptions::install wasn't called");
+        ptr::write_volatile(TABLE.offset(16 + irq as isize), handler as usize as u32);
+        asm!("dsb" :::: "volatile");
+        ptr::write_volatile(NVIC_ISER.offset(irq as isize / 32), 1 << (irq % 32));
+    }
+}
+
+// bit 2 of the EXC_RETURN in lr tells which stack the registers were pushed
+// on, `handle_fault` gets a pointer to them
+#[naked]
+extern "C" fn fault_trampoline() {
+    unsafe {
+        asm!("tst lr, #4\n\t\
+              ite eq\n\t\
+              mrseq r0, msp\n\t\
+              mrsne r0, psp\n\t\
+              b handle_fault"
+             :::: "volatile");
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn handle_fault(frame: &ExceptionFrame) -> ! {
+    let report = unsafe {
+        FaultReport {
+            exception: ptr::read_volatile(SCB_ICSR) & ICSR_VECTACTIVE_MASK,
+            frame: *frame,
+            status: FaultStatus {
+                cfsr: ptr::read_volatile(SCB_CFSR),
+                hfsr: ptr::read_volatile(SCB_HFSR),
+                mmfar: ptr::read_volatile(SCB_MMFAR),
+                bfar: ptr::read_volatile(SCB_BFAR)
+            }
+        }
+    };
+
+    println_err!("{}", report);
+    panic::crash(fault::exception_name(report.exception), format_args!("{}", report));
+}
@@ -0,0 +1,218 @@
+// Decodes the Cortex-M7 fault status registers into text.
+
+use core::fmt;
+
+/// The registers the processor pushes onto the stack when it takes an
+/// exception.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32
+}
+
+/// The fault status and address registers of the system control block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultStatus {
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32
+}
+
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    Cfsr,
+    Hfsr
+}
+
+static REASONS: [(Register, u32, &'static str); 17] = [
+    // MemManage, CFSR bits 0 to 7
+    (Register::Cfsr, 0, "instruction access violation"),
+    (Register::Cfsr, 1, "data access violation"),
+    (Register::Cfsr, 3, "memory fault while unstacking"),
+    (Register::Cfsr, 4, "memory fault while stacking"),
+    (Register::Cfsr, 5, "memory fault during lazy FP state preservation"),
+    // BusFault, CFSR bits 8 to 15
+    (Register::Cfsr, 8, "instruction bus error"),
+    (Register::Cfsr, 9, "precise data bus error"),
+    (Register::Cfsr, 10, "imprecise data bus error"),
+    (Register::Cfsr, 11, "bus fault while unstacking"),
+    (Register::Cfsr, 12, "bus fault while stacking"),
+    (Register::Cfsr, 13, "bus fault during lazy FP state preservation"),
+    // UsageFault, CFSR bits 16 to 31
+    (Register::Cfsr, 16, "undefined instruction"),
+    (Register::Cfsr, 17, "invalid state (no thumb bit)"),
+    (Register::Cfsr, 18, "invalid PC load on exception return"),
+    (Register::Cfsr, 19, "no coprocessor (FPU disabled?)"),
+    (Register::Cfsr, 24, "unaligned access"),
+    (Register::Cfsr, 25, "division by zero"),
+];
+
+static HARD_FAULT_REASONS: [(Register, u32, &'static str); 3] = [
+    (Register::Hfsr, 1, "bus fault on vector table read"),
+    (Register::Hfsr, 30, "escalated from a configurable fault"),
+    (Register::Hfsr, 31, "debug event"),
+];
+
+impl FaultStatus {
+    /// The address of the MemManage fault, if the processor recorded one.
+    pub fn memory_address(&self) -> Option<u32> {
+        if self.cfsr & CFSR_MMARVALID != 0 { Some(self.mmfar) } else { None }
+    }
+
+    /// The address of a precise bus fault, if the processor recorded one.
+    pub fn bus_address(&self) -> Option<u32> {
+        if self.cfsr & CFSR_BFARVALID != 0 { Some(self.bfar) } else { None }
+    }
+
+    /// Every reason set in the status registers, hard fault reasons last.
+    pub fn reasons(&self) -> Reasons {
+        Reasons { status: *self, index: 0 }
+    }
+
+    fn is_set(&self, register: Register, bit: u32) -> bool {
+        let value = match register {
+            Register::Cfsr => self.cfsr,
+            Register::Hfsr => self.hfsr,
+        };
+        value & (1 << bit) != 0
+    }
+}
+
+pub struct Reasons {
+    status: FaultStatus,
+    index: usize
+}
+
+impl Iterator for Reasons {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        while self.index < REASONS.len() + HARD_FAULT_REASONS.len() {
+            let (register, bit, text) = if self.index < REASONS.len() {
+                REASONS[self.index]
+            } else {
+                HARD_FAULT_REASONS[self.index - REASONS.len()]
+            };
+            self.index += 1;
+            if self.status.is_set(register, bit) {
+                return Some(text);
+            }
+        }
+        None
+    }
+}
+
+/// The name of an exception by its number, as in the IPSR or the VECTACTIVE
+/// field of the ICSR.
+pub fn exception_name(number: u32) -> &'static str {
+    match number {
+        2 => "NMI",
+        3 => "HardFault",
+        4 => "MemManage",
+        5 => "BusFault",
+        6 => "UsageFault",
+        11 => "SVCall",
+        12 => "DebugMonitor",
+        14 => "PendSV",
+        15 => "SysTick",
+        n if n >= 16 => "interrupt",
+        _ => "reserved exception",
+    }
+}
+
+/// Everything known about a fault, formatted over several lines.
+pub struct FaultReport {
+    pub exception: u32,
+    pub frame: ExceptionFrame,
+    pub status: FaultStatus
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frame = &self.frame;
+        let status = &self.status;
+        writeln!(f, "{} at pc 0x{:08x}, lr 0x{:08x}", exception_name(self.exception), frame.pc, frame.lr)?;
+        writeln!(f, "r0 0x{:08x} r1 0x{:08x} r2 0x{:08x} r3 0x{:08x}", frame.r0, frame.r1, frame.r2, frame.r3)?;
+        writeln!(f, "r12 0x{:08x} xpsr 0x{:08x}", frame.r12, frame.xpsr)?;
+        writeln!(f, "CFSR 0x{:08x} HFSR 0x{:08x}", status.cfsr, status.hfsr)?;
+        for reason in status.reasons() {
+            writeln!(f, "{}", reason)?;
+        }
+        if let Some(address) = status.memory_address() {
+            writeln!(f, "memory fault address 0x{:08x}", address)?;
+        }
+        if let Some(address) = status.bus_address() {
+            writeln!(f, "bus fault address 0x{:08x}", address)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exception_name, ExceptionFrame, FaultReport, FaultStatus};
+
+    fn status(cfsr: u32, hfsr: u32) -> FaultStatus {
+        FaultStatus { cfsr: cfsr, hfsr: hfsr, mmfar: 0x1000, bfar: 0x6000_0000 }
+    }
+
+    #[test]
+    fn decodes_a_precise_bus_fault() {
+        let status = status(1 << 9 | 1 << 15, 0);
+        assert_eq!(status.reasons().collect::<Vec<_>>(), vec!["precise data bus error"]);
+        assert_eq!(status.bus_address(), Some(0x6000_0000));
+        assert_eq!(status.memory_address(), None);
+    }
+
+    #[test]
+    fn ignores_the_address_without_the_valid_bit() {
+        let status = status(1 << 1, 0);
+        assert_eq!(status.reasons().collect::<Vec<_>>(), vec!["data access violation"]);
+        assert_eq!(status.memory_address(), None);
+        assert_eq!(status.bus_address(), None);
+    }
+
+    #[test]
+    fn lists_hard_fault_reasons_last() {
+        let status = status(1 << 25 | 1 << 16, 1 << 30);
+        assert_eq!(status.reasons().collect::<Vec<_>>(),
+                   vec!["undefined instruction", "division by zero", "escalated from a configurable fault"]);
+    }
+
+    #[test]
+    fn nothing_set() {
+        assert_eq!(status(0, 0).reasons().count(), 0);
+    }
+
+    #[test]
+    fn names_exceptions() {
+        assert_eq!(exception_name(3), "HardFault");
+        assert_eq!(exception_name(5), "BusFault");
+        assert_eq!(exception_name(42), "interrupt");
+        assert_eq!(exception_name(7), "reserved exception");
+    }
+
+    #[test]
+    fn formats_a_report() {
+        let report = FaultReport {
+            exception: 3,
+            frame: ExceptionFrame { r0: 1, r1: 2, r2: 3, r3: 4, r12: 12, lr: 0x0800_0101, pc: 0x0800_0200, xpsr: 0x0100_0000 },
+            status: status(1 << 9 | 1 << 15, 1 << 30)
+        };
+        let text = format!("{}", report);
+        assert!(text.starts_with("HardFault at pc 0x08000200, lr 0x08000101\n"));
+        assert!(text.contains("precise data bus error\nescalated from a configurable fault\n"));
+        assert!(text.ends_with("bus fault address 0x60000000\n"));
+    }
+}
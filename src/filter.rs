@@ -0,0 +1,914 @@
+// Display filters, e.g. `proto == dns && ip.src == 10.0.0.5`, `port 53`,
+// `not arp`, `mac.dst == ff:ff:ff:ff:ff:ff` or `tcp.state == syn_sent`. Only
+// frames that match are counted and drawn.
+//
+//     expression := term (("||" | "or") term)*
+//     term       := factor (("&&" | "and") factor)*
+//     factor     := ("!" | "not") factor | "(" expression ")" | test
+//     test       := field [operator] value | protocol
+//
+// Without an operator a test compares with `==`. `mac`, `ip` (or `host`) and
+// `port` match either the source or the destination, `ip` takes IPv4 and IPv6
+// addresses. Tests on fields a frame doesn't have, like the ports of an ARP
+// frame, are false, `!=` included.
+
+use collections::{String, Vec};
+use collections::boxed::Box;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// The parts of a frame the filters look at, see `context::filter_fields`.
+#[derive(Debug, Clone, Default)]
+pub struct Fields {
+    /// The `ParseResultType::index`
+    pub protocol: usize,
+    pub len: usize,
+    pub mac_src: Option<[u8; 6]>,
+    pub mac_dst: Option<[u8; 6]>,
+    pub ipv4_src: Option<[u8; 4]>,
+    pub ipv4_dst: Option<[u8; 4]>,
+    pub ipv6_src: Option<[u8; 16]>,
+    pub ipv6_dst: Option<[u8; 16]>,
+    pub port_src: Option<u16>,
+    pub port_dst: Option<u16>,
+    /// The 802.1Q VLAN id
+    pub vlan: Option<u16>,
+    /// The `State::index` of the TCP connection the frame belongs to
+    pub tcp_state: Option<usize>,
+    /// The handshake round trip time of that connection in milliseconds
+    pub tcp_rtt: Option<u32>
+}
+
+/// Why an expression couldn't be compiled, `position` is the byte offset of
+/// the offending part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub position: usize,
+    pub kind: ErrorKind
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    Empty,
+    /// Something is missing at the end
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    /// A word or symbol where something else was expected
+    Unexpected(String),
+    /// Neither a field nor a protocol
+    UnknownName(String),
+    /// The value doesn't fit the field, e.g. `port == 70000`
+    BadValue(&'static str, String),
+    /// Only numbers can be ordered, e.g. `ip.src < 10.0.0.1` is wrong
+    BadOperator(&'static str, &'static str),
+    UnclosedParenthesis,
+    /// More than `MAX_NESTING` parentheses and `not`s inside each other
+    TooDeep
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: ", self.position + 1)?;
+        match self.kind {
+            ErrorKind::Empty => write!(f, "the filter is empty"),
+            ErrorKind::UnexpectedEnd => write!(f, "the filter ends too early"),
+            ErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            ErrorKind::Unexpected(ref text) => write!(f, "unexpected '{}'", text),
+            ErrorKind::UnknownName(ref name) => write!(f, "'{}' is neither a field nor a protocol", name),
+            ErrorKind::BadValue(field, ref value) => write!(f, "'{}' is not a valid value for {}", value, field),
+            ErrorKind::BadOperator(operator, field) => write!(f, "{} can't be used with {}", operator, field),
+            ErrorKind::UnclosedParenthesis => write!(f, "the parenthesis is never closed"),
+            ErrorKind::TooDeep => write!(f, "more than {} parentheses and nots inside each other", MAX_NESTING),
+        }
+    }
+}
+
+/// How deep parentheses and `not`s may nest, the parser recurses for each
+/// level and the stack is small.
+pub const MAX_NESTING: usize = 16;
+
+/// A compiled filter expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    expression: Expression
+}
+
+impl Filter {
+    /// Compiles `text`. `protocols` are the names of the frame types and
+    /// `tcp_states` the names of the connection states, by their index.
+    /// Both are matched ignoring case.
+    pub fn parse(text: &str, protocols: &[&str], tcp_states: &[&str]) -> Result<Filter, Error> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err(Error { position: 0, kind: ErrorKind::Empty });
+        }
+        let mut parser = Parser {
+            tokens: tokens,
+            index: 0,
+            end: text.len(),
+            depth: 0,
+            protocols: protocols,
+            tcp_states: tcp_states
+        };
+        let expression = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(Error { position: token.position, kind: ErrorKind::Unexpected(String::from(token.text)) });
+        }
+        Ok(Filter { expression: expression })
+    }
+
+    pub fn matches(&self, fields: &Fields) -> bool {
+        self.expression.matches(fields)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Test(Field, Operator, Value)
+}
+
+impl Expression {
+    fn matches(&self, fields: &Fields) -> bool {
+        match *self {
+            Expression::Or(ref a, ref b) => a.matches(fields) || b.matches(fields),
+            Expression::And(ref a, ref b) => a.matches(fields) && b.matches(fields),
+            Expression::Not(ref a) => !a.matches(fields),
+            Expression::Test(field, operator, ref value) => {
+                let mut present = false;
+                let mut equal = false;
+                let mut ordered = false;
+                for actual in field.values(fields).iter().filter_map(|v| v.as_ref()) {
+                    present = true;
+                    equal |= actual.matches(value);
+                    ordered |= match (actual, value) {
+                        (&Value::Number(a), &Value::Number(b)) => operator.accepts(a.cmp(&b)),
+                        _ => false,
+                    };
+                }
+                match operator {
+                    Operator::Equal => equal,
+                    // none of the addresses of `ip != ...` may match
+                    Operator::NotEqual => present && !equal,
+                    _ => ordered,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Protocol,
+    Len,
+    MacSrc,
+    MacDst,
+    Mac,
+    IpSrc,
+    IpDst,
+    Ip,
+    PortSrc,
+    PortDst,
+    Port,
+    Vlan,
+    TcpState,
+    TcpRtt
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Protocol,
+    TcpState,
+    Number,
+    Mac,
+    Ip
+}
+
+static FIELDS: [(&'static str, Field); 16] = [
+    ("proto", Field::Protocol),
+    ("protocol", Field::Protocol),
+    ("len", Field::Len),
+    ("mac.src", Field::MacSrc),
+    ("mac.dst", Field::MacDst),
+    ("mac", Field::Mac),
+    ("ip.src", Field::IpSrc),
+    ("ip.dst", Field::IpDst),
+    ("ip", Field::Ip),
+    ("host", Field::Ip),
+    ("port.src", Field::PortSrc),
+    ("port.dst", Field::PortDst),
+    ("port", Field::Port),
+    ("vlan", Field::Vlan),
+    ("tcp.state", Field::TcpState),
+    ("tcp.rtt", Field::TcpRtt),
+];
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().find(|&&(n, _)| eq_ignore_case(n, name)).map(|&(_, field)| field)
+    }
+
+    fn name(&self) -> &'static str {
+        FIELDS.iter().find(|&&(_, f)| f == *self).map(|&(n, _)| n).unwrap_or("?")
+    }
+
+    fn kind(&self) -> Kind {
+        match *self {
+            Field::Protocol => Kind::Protocol,
+            Field::TcpState => Kind::TcpState,
+            Field::Len | Field::PortSrc | Field::PortDst | Field::Port | Field::Vlan | Field::TcpRtt => Kind::Number,
+            Field::MacSrc | Field::MacDst | Field::Mac => Kind::Mac,
+            Field::IpSrc | Field::IpDst | Field::Ip => Kind::Ip,
+        }
+    }
+
+    // at most two, the source and the destination
+    fn values(&self, fields: &Fields) -> [Option<Value>; 2] {
+        let mac = |mac: Option<[u8; 6]>| mac.map(Value::Mac);
+        // a frame has either IPv4 or IPv6 addresses
+        let ip = |ipv4: Option<[u8; 4]>, ipv6: Option<[u8; 16]>| {
+            ipv4.map(|ip| Value::Ip(ip, 32)).or(ipv6.map(|ip| Value::Ipv6(ip, 128)))
+        };
+        let port = |port: Option<u16>| port.map(|port| Value::Number(port as u32));
+        match *self {
+            Field::Protocol => [Some(Value::Protocol(fields.protocol)), None],
+            Field::Len => [Some(Value::Number(fields.len as u32)), None],
+            Field::MacSrc => [mac(fields.mac_src), None],
+            Field::MacDst => [mac(fields.mac_dst), None],
+            Field::Mac => [mac(fields.mac_src), mac(fields.mac_dst)],
+            Field::IpSrc => [ip(fields.ipv4_src, fields.ipv6_src), None],
+            Field::IpDst => [ip(fields.ipv4_dst, fields.ipv6_dst), None],
+            Field::Ip => [ip(fields.ipv4_src, fields.ipv6_src), ip(fields.ipv4_dst, fields.ipv6_dst)],
+            Field::PortSrc => [port(fields.port_src), None],
+            Field::PortDst => [port(fields.port_dst), None],
+            Field::Port => [port(fields.port_src), port(fields.port_dst)],
+            Field::Vlan => [fields.vlan.map(|vlan| Value::Number(vlan as u32)), None],
+            Field::TcpState => [fields.tcp_state.map(Value::TcpState), None],
+            Field::TcpRtt => [fields.tcp_rtt.map(Value::Number), None],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+impl Operator {
+    fn from_symbol(symbol: &str) -> Option<Operator> {
+        match symbol {
+            "==" | "=" => Some(Operator::Equal),
+            "!=" => Some(Operator::NotEqual),
+            "<" => Some(Operator::Less),
+            "<=" => Some(Operator::LessOrEqual),
+            ">" => Some(Operator::Greater),
+            ">=" => Some(Operator::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn accepts(&self, ordering: Ordering) -> bool {
+        match *self {
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual => ordering != Ordering::Equal,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessOrEqual => ordering != Ordering::Greater,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Protocol(usize),
+    TcpState(usize),
+    Number(u32),
+    Mac([u8; 6]),
+    /// An address and the length of the prefix that has to match
+    Ip([u8; 4], u8),
+    Ipv6([u8; 16], u8)
+}
+
+impl Value {
+    // `self` is the value of the frame, `expected` the one in the filter
+    fn matches(&self, expected: &Value) -> bool {
+        match (*self, *expected) {
+            (Value::Ip(actual, _), Value::Ip(network, prefix)) => {
+                let mask = if prefix == 0 { 0 } else { !0u32 << (32 - prefix as u32) };
+                ip_to_u32(actual) & mask == ip_to_u32(network) & mask
+            }
+            (Value::Ipv6(actual, _), Value::Ipv6(network, prefix)) => {
+                let prefix = prefix as usize;
+                let (bytes, bits) = (prefix / 8, prefix % 8);
+                let mask = if bits == 0 { 0 } else { 0xffu8 << (8 - bits) };
+                actual[..bytes] == network[..bytes] &&
+                    (bits == 0 || actual[bytes] & mask == network[bytes] & mask)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    fn parse(kind: Kind, text: &str, protocols: &[&str], tcp_states: &[&str]) -> Option<Value> {
+        match kind {
+            Kind::Protocol => protocols.iter().position(|p| eq_ignore_case(p, text)).map(Value::Protocol),
+            Kind::TcpState => tcp_states.iter().position(|s| eq_ignore_case(s, text)).map(Value::TcpState),
+            Kind::Number => text.parse().ok().map(Value::Number),
+            Kind::Mac => parse_mac(text).map(Value::Mac),
+            Kind::Ip => match parse_ip(text) {
+                Some((ip, prefix)) => Some(Value::Ip(ip, prefix)),
+                None => parse_ipv6(text).map(|(ip, prefix)| Value::Ipv6(ip, prefix)),
+            },
+        }
+    }
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    fn lower(c: u8) -> u8 {
+        if c >= b'A' && c <= b'Z' { c + (b'a' - b'A') } else { c }
+    }
+    a.len() == b.len() && a.bytes().zip(b.bytes()).all(|(x, y)| lower(x) == lower(y))
+}
+
+fn ip_to_u32(ip: [u8; 4]) -> u32 {
+    (ip[0] as u32) << 24 | (ip[1] as u32) << 16 | (ip[2] as u32) << 8 | ip[3] as u32
+}
+
+fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0; 6];
+    let mut count = 0;
+    for part in text.split(|c| c == ':' || c == '-') {
+        if count == 6 || part.is_empty() || part.len() > 2 {
+            return None;
+        }
+        mac[count] = match u8::from_str_radix(part, 16) {
+            Ok(byte) => byte,
+            Err(_) => return None,
+        };
+        count += 1;
+    }
+    if count == 6 { Some(mac) } else { None }
+}
+
+/// An address with an optional prefix length, `10.0.0.0/8`. Without one
+/// the prefix length is 32.
+pub fn parse_ip(text: &str) -> Option<([u8; 4], u8)> {
+    let mut halves = text.splitn(2, '/');
+    let address = halves.next().unwrap_or("");
+    let prefix = match halves.next() {
+        Some(prefix) => match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= 32 => prefix,
+            _ => return None,
+        },
+        None => 32,
+    };
+    let mut ip = [0; 4];
+    let mut count = 0;
+    for part in address.split('.') {
+        if count == 4 {
+            return None;
+        }
+        ip[count] = match part.parse() {
+            Ok(byte) => byte,
+            Err(_) => return None,
+        };
+        count += 1;
+    }
+    if count == 4 { Some((ip, prefix)) } else { None }
+}
+
+/// An IPv6 address with an optional prefix length, `fe80::/10`. Without one
+/// the prefix length is 128. The last 32 bits may be written like an IPv4
+/// address, `::ffff:10.0.0.1`.
+pub fn parse_ipv6(text: &str) -> Option<([u8; 16], u8)> {
+    let mut halves = text.splitn(2, '/');
+    let address = halves.next().unwrap_or("");
+    let prefix = match halves.next() {
+        Some(prefix) => match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= 128 => prefix,
+            _ => return None,
+        },
+        None => 128,
+    };
+
+    // the groups before and after a `::`
+    let mut parts = address.splitn(2, "::");
+    let head = parts.next().unwrap_or("");
+    let tail = parts.next();
+    let mut groups = [0u16; 8];
+    let head_len = match parse_ipv6_groups(head, &mut groups, tail.is_none()) {
+        Some(len) => len,
+        None => return None,
+    };
+    match tail {
+        Some(tail) => {
+            let mut tail_groups = [0u16; 8];
+            let tail_len = match parse_ipv6_groups(tail, &mut tail_groups, true) {
+                Some(len) => len,
+                None => return None,
+            };
+            // `::` stands for at least one group of zeros
+            if head_len + tail_len > 7 {
+                return None;
+            }
+            groups[8 - tail_len..].copy_from_slice(&tail_groups[..tail_len]);
+        }
+        None if head_len != 8 => return None,
+        None => {}
+    }
+
+    let mut ip = [0; 16];
+    for (i, group) in groups.iter().enumerate() {
+        ip[2 * i] = (group >> 8) as u8;
+        ip[2 * i + 1] = *group as u8;
+    }
+    Some((ip, prefix))
+}
+
+// the colon separated groups of `text` into `groups`, returns how many there
+// were. The last one may be an IPv4 address if `last` is set.
+fn parse_ipv6_groups(text: &str, groups: &mut [u16; 8], last: bool) -> Option<usize> {
+    if text.is_empty() {
+        return Some(0);
+    }
+    let parts: Vec<&str> = text.split(':').collect();
+    let mut count = 0;
+    for (i, part) in parts.iter().enumerate() {
+        if last && i == parts.len() - 1 && part.contains('.') {
+            let ipv4 = match parse_ip(part) {
+                Some((ipv4, 32)) if !part.contains('/') => ipv4,
+                _ => return None,
+            };
+            if count + 2 > 8 {
+                return None;
+            }
+            groups[count] = (ipv4[0] as u16) << 8 | ipv4[1] as u16;
+            groups[count + 1] = (ipv4[2] as u16) << 8 | ipv4[3] as u16;
+            count += 2;
+            continue;
+        }
+        if count == 8 || part.is_empty() || part.len() > 4 {
+            return None;
+        }
+        groups[count] = match u16::from_str_radix(part, 16) {
+            Ok(group) => group,
+            Err(_) => return None,
+        };
+        count += 1;
+    }
+    Some(count)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Operator,
+    And,
+    Or,
+    Not,
+    Open,
+    Close
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    position: usize
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '.' || c == ':' || c == '/' || c == '_' || c == '-'
+}
+
+fn tokenize<'a>(text: &'a str) -> Result<Vec<Token<'a>>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let (kind, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('(', _) => (TokenKind::Open, 1),
+            (')', _) => (TokenKind::Close, 1),
+            ('&', Some('&')) => (TokenKind::And, 2),
+            ('|', Some('|')) => (TokenKind::Or, 2),
+            ('!', Some('=')) | ('=', Some('=')) | ('<', Some('=')) | ('>', Some('=')) => (TokenKind::Operator, 2),
+            ('!', _) => (TokenKind::Not, 1),
+            ('=', _) | ('<', _) | ('>', _) => (TokenKind::Operator, 1),
+            (c, _) if is_word_char(c) => {
+                let mut end = position + c.len_utf8();
+                loop {
+                    match chars.peek() {
+                        Some(&(index, c)) if is_word_char(c) => end = index + c.len_utf8(),
+                        _ => break,
+                    }
+                    chars.next();
+                }
+                tokens.push(Token { kind: TokenKind::Word, text: &text[position..end], position: position });
+                continue;
+            }
+            (c, _) => return Err(Error { position: position, kind: ErrorKind::UnexpectedChar(c) }),
+        };
+        if len == 2 {
+            chars.next();
+        }
+        tokens.push(Token { kind: kind, text: &text[position..position + len], position: position });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a, 'p> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+    // reported as the position when the expression ends too early
+    end: usize,
+    // the factors inside each other at the moment
+    depth: usize,
+    protocols: &'p [&'p str],
+    tcp_states: &'p [&'p str]
+}
+
+impl<'a, 'p> Parser<'a, 'p> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.index).cloned()
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        match self.peek() {
+            Some(token) => {
+                self.index += 1;
+                Ok(token)
+            }
+            None => Err(Error { position: self.end, kind: ErrorKind::UnexpectedEnd }),
+        }
+    }
+
+    // the symbol or the word, `and`, `or` and `not` can be written either way
+    fn next_is(&mut self, kind: TokenKind, word: &str) -> bool {
+        match self.peek() {
+            Some(token) if token.kind == kind || (token.kind == TokenKind::Word && eq_ignore_case(token.text, word)) => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, Error> {
+        let mut left = self.term()?;
+        while self.next_is(TokenKind::Or, "or") {
+            let right = self.term()?;
+            left = Expression::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expression, Error> {
+        let mut left = self.factor()?;
+        while self.next_is(TokenKind::And, "and") {
+            let right = self.factor()?;
+            left = Expression::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<Expression, Error> {
+        if self.depth == MAX_NESTING {
+            let position = self.peek().map(|token| token.position).unwrap_or(self.end);
+            return Err(Error { position: position, kind: ErrorKind::TooDeep });
+        }
+        self.depth += 1;
+        let factor = self.nested_factor();
+        self.depth -= 1;
+        factor
+    }
+
+    fn nested_factor(&mut self) -> Result<Expression, Error> {
+        if self.next_is(TokenKind::Not, "not") {
+            return Ok(Expression::Not(Box::new(self.factor()?)));
+        }
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Open => {
+                let expression = self.expression()?;
+                match self.peek() {
+                    Some(ref close) if close.kind == TokenKind::Close => {
+                        self.index += 1;
+                        Ok(expression)
+                    }
+                    _ => Err(Error { position: token.position, kind: ErrorKind::UnclosedParenthesis }),
+                }
+            }
+            TokenKind::Word => self.test(token),
+            _ => Err(Error { position: token.position, kind: ErrorKind::Unexpected(String::from(token.text)) }),
+        }
+    }
+
+    fn test(&mut self, name: Token<'a>) -> Result<Expression, Error> {
+        let field = match Field::from_name(name.text) {
+            Some(field) => field,
+            None => {
+                // a protocol on its own, `arp` is `proto == arp`
+                return match Value::parse(Kind::Protocol, name.text, self.protocols, self.tcp_states) {
+                    Some(value) => Ok(Expression::Test(Field::Protocol, Operator::Equal, value)),
+                    None => Err(Error { position: name.position, kind: ErrorKind::UnknownName(String::from(name.text)) }),
+                };
+            }
+        };
+
+        let mut operator = Operator::Equal;
+        if let Some(token) = self.peek() {
+            if token.kind == TokenKind::Operator {
+                self.index += 1;
+                // the tokenizer only makes operator tokens from known symbols
+                operator = Operator::from_symbol(token.text).unwrap_or(Operator::Equal);
+                let ordered = operator != Operator::Equal && operator != Operator::NotEqual;
+                if ordered && field.kind() != Kind::Number {
+                    return Err(Error { position: token.position, kind: ErrorKind::BadOperator(operator.symbol(), field.name()) });
+                }
+            }
+        }
+
+        let value = self.next()?;
+        let keyword = ["and", "or", "not"].iter().any(|k| eq_ignore_case(k, value.text));
+        if value.kind != TokenKind::Word || keyword {
+            return Err(Error { position: value.position, kind: ErrorKind::Unexpected(String::from(value.text)) });
+        }
+        match Value::parse(field.kind(), value.text, self.protocols, self.tcp_states) {
+            Some(Value::Number(n)) if field.kind() == Kind::Number && field != Field::Len && field != Field::TcpRtt && n > 0xffff => {
+                Err(Error { position: value.position, kind: ErrorKind::BadValue(field.name(), String::from(value.text)) })
+            }
+            Some(parsed) => Ok(Expression::Test(field, operator, parsed)),
+            None => Err(Error { position: value.position, kind: ErrorKind::BadValue(field.name(), String::from(value.text)) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind, Fields, Filter, MAX_NESTING};
+
+    static PROTOCOLS: [&'static str; 8] = ["Unknown", "Malformed", "ARP", "DHCP", "ICMP", "DNS", "TCP", "HTTP"];
+    static TCP_STATES: [&'static str; 6] = ["syn_sent", "syn_received", "established", "closing", "closed", "reset"];
+    const SYN_SENT: usize = 0;
+    const ESTABLISHED: usize = 2;
+    const ARP: usize = 2;
+    const DNS: usize = 5;
+    const TCP: usize = 6;
+
+    fn filter(text: &str) -> Filter {
+        match Filter::parse(text, &PROTOCOLS, &TCP_STATES) {
+            Ok(filter) => filter,
+            Err(e) => panic!("{}: {}", text, e),
+        }
+    }
+
+    fn error(text: &str) -> Error {
+        Filter::parse(text, &PROTOCOLS, &TCP_STATES).unwrap_err()
+    }
+
+    fn dns_query() -> Fields {
+        Fields {
+            protocol: DNS,
+            len: 74,
+            mac_src: Some([0x02, 0, 0, 0, 0, 0x05]),
+            mac_dst: Some([0x02, 0, 0, 0, 0, 0x01]),
+            ipv4_src: Some([10, 0, 0, 5]),
+            ipv4_dst: Some([10, 0, 0, 1]),
+            port_src: Some(40000),
+            port_dst: Some(53),
+            ..Fields::default()
+        }
+    }
+
+    // fe80::5 asking for the address of fe80::1
+    fn neighbor_solicitation() -> Fields {
+        let mut src = [0; 16];
+        src[0] = 0xfe;
+        src[1] = 0x80;
+        let mut dst = src;
+        src[15] = 5;
+        dst[15] = 1;
+        Fields {
+            protocol: ARP,
+            len: 86,
+            mac_src: Some([0x02, 0, 0, 0, 0, 0x05]),
+            mac_dst: Some([0x33, 0x33, 0xff, 0, 0, 0x01]),
+            ipv6_src: Some(src),
+            ipv6_dst: Some(dst),
+            ..Fields::default()
+        }
+    }
+
+    fn arp_broadcast() -> Fields {
+        Fields {
+            protocol: ARP,
+            len: 60,
+            mac_src: Some([0x02, 0, 0, 0, 0, 0x07]),
+            mac_dst: Some([0xff; 6]),
+            ..Fields::default()
+        }
+    }
+
+    #[test]
+    fn the_examples() {
+        assert!(filter("proto == dns && ip.src == 10.0.0.5").matches(&dns_query()));
+        assert!(!filter("proto == dns && ip.src == 10.0.0.6").matches(&dns_query()));
+        assert!(filter("port 53").matches(&dns_query()));
+        assert!(!filter("port 53").matches(&arp_broadcast()));
+        assert!(filter("not arp").matches(&dns_query()));
+        assert!(!filter("not arp").matches(&arp_broadcast()));
+        assert!(filter("mac.dst == ff:ff:ff:ff:ff:ff").matches(&arp_broadcast()));
+        assert!(!filter("mac.dst == ff:ff:ff:ff:ff:ff").matches(&dns_query()));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // arp || (dns && port 80), not (arp || dns) && port 80
+        let f = filter("arp or dns and port 80");
+        assert!(f.matches(&arp_broadcast()));
+        assert!(!f.matches(&dns_query()));
+        assert!(!filter("(arp or dns) and port 80").matches(&arp_broadcast()));
+    }
+
+    #[test]
+    fn not_and_parentheses() {
+        assert!(filter("!(tcp || arp)").matches(&dns_query()));
+        assert!(filter("not not dns").matches(&dns_query()));
+        assert!(!filter("! dns").matches(&dns_query()));
+    }
+
+    #[test]
+    fn either_direction_fields() {
+        assert!(filter("ip 10.0.0.1").matches(&dns_query()));
+        assert!(filter("host 10.0.0.5").matches(&dns_query()));
+        assert!(filter("mac == 02:00:00:00:00:01").matches(&dns_query()));
+        // neither address may match
+        assert!(!filter("ip != 10.0.0.1").matches(&dns_query()));
+        assert!(filter("ip != 10.0.0.9").matches(&dns_query()));
+    }
+
+    #[test]
+    fn missing_fields_never_match() {
+        assert!(!filter("ip.src == 10.0.0.5").matches(&arp_broadcast()));
+        assert!(!filter("ip.src != 10.0.0.5").matches(&arp_broadcast()));
+        assert!(!filter("port < 100").matches(&arp_broadcast()));
+    }
+
+    #[test]
+    fn numbers_compare() {
+        assert!(filter("port.dst <= 53").matches(&dns_query()));
+        assert!(!filter("port.dst < 53").matches(&dns_query()));
+        assert!(filter("port.src > 1023").matches(&dns_query()));
+        assert!(filter("len >= 74 && len < 100").matches(&dns_query()));
+        assert!(!filter("len > 74").matches(&dns_query()));
+    }
+
+    #[test]
+    fn networks() {
+        assert!(filter("ip.src == 10.0.0.0/24").matches(&dns_query()));
+        assert!(filter("ip.src == 10.1.2.3/8").matches(&dns_query()));
+        assert!(!filter("ip.src == 192.168.0.0/16").matches(&dns_query()));
+        assert!(filter("ip.src == 0.0.0.0/0").matches(&dns_query()));
+    }
+
+    #[test]
+    fn ipv6_addresses() {
+        let ns = neighbor_solicitation();
+        assert!(filter("ip.src == fe80::5").matches(&ns));
+        assert!(filter("ip.src == FE80:0:0:0:0:0:0:5").matches(&ns));
+        assert!(!filter("ip.src == fe80::1").matches(&ns));
+        assert!(filter("ip fe80::1 and host fe80::5").matches(&ns));
+        assert!(filter("ip.dst == fe80::/10").matches(&ns));
+        assert!(filter("ip.dst == fe80::ff/120").matches(&ns));
+        assert!(!filter("ip.dst == fe80::ff/121").matches(&ns));
+        assert!(filter("ip.dst == ::/0").matches(&ns));
+        assert!(!filter("ip != fe80::1").matches(&ns));
+        assert!(filter("ip != fe80::9").matches(&ns));
+    }
+
+    #[test]
+    fn address_families_dont_mix() {
+        let ns = neighbor_solicitation();
+        assert!(!filter("ip.src == 0.0.0.5").matches(&ns));
+        assert!(!filter("ip.src == 0.0.0.0/0").matches(&ns));
+        assert!(!filter("ip.src == ::/0").matches(&dns_query()));
+        assert!(!filter("ip == ::ffff:10.0.0.5").matches(&dns_query()));
+        assert!(!filter("ip.src == fe80::5").matches(&arp_broadcast()));
+    }
+
+    #[test]
+    fn ipv6_literals() {
+        use super::parse_ipv6;
+        let loopback = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(parse_ipv6("::1"), Some((loopback, 128)));
+        assert_eq!(parse_ipv6("0:0:0:0:0:0:0:1/64"), Some((loopback, 64)));
+        assert_eq!(parse_ipv6("::"), Some(([0; 16], 128)));
+        assert_eq!(parse_ipv6("2001:db8::"), Some(([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 128)));
+        assert_eq!(parse_ipv6("::ffff:192.168.7.1"),
+                   Some(([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 168, 7, 1], 128)));
+        assert_eq!(parse_ipv6("1:2:3:4:5:6:7:8").map(|(ip, _)| ip[15]), Some(8));
+        assert_eq!(parse_ipv6("1:2:3:4:5:6:7"), None);
+        assert_eq!(parse_ipv6("1:2:3:4:5:6:7:8:9"), None);
+        assert_eq!(parse_ipv6("1:2:3:4::5:6:7:8"), None);
+        assert_eq!(parse_ipv6("1::2::3"), None);
+        assert_eq!(parse_ipv6(":1"), None);
+        assert_eq!(parse_ipv6("12345::"), None);
+        assert_eq!(parse_ipv6("::g"), None);
+        assert_eq!(parse_ipv6("::1/129"), None);
+        assert_eq!(parse_ipv6("1.2.3.4::"), None);
+    }
+
+    #[test]
+    fn case_and_spacing_dont_matter() {
+        assert!(filter("PROTO==DNS&&Port.Dst==53").matches(&dns_query()));
+        assert!(filter("  ( dns )  ").matches(&dns_query()));
+        assert!(filter("mac.src = 02-00-00-00-00-05").matches(&dns_query()));
+        assert!(filter("proto tcp").matches(&Fields { protocol: TCP, ..Fields::default() }));
+    }
+
+    #[test]
+    fn vlans() {
+        let tagged = Fields { vlan: Some(42), ..dns_query() };
+        assert!(filter("vlan 42").matches(&tagged));
+        assert!(filter("vlan >= 10 && dns").matches(&tagged));
+        assert!(!filter("vlan != 42").matches(&tagged));
+        // untagged frames have no VLAN, not VLAN 0
+        assert!(!filter("vlan == 0").matches(&dns_query()));
+        assert!(!filter("vlan != 42").matches(&dns_query()));
+        assert!(filter("not vlan 42").matches(&dns_query()));
+    }
+
+    #[test]
+    fn tcp_connections() {
+        let http = |state, rtt| Fields { protocol: TCP, port_src: Some(40000), port_dst: Some(80),
+                                         tcp_state: Some(state), tcp_rtt: rtt, ..Fields::default() };
+        assert!(filter("tcp.state == syn_sent").matches(&http(SYN_SENT, None)));
+        assert!(filter("tcp.state != SYN_SENT").matches(&http(ESTABLISHED, Some(12))));
+        assert!(filter("tcp.rtt > 10 and port 80").matches(&http(ESTABLISHED, Some(12))));
+        assert!(!filter("tcp.rtt > 10").matches(&http(ESTABLISHED, Some(8))));
+        // no handshake seen, no round trip time
+        assert!(!filter("tcp.rtt < 100").matches(&http(ESTABLISHED, None)));
+        assert!(!filter("tcp.state == established").matches(&dns_query()));
+        assert_eq!(error("tcp.state == open"), Error { position: 13, kind: ErrorKind::BadValue("tcp.state", "open".to_string()) });
+        assert_eq!(error("tcp.state > closing"), Error { position: 10, kind: ErrorKind::BadOperator(">", "tcp.state") });
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(""), Error { position: 0, kind: ErrorKind::Empty });
+        assert_eq!(error("   "), Error { position: 0, kind: ErrorKind::Empty });
+        assert_eq!(error("dns &&"), Error { position: 6, kind: ErrorKind::UnexpectedEnd });
+        assert_eq!(error("port =="), Error { position: 7, kind: ErrorKind::UnexpectedEnd });
+        assert_eq!(error("dns # x"), Error { position: 4, kind: ErrorKind::UnexpectedChar('#') });
+        assert_eq!(error("dsn"), Error { position: 0, kind: ErrorKind::UnknownName("dsn".to_string()) });
+        assert_eq!(error("dns arp"), Error { position: 4, kind: ErrorKind::Unexpected("arp".to_string()) });
+        assert_eq!(error("(dns || arp"), Error { position: 0, kind: ErrorKind::UnclosedParenthesis });
+        assert_eq!(error("dns )"), Error { position: 4, kind: ErrorKind::Unexpected(")".to_string()) });
+        assert_eq!(error("port and dns"), Error { position: 5, kind: ErrorKind::Unexpected("and".to_string()) });
+        assert_eq!(error("port 70000"), Error { position: 5, kind: ErrorKind::BadValue("port", "70000".to_string()) });
+        assert_eq!(error("ip.src == 10.0.0"), Error { position: 10, kind: ErrorKind::BadValue("ip.src", "10.0.0".to_string()) });
+        assert_eq!(error("ip == 10.0.0.1/33"), Error { position: 6, kind: ErrorKind::BadValue("ip", "10.0.0.1/33".to_string()) });
+        assert_eq!(error("mac.dst == ff:ff"), Error { position: 11, kind: ErrorKind::BadValue("mac.dst", "ff:ff".to_string()) });
+        assert_eq!(error("proto == ftp"), Error { position: 9, kind: ErrorKind::BadValue("proto", "ftp".to_string()) });
+        assert_eq!(error("ip.src < 10.0.0.1"), Error { position: 7, kind: ErrorKind::BadOperator("<", "ip.src") });
+        assert_eq!(error("ip == fe80::1::2"), Error { position: 6, kind: ErrorKind::BadValue("ip", "fe80::1::2".to_string()) });
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| format!("{}dns{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(filter(&nested(MAX_NESTING - 1)).matches(&dns_query()));
+        assert_eq!(error(&nested(MAX_NESTING)), Error { position: MAX_NESTING, kind: ErrorKind::TooDeep });
+        assert_eq!(error(&"(".repeat(10000)), Error { position: MAX_NESTING, kind: ErrorKind::TooDeep });
+        assert!(filter(&format!("{}dns", "not ".repeat(MAX_NESTING - 2))).matches(&dns_query()));
+        assert_eq!(error(&"!".repeat(MAX_NESTING + 1)), Error { position: MAX_NESTING, kind: ErrorKind::TooDeep });
+        // side by side is fine
+        let side_by_side: Vec<String> = (0..100).map(|_| nested(4)).collect();
+        assert!(filter(&side_by_side.join(" or ")).matches(&dns_query()));
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(format!("{}", error("dsn")), "column 1: 'dsn' is neither a field nor a protocol");
+        assert_eq!(format!("{}", error("port 70000")), "column 6: '70000' is not a valid value for port");
+    }
+}
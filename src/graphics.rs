@@ -6,15 +6,26 @@ use collections::{vec, Vec};
 use board::rcc::Rcc;
 use board::ltdc::Ltdc;
 use embedded::interfaces::gpio::Gpio;
-use alloc::rc::{self, Rc};
-use collections::boxed::{self, Box};
+use core::cmp;
+use font::{self, Font};
 
-enum Button {
+pub const WIDTH: u16 = 480;
+pub const HEIGHT: u16 = 272;
+
+pub enum Button {
     ColorSquareButton {
         x: u16,
         y: u16,
         len: u16,
         color: u16
+    },
+    LabelButton {
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        color: u16,
+        label: &'static str
     }
 }
 /*
@@ -30,12 +41,22 @@ impl Button {
     pub fn touched_inside(&self, touch_x: u16, touch_y: u16) -> bool {
         match self {
             &Button::ColorSquareButton {x, y, len, color} => (x <= touch_x && touch_x <= (x + len) && y <= touch_y && touch_y <= (y + len)),
+            &Button::LabelButton {x, y, width, height, ..} => (x <= touch_x && touch_x < (x + width) && y <= touch_y && touch_y < (y + height)),
         }
     }
 
-    pub fn draw(&self, lcd: &mut Lcd) {
+    pub fn draw(&self, graphics: &mut Graphics) {
         match self {
-            &Button::ColorSquareButton {x, y, len, color} => Graphics::draw_square_filled(lcd, x, y, len, color),
+            &Button::ColorSquareButton {x, y, len, color} => Graphics::draw_square_filled(&mut graphics.lcd, x, y, len, color),
+            &Button::LabelButton {x, y, width, height, color, label} => {
+                Graphics::draw_rect_filled(&mut graphics.lcd, x, y, width, height, color);
+                // center the label, it is cut off if the button is too small
+                let font = graphics.font();
+                let text_width = label.len() as u16 * font.char_width();
+                let text_x = x + width.saturating_sub(text_width) / 2;
+                let text_y = y + height.saturating_sub(font.line_height()) / 2;
+                graphics.draw_text_in(text_x, text_y, x + width - text_x, y + height - text_y, label, 0x0, color);
+            }
         }
     }
 
@@ -43,8 +64,7 @@ impl Button {
 
 pub struct Graphics {
     pub lcd: Lcd,
-    buttons: Vec<Rc<Button>>,
-    touch_color: u16
+    font: Font
 }
 
 impl Graphics {
@@ -52,10 +72,9 @@ impl Graphics {
     pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, mut gpio: &mut Gpio, i2c_3: &mut I2C) -> Self {
         let mut graphics = Graphics {
             lcd: lcd::init(ltdc, rcc, &mut gpio),
-            buttons: Vec::new(),
-            touch_color: 0xffff
+            font: font::SMALL
         };
-        touch::check_family_id(i2c_3).unwrap();
+        touch::check_family_id(i2c_3).unwrap_or_else(|e| fatal!("no touch controller: {:?}", e));
         graphics
     }
 
@@ -64,68 +83,97 @@ impl Graphics {
         self.lcd.set_background_color(Color::from_hex(0x0));
     }
 
-    pub fn prepare(&mut self) {
-        self.clear_screen();
-
-        self.touch_color = 0xffff;
+    pub fn draw_square(lcd: &mut Lcd, x: u16, y: u16, len: u16, color: u16) {
 
-        let b1 = Rc::new(Button::ColorSquareButton {x: 10, y: 10,  len: 50, color: self.touch_color});
-        let b2 = Rc::new(Button::ColorSquareButton {x: 10, y: 70,  len: 50, color: 0xff00});
-        let b3 = Rc::new(Button::ColorSquareButton {x: 10, y: 130, len: 50, color: 0xaacc});
-        let b4 = Rc::new(Button::ColorSquareButton {x: 10, y: 190, len: 50, color: 0xccaa});
-
-        self.buttons.push(b1.clone());
-        self.buttons.push(b2.clone());
-        self.buttons.push(b3.clone());
-        self.buttons.push(b4.clone());
-
-        for button in self.buttons.iter() {
-            button.draw(&mut self.lcd);
+        for i in x..(x+len) {
+            lcd.print_point_color_at(i, y, color);
+            lcd.print_point_color_at(i, y + len - 1, color);
         }
 
+        for i in y..(y+len) {
+            lcd.print_point_color_at(x, i, color);
+            lcd.print_point_color_at(x + len - 1, i, color);
+        }
     }
 
-    pub fn tick(&mut self, i2c_3: &mut I2C) {
-
-        for touch in &touch::touches(i2c_3).unwrap() {
-            let mut color_changed = false;
-
-            // check if one of the color buttons was touched
-            for button in self.buttons.iter() {
-                match **button {
-                    Button::ColorSquareButton {x, y, len, color} => {
-                        if !color_changed && button.touched_inside(touch.x, touch.y) {
-                            self.touch_color = color;
-                            color_changed = true;
-                        }
-                    }
-                }
-            }
+    pub fn draw_square_filled(lcd: &mut Lcd, x: u16, y: u16, len: u16, color: u16) {
+        Self::draw_rect_filled(lcd, x, y, len, len, color);
+    }
 
-            // draw a point if this touch didn't touch a color button
-            if !color_changed {
-                self.lcd.print_point_color_at(touch.x, touch.y, self.touch_color);
+    pub fn draw_rect_filled(lcd: &mut Lcd, x: u16, y: u16, width: u16, height: u16, color: u16) {
+        for i in x..(x+width) {
+            for j in y..(y+height) {
+                lcd.print_point_color_at(i, j, color);
             }
         }
     }
 
-    pub fn draw_square(&mut self, x: u16, y: u16, len: u16, color: u16) {
+    /// Sets the font used by `draw_text` and `draw_text_in`.
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
 
-        for i in x..(x+len) {
-            self.lcd.print_point_color_at(i, y, color);
-            self.lcd.print_point_color_at(i, y + len - 1, color);
+    pub fn font(&self) -> Font {
+        self.font
+    }
+
+    /// Draws `text` starting at (`x`, `y`), wrapping at the right edge of the
+    /// screen. Returns the position after the last character.
+    pub fn draw_text(&mut self, x: u16, y: u16, text: &str, fg: u16, bg: u16) -> (u16, u16) {
+        if x >= WIDTH || y >= HEIGHT {
+            return (x, y);
         }
+        self.draw_text_in(x, y, WIDTH - x, HEIGHT - y, text, fg, bg)
+    }
 
-        for i in y..(y+len) {
-            self.lcd.print_point_color_at(x, i, color);
-            self.lcd.print_point_color_at(x + len - 1, i, color);
+    /// Draws `text` into the given box. Lines are wrapped at the right edge
+    /// of the box (and on '\n'), everything below the box is clipped.
+    /// Returns the position after the last character.
+    pub fn draw_text_in(&mut self, x: u16, y: u16, width: u16, height: u16, text: &str, fg: u16, bg: u16) -> (u16, u16) {
+        let font = self.font;
+        let right = cmp::min(x.saturating_add(width), WIDTH);
+        let bottom = cmp::min(y.saturating_add(height), HEIGHT);
+
+        let mut cursor_x = x;
+        let mut cursor_y = y;
+        for c in text.chars() {
+            // always put at least one character on a line, even in narrow boxes
+            let line_full = cursor_x > x && cursor_x + font.char_width() > right;
+            if c == '\n' || line_full {
+                cursor_x = x;
+                cursor_y += font.line_height();
+                if c == '\n' {
+                    continue;
+                }
+            }
+            if cursor_y >= bottom {
+                break;
+            }
+            Self::draw_char(&mut self.lcd, font, cursor_x, cursor_y, c, fg, bg, right, bottom);
+            cursor_x += font.char_width();
         }
+        (cursor_x, cursor_y)
     }
 
-    pub fn draw_square_filled(lcd: &mut Lcd, x: u16, y: u16, len: u16, color: u16) {
-        for i in x..(x+len) {
-            for j in y..(y+len) {
-                lcd.print_point_color_at(i, j, color);
+    // draws one character cell (glyph plus spacing column), without touching
+    // any pixel right of `right` or below `bottom`
+    fn draw_char(lcd: &mut Lcd, font: Font, x: u16, y: u16, c: char, fg: u16, bg: u16, right: u16, bottom: u16) {
+        let columns = font::glyph(c);
+        let scale = font.scale();
+
+        for column in 0..(font::GLYPH_WIDTH + 1) {
+            let bits = columns.get(column as usize).cloned().unwrap_or(0);
+            for row in 0..font::GLYPH_HEIGHT {
+                let color = if (bits >> row) & 1 == 1 { fg } else { bg };
+                for dx in 0..scale {
+                    for dy in 0..scale {
+                        let px = x + column * scale + dx;
+                        let py = y + row * scale + dy;
+                        if px < right && py < bottom {
+                            lcd.print_point_color_at(px, py, color);
+                        }
+                    }
+                }
             }
         }
     }
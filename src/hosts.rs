@@ -0,0 +1,370 @@
+use collections::string::String;
+use collections::vec::Vec;
+
+use classify::{DnsRecordData, ParseResult, ParseResultType};
+
+// the table lives on the small heap, the least recently seen host is
+// dropped when it is full
+const MAX_HOSTS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub mac: [u8; 6],
+    pub ipv4: Option<[u8; 4]>,
+    pub first_seen: usize,
+    pub last_seen: usize,
+    /// Frames sent or received by this host
+    pub frames: u32,
+    /// One bit per `ParseResultType::index` seen in those frames
+    pub protocols: u32,
+    /// Learned from DNS answers or DHCP requests
+    pub name: Option<String>
+}
+
+impl Host {
+    pub fn used_protocol(&self, type_index: usize) -> bool {
+        self.protocols & (1 << type_index) != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    LastSeen,
+    FirstSeen,
+    Frames,
+    Address
+}
+
+impl SortOrder {
+    pub fn next(&self) -> SortOrder {
+        match *self {
+            SortOrder::LastSeen => SortOrder::FirstSeen,
+            SortOrder::FirstSeen => SortOrder::Frames,
+            SortOrder::Frames => SortOrder::Address,
+            SortOrder::Address => SortOrder::LastSeen,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SortOrder::LastSeen => "last seen",
+            SortOrder::FirstSeen => "first seen",
+            SortOrder::Frames => "frames",
+            SortOrder::Address => "address",
+        }
+    }
+}
+
+/// Every MAC/IPv4 address pair seen on the network.
+pub struct HostTable {
+    hosts: Vec<Host>,
+    /// Hosts not seen for this many ticks are removed, `None` keeps them forever
+    timeout: Option<usize>,
+    order: SortOrder
+}
+
+impl HostTable {
+    pub fn new(timeout: Option<usize>) -> Self {
+        HostTable {
+            hosts: Vec::with_capacity(MAX_HOSTS),
+            timeout: timeout,
+            order: SortOrder::LastSeen
+        }
+    }
+
+    /// The hosts in the sort order. Only sorted here, the order changes with
+    /// every frame but is only seen once a second.
+    pub fn sorted(&self) -> Vec<&Host> {
+        let mut hosts: Vec<&Host> = self.hosts.iter().collect();
+        match self.order {
+            // most recent first
+            SortOrder::LastSeen => hosts.sort_by(|a, b| b.last_seen.cmp(&a.last_seen)),
+            SortOrder::FirstSeen => hosts.sort_by(|a, b| b.first_seen.cmp(&a.first_seen)),
+            SortOrder::Frames => hosts.sort_by(|a, b| b.frames.cmp(&a.frames)),
+            SortOrder::Address => hosts.sort_by(|a, b| {
+                a.ipv4.cmp(&b.ipv4).then_with(|| a.mac.cmp(&b.mac))
+            }),
+        }
+        hosts
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn timeout(&self) -> Option<usize> {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<usize>) {
+        self.timeout = timeout;
+    }
+
+    pub fn order(&self) -> SortOrder {
+        self.order
+    }
+
+    pub fn set_order(&mut self, order: SortOrder) {
+        self.order = order;
+    }
+
+    pub fn on_packet(&mut self, parse_result: &ParseResult, ticks: usize) {
+        let type_index = parse_result.pkt_type.index();
+
+        if let Some(mac) = parse_result.mac_src {
+            self.observe(mac, parse_result.ipv4_src, type_index, ticks);
+        }
+        if let Some(mac) = parse_result.mac_dst {
+            // broadcast and multicast addresses are no hosts
+            if mac[0] & 0x01 == 0 {
+                self.observe(mac, parse_result.ipv4_dst, type_index, ticks);
+            }
+        }
+
+        match parse_result.pkt_type {
+            ParseResultType::DNS => {
+                if let Some(ref dns) = parse_result.dns {
+                    for answer in dns.answers.iter() {
+                        if let DnsRecordData::A(ip) = answer.data {
+                            self.learn_name(|host| host.ipv4 == Some(ip), &answer.name);
+                        }
+                    }
+                }
+            }
+            ParseResultType::DHCP => {
+                if let (Some(mac), Some(name)) = (parse_result.mac_src, parse_result.hostname.as_ref()) {
+                    self.learn_name(|host| host.mac == mac, name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes the hosts that timed out.
+    pub fn expire(&mut self, ticks: usize) {
+        if let Some(timeout) = self.timeout {
+            self.hosts.retain(|host| ticks.wrapping_sub(host.last_seen) < timeout);
+        }
+    }
+
+    fn observe(&mut self, mac: [u8; 6], ipv4: Option<[u8; 4]>, type_index: usize, ticks: usize) {
+        // the IP address of a DHCP client is 0.0.0.0 until it got one
+        let ipv4 = match ipv4 {
+            Some(ip) if ip.iter().any(|&b| b != 0) => Some(ip),
+            _ => None,
+        };
+
+        if let Some(host) = self.hosts.iter_mut().find(|h| h.mac == mac && h.ipv4 == ipv4) {
+            host.last_seen = ticks;
+            host.frames = host.frames.wrapping_add(1);
+            host.protocols |= 1 << type_index;
+            return;
+        }
+
+        if self.hosts.len() >= MAX_HOSTS {
+            let oldest = self.hosts.iter().enumerate()
+                .min_by_key(|&(_, h)| h.last_seen)
+                .map(|(i, _)| i);
+            if let Some(oldest) = oldest {
+                self.hosts.swap_remove(oldest);
+            }
+        }
+
+        // names are learned per MAC address, a new IP doesn't forget them
+        let name = self.hosts.iter().find(|h| h.mac == mac).and_then(|h| h.name.clone());
+        self.hosts.push(Host {
+            mac: mac,
+            ipv4: ipv4,
+            first_seen: ticks,
+            last_seen: ticks,
+            frames: 1,
+            protocols: 1 << type_index,
+            name: name
+        });
+    }
+
+    fn learn_name<F>(&mut self, matches: F, name: &str) where F: Fn(&Host) -> bool {
+        for host in self.hosts.iter_mut().filter(|h| matches(h)) {
+            host.name = Some(String::from(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use classify::{DnsAnswer, DnsInfo, DnsRecordData, DnsType, ParseResult, ParseResultType};
+    use collections::string::String;
+    use collections::vec::Vec;
+    use super::{HostTable, SortOrder, MAX_HOSTS};
+
+    const ROUTER_MAC: [u8; 6] = [0x3a, 0x29, 0x08, 0x38, 0xf9, 0xe7];
+    const ROUTER: [u8; 4] = [192, 168, 7, 1];
+
+    fn mac(n: u8) -> [u8; 6] {
+        [0x02, 0, 0, 0, 0, n]
+    }
+
+    fn ip(n: u8) -> [u8; 4] {
+        [192, 168, 7, n]
+    }
+
+    // a frame from host `n` to the router
+    fn frame(pkt_type: ParseResultType, n: u8) -> ParseResult {
+        let mut result = ParseResult::new();
+        result.pkt_type = pkt_type;
+        result.mac_src = Some(mac(n));
+        result.mac_dst = Some(ROUTER_MAC);
+        result.ipv4_src = Some(ip(n));
+        result.ipv4_dst = Some(ROUTER);
+        result
+    }
+
+    fn sorted_ips(table: &HostTable) -> Vec<u8> {
+        table.sorted().iter().filter(|h| h.mac != ROUTER_MAC).map(|h| h.ipv4.unwrap()[3]).collect()
+    }
+
+    #[test]
+    fn both_ends_are_hosts() {
+        let mut table = HostTable::new(None);
+        table.on_packet(&frame(ParseResultType::TCP, 10), 100);
+        table.on_packet(&frame(ParseResultType::DNS, 10), 200);
+        assert_eq!(table.len(), 2);
+
+        let hosts = table.sorted();
+        let host = hosts.iter().find(|h| h.mac == mac(10)).unwrap();
+        assert_eq!(host.ipv4, Some(ip(10)));
+        assert_eq!((host.first_seen, host.last_seen, host.frames), (100, 200, 2));
+        assert!(host.used_protocol(ParseResultType::TCP.index()));
+        assert!(host.used_protocol(ParseResultType::DNS.index()));
+        assert!(!host.used_protocol(ParseResultType::ARP.index()));
+        assert!(hosts.iter().any(|h| h.mac == ROUTER_MAC && h.ipv4 == Some(ROUTER)));
+    }
+
+    #[test]
+    fn broadcasts_and_unassigned_addresses() {
+        let mut table = HostTable::new(None);
+        let mut discover = frame(ParseResultType::DHCP, 10);
+        discover.mac_dst = Some([0xff; 6]);
+        discover.ipv4_src = Some([0; 4]);
+        discover.ipv4_dst = Some([255; 4]);
+        table.on_packet(&discover, 100);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.sorted()[0].ipv4, None);
+    }
+
+    #[test]
+    fn expiry() {
+        let mut table = HostTable::new(Some(1000));
+        table.on_packet(&frame(ParseResultType::TCP, 10), 100);
+        table.on_packet(&frame(ParseResultType::TCP, 11), 600);
+        table.expire(1099);
+        assert_eq!(sorted_ips(&table), vec![11, 10]);
+        table.expire(1100);
+        assert_eq!(sorted_ips(&table), vec![11]);
+
+        // without a timeout they stay
+        table.set_timeout(None);
+        table.expire(100_000);
+        assert_eq!(sorted_ips(&table), vec![11]);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen_when_full() {
+        let mut table = HostTable::new(None);
+        // the router is one of them
+        for n in 0..MAX_HOSTS as u8 - 1 {
+            table.on_packet(&frame(ParseResultType::TCP, n), 100 + n as usize);
+        }
+        assert_eq!(table.len(), MAX_HOSTS);
+        // host 0 is seen again, host 1 is the oldest now
+        table.on_packet(&frame(ParseResultType::TCP, 0), 1000);
+        let mut new = frame(ParseResultType::TCP, 200);
+        new.mac_dst = Some(mac(0));
+        new.ipv4_dst = Some(ip(0));
+        table.on_packet(&new, 1001);
+
+        assert_eq!(table.len(), MAX_HOSTS);
+        let ips = sorted_ips(&table);
+        assert!(ips.contains(&200));
+        assert!(ips.contains(&0));
+        assert!(!ips.contains(&1));
+    }
+
+    #[test]
+    fn sort_orders() {
+        let mut table = HostTable::new(None);
+        table.on_packet(&frame(ParseResultType::TCP, 30), 100);
+        table.on_packet(&frame(ParseResultType::TCP, 10), 200);
+        table.on_packet(&frame(ParseResultType::TCP, 20), 300);
+        table.on_packet(&frame(ParseResultType::TCP, 20), 400);
+        table.on_packet(&frame(ParseResultType::TCP, 30), 500);
+        table.on_packet(&frame(ParseResultType::TCP, 30), 600);
+
+        assert_eq!(table.order(), SortOrder::LastSeen);
+        assert_eq!(sorted_ips(&table), vec![30, 20, 10]);
+        table.set_order(SortOrder::FirstSeen);
+        assert_eq!(sorted_ips(&table), vec![20, 10, 30]);
+        table.set_order(SortOrder::Frames);
+        assert_eq!(sorted_ips(&table), vec![30, 20, 10]);
+        table.set_order(SortOrder::Address);
+        assert_eq!(sorted_ips(&table), vec![10, 20, 30]);
+        assert_eq!(table.order().next(), SortOrder::LastSeen);
+    }
+
+    #[test]
+    fn address_order_puts_hosts_without_ip_first() {
+        let mut table = HostTable::new(None);
+        table.set_order(SortOrder::Address);
+        let mut arp = frame(ParseResultType::ARP, 10);
+        arp.mac_dst = Some([0xff; 6]);
+        arp.ipv4_src = None;
+        table.on_packet(&arp, 100);
+        table.on_packet(&frame(ParseResultType::TCP, 10), 200);
+        let hosts = table.sorted();
+        assert_eq!(hosts.iter().map(|h| h.ipv4).collect::<Vec<_>>(), vec![None, Some(ip(1)), Some(ip(10))]);
+    }
+
+    #[test]
+    fn names_from_dns_answers() {
+        let mut table = HostTable::new(None);
+        table.on_packet(&frame(ParseResultType::TCP, 10), 100);
+        table.on_packet(&frame(ParseResultType::TCP, 11), 100);
+
+        let mut response = frame(ParseResultType::DNS, 1);
+        response.dns = Some(DnsInfo {
+            id: 1,
+            query_type: Some(DnsType::A),
+            answers: vec![
+                DnsAnswer {
+                    name: String::from("www.example.com"),
+                    record_type: DnsType::CNAME,
+                    ttl: 60,
+                    data: DnsRecordData::Name(String::from("example.com"))
+                },
+                DnsAnswer { name: String::from("example.com"), record_type: DnsType::A, ttl: 60, data: DnsRecordData::A(ip(10)) },
+            ]
+        });
+        table.on_packet(&response, 200);
+
+        let hosts = table.sorted();
+        let name = |n| hosts.iter().find(|h| h.mac == mac(n)).unwrap().name.clone();
+        assert_eq!(name(10), Some(String::from("example.com")));
+        assert_eq!(name(11), None);
+    }
+
+    #[test]
+    fn names_from_dhcp_requests() {
+        let mut table = HostTable::new(None);
+        let mut request = frame(ParseResultType::DHCP, 10);
+        request.ipv4_src = Some([0; 4]);
+        request.hostname = Some(String::from("vm"));
+        table.on_packet(&request, 100);
+        assert_eq!(table.sorted()[0].name, Some(String::from("vm")));
+
+        // the address it got later keeps the name
+        table.on_packet(&frame(ParseResultType::TCP, 10), 200);
+        let hosts = table.sorted();
+        let host = hosts.iter().find(|h| h.ipv4 == Some(ip(10))).unwrap();
+        assert_eq!(host.name, Some(String::from("vm")));
+    }
+}
@@ -0,0 +1,32 @@
+// Instrumentation Trace Macrocell output, an alternative to semihosting for
+// stdout (cargo feature `itm`). The debugger has to set up the TPIU and SWO
+// pin, e.g. openocd's `tpiu config internal itm.log uart off 216000000`.
+
+use core::ptr;
+
+// stimulus port 0
+const ITM_STIM0: *mut u32 = 0xe000_0000 as *mut u32;
+// trace enable, one bit per stimulus port
+const ITM_TER: *const u32 = 0xe000_0e00 as *const u32;
+// trace control, bit 0 is ITMENA
+const ITM_TCR: *const u32 = 0xe000_0e80 as *const u32;
+
+/// Whether a debugger enabled stimulus port 0.
+pub fn enabled() -> bool {
+    unsafe { ptr::read_volatile(ITM_TCR) & 1 != 0 && ptr::read_volatile(ITM_TER) & 1 != 0 }
+}
+
+/// Writes `data` to stimulus port 0, one byte at a time. The data is
+/// dropped if no debugger listens.
+pub fn write_all(data: &[u8]) {
+    if !enabled() {
+        return;
+    }
+    for &byte in data {
+        unsafe {
+            // reads as 1 when the port can take the next write
+            while ptr::read_volatile(ITM_STIM0) & 1 == 0 {}
+            ptr::write_volatile(ITM_STIM0 as *mut u8, byte);
+        }
+    }
+}
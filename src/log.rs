@@ -0,0 +1,126 @@
+// Leveled logging on top of the semihosting console. Use the `error!`,
+// `warn!`, `info!`, `debug!` and `trace!` macros, they prefix the message
+// with the ticks, the level and the module:
+//
+//     [   12.345 INFO  context] dns lookup: example.com
+//
+// The `max_level_*` cargo features (and `release_max_level_*` for release
+// builds) remove the disabled levels at compile time, everything else is
+// filtered at runtime per module, see `set_level`.
+
+use core::fmt;
+use stm32f7::system_clock;
+
+use context::TICKS_PER_SECOND;
+use log_level::{self, short_module, Filters};
+pub use log_level::Level;
+
+/// The most verbose level compiled in. A constant after inlining, so the
+/// disabled log statements are optimized away.
+#[inline(always)]
+pub fn static_max_level() -> Level {
+    log_level::static_max_level(cfg!(debug_assertions), max_level(), release_max_level())
+}
+
+#[inline(always)]
+fn max_level() -> Level {
+    if cfg!(feature = "max_level_off") {
+        Level::Off
+    } else if cfg!(feature = "max_level_error") {
+        Level::Error
+    } else if cfg!(feature = "max_level_warn") {
+        Level::Warn
+    } else if cfg!(feature = "max_level_info") {
+        Level::Info
+    } else if cfg!(feature = "max_level_debug") {
+        Level::Debug
+    } else {
+        Level::Trace
+    }
+}
+
+#[inline(always)]
+fn release_max_level() -> Level {
+    if cfg!(feature = "release_max_level_off") {
+        Level::Off
+    } else if cfg!(feature = "release_max_level_error") {
+        Level::Error
+    } else if cfg!(feature = "release_max_level_warn") {
+        Level::Warn
+    } else if cfg!(feature = "release_max_level_info") {
+        Level::Info
+    } else if cfg!(feature = "release_max_level_debug") {
+        Level::Debug
+    } else {
+        Level::Trace
+    }
+}
+
+// only changed from the main loop
+static mut FILTERS: Filters = Filters::new();
+
+/// Sets the level for all modules without a level of their own.
+pub fn set_default_level(level: Level) {
+    unsafe { FILTERS.set_default_level(level); }
+}
+
+/// Sets the level of `module` (without the crate name, e.g. `screen::grid`)
+/// and its submodules. Fails if the module name is too long or there are
+/// too many module levels already.
+pub fn set_level(module: &str, level: Level) -> Result<(), ()> {
+    unsafe { FILTERS.set_level(module, level) }
+}
+
+/// Whether a message of `level` from `module_path` would be printed.
+#[inline(always)]
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    level <= static_max_level() && level <= unsafe { FILTERS.level(short_module(module_path)) }
+}
+
+/// Prints a message, use the macros instead.
+pub fn log(level: Level, module_path: &str, args: fmt::Arguments) {
+    let ticks = system_clock::ticks();
+    let (seconds, millis) = (ticks / TICKS_PER_SECOND, ticks % TICKS_PER_SECOND);
+    let module = short_module(module_path);
+    match level {
+        Level::Error | Level::Warn => {
+            println_err!("[{:>5}.{:03} {:5} {}] {}", seconds, millis, level.name(), module, args)
+        }
+        _ => println!("[{:>5}.{:03} {:5} {}] {}", seconds, millis, level.name(), module, args),
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => ({
+        let level = $level;
+        if $crate::log::enabled(level, module_path!()) {
+            $crate::log::log(level, module_path!(), format_args!($($arg)+));
+        }
+    });
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => (log!($crate::log::Level::Error, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => (log!($crate::log::Level::Warn, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => (log!($crate::log::Level::Info, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => (log!($crate::log::Level::Debug, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => (log!($crate::log::Level::Trace, $($arg)+));
+}
@@ -0,0 +1,228 @@
+// The log levels and the per module filter, apart from `log` so that they
+// build on the host.
+
+use core::cmp;
+
+pub const MAX_MODULE_FILTERS: usize = 8;
+pub const MAX_MODULE_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Nothing is logged
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// The most verbose level compiled in, from the `max_level_*` and the
+/// `release_max_level_*` features. Release builds use the less verbose one.
+pub fn static_max_level(debug_assertions: bool, max_level: Level, release_max_level: Level) -> Level {
+    if debug_assertions {
+        max_level
+    } else {
+        cmp::min(release_max_level, max_level)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ModuleFilter {
+    module: [u8; MAX_MODULE_LEN],
+    len: usize,
+    level: Level
+}
+
+impl ModuleFilter {
+    fn module(&self) -> &[u8] {
+        &self.module[..self.len]
+    }
+
+    // `screen` matches `screen` and `screen::grid`, but not `screens`
+    fn matches(&self, module: &str) -> bool {
+        let module = module.as_bytes();
+        module.starts_with(self.module()) &&
+            (module.len() == self.len || module[self.len..].starts_with(b"::"))
+    }
+}
+
+/// The runtime levels, a default one and up to `MAX_MODULE_FILTERS` for
+/// modules of their own.
+pub struct Filters {
+    default_level: Level,
+    modules: [Option<ModuleFilter>; MAX_MODULE_FILTERS]
+}
+
+impl Filters {
+    pub const fn new() -> Self {
+        Filters { default_level: Level::Info, modules: [None; MAX_MODULE_FILTERS] }
+    }
+
+    /// Sets the level for all modules without a level of their own.
+    pub fn set_default_level(&mut self, level: Level) {
+        self.default_level = level;
+    }
+
+    /// Sets the level of `module` (without the crate name, e.g.
+    /// `screen::grid`) and its submodules. Fails if the module name is too
+    /// long or there are too many module levels already.
+    pub fn set_level(&mut self, module: &str, level: Level) -> Result<(), ()> {
+        if module.len() > MAX_MODULE_LEN {
+            return Err(());
+        }
+        let mut filter = ModuleFilter {
+            module: [0; MAX_MODULE_LEN],
+            len: module.len(),
+            level: level
+        };
+        filter.module[..module.len()].copy_from_slice(module.as_bytes());
+
+        let existing = self.modules.iter().position(|f| match *f {
+            Some(ref f) => f.module() == module.as_bytes(),
+            None => false,
+        });
+        match existing.or_else(|| self.modules.iter().position(|f| f.is_none())) {
+            Some(index) => {
+                self.modules[index] = Some(filter);
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    /// The level of `module`, as given by `short_module`.
+    pub fn level(&self, module: &str) -> Level {
+        // the longest matching module wins, so `screen::grid` overrides `screen`
+        let mut best: Option<&ModuleFilter> = None;
+        for filter in self.modules.iter() {
+            if let Some(ref filter) = *filter {
+                if filter.matches(module) && best.map(|b| b.len < filter.len).unwrap_or(true) {
+                    best = Some(filter);
+                }
+            }
+        }
+        match best {
+            Some(filter) => filter.level,
+            None => self.default_level,
+        }
+    }
+}
+
+// `dr3th3rn3t::screen::grid` is `screen::grid`, the crate root is `main`
+pub fn short_module(module_path: &str) -> &str {
+    match module_path.find("::") {
+        Some(index) => &module_path[index + 2..],
+        None => "main",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_level() {
+        let mut filters = Filters::new();
+        assert_eq!(filters.level("context"), Level::Info);
+        filters.set_default_level(Level::Warn);
+        assert_eq!(filters.level("context"), Level::Warn);
+        assert_eq!(filters.level("main"), Level::Warn);
+    }
+
+    #[test]
+    fn module_and_submodules() {
+        let mut filters = Filters::new();
+        filters.set_level("screen", Level::Trace).unwrap();
+        assert_eq!(filters.level("screen"), Level::Trace);
+        assert_eq!(filters.level("screen::grid"), Level::Trace);
+        assert_eq!(filters.level("screens"), Level::Info);
+        assert_eq!(filters.level("context"), Level::Info);
+    }
+
+    #[test]
+    fn longest_module_wins() {
+        let mut filters = Filters::new();
+        filters.set_level("screen::grid", Level::Off).unwrap();
+        filters.set_level("screen", Level::Debug).unwrap();
+        assert_eq!(filters.level("screen::grid"), Level::Off);
+        assert_eq!(filters.level("screen::hosts"), Level::Debug);
+    }
+
+    #[test]
+    fn setting_a_module_again_replaces_it() {
+        let mut filters = Filters::new();
+        filters.set_level("dhcp_client", Level::Trace).unwrap();
+        filters.set_level("dhcp_client", Level::Error).unwrap();
+        assert_eq!(filters.level("dhcp_client"), Level::Error);
+        // and doesn't take up another place
+        for i in 0..MAX_MODULE_FILTERS - 1 {
+            filters.set_level(&format!("module{}", i), Level::Debug).unwrap();
+        }
+        assert_eq!(filters.set_level("dhcp_client", Level::Warn), Ok(()));
+    }
+
+    #[test]
+    fn at_most_eight_modules() {
+        let mut filters = Filters::new();
+        for i in 0..MAX_MODULE_FILTERS {
+            filters.set_level(&format!("module{}", i), Level::Debug).unwrap();
+        }
+        assert_eq!(filters.set_level("context", Level::Debug), Err(()));
+        assert_eq!(filters.level("context"), Level::Info);
+        assert_eq!(filters.level("module7"), Level::Debug);
+    }
+
+    #[test]
+    fn module_names_up_to_24_bytes() {
+        let mut filters = Filters::new();
+        let longest = "a_module_name_of_24_byte";
+        assert_eq!(longest.len(), MAX_MODULE_LEN);
+        assert_eq!(filters.set_level(longest, Level::Trace), Ok(()));
+        assert_eq!(filters.level(longest), Level::Trace);
+        assert_eq!(filters.set_level("a_module_name_of_25_bytes", Level::Trace), Err(()));
+        assert_eq!(filters.level("a_module_name_of_25_bytes"), Level::Info);
+    }
+
+    #[test]
+    fn compile_time_max_level() {
+        // debug builds ignore the `release_max_level_*` features
+        assert_eq!(static_max_level(true, Level::Debug, Level::Warn), Level::Debug);
+        // release builds use the less verbose of both
+        assert_eq!(static_max_level(false, Level::Debug, Level::Warn), Level::Warn);
+        assert_eq!(static_max_level(false, Level::Info, Level::Trace), Level::Info);
+        assert_eq!(static_max_level(false, Level::Trace, Level::Off), Level::Off);
+    }
+
+    #[test]
+    fn short_module_names() {
+        assert_eq!(short_module("dr3th3rn3t::screen::grid"), "screen::grid");
+        assert_eq!(short_module("dr3th3rn3t::context"), "context");
+        assert_eq!(short_module("dr3th3rn3t"), "main");
+    }
+}
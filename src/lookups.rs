@@ -0,0 +1,54 @@
+use collections::string::String;
+use collections::vec_deque::{self, VecDeque};
+use graphics::Graphics;
+
+/// The most recently looked up DNS names, newest first.
+pub struct RecentLookups {
+    names: VecDeque<String>,
+    capacity: usize
+}
+
+impl RecentLookups {
+    pub fn new(capacity: usize) -> Self {
+        RecentLookups {
+            names: VecDeque::with_capacity(capacity),
+            capacity: capacity
+        }
+    }
+
+    pub fn push(&mut self, name: &str) {
+        // resolvers often ask for A and AAAA right after each other
+        if self.names.front().map(|n| n == name).unwrap_or(false) {
+            return;
+        }
+        if self.names.len() >= self.capacity {
+            self.names.pop_back();
+        }
+        self.names.push_front(String::from(name));
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<String> {
+        self.names.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Draws the list into the given box, one name per line, newest on top.
+    /// Names that don't fit are cut off.
+    pub fn draw(&self, graphics: &mut Graphics, x: u16, y: u16, width: u16, height: u16) {
+        let line_height = graphics.font().line_height();
+        Graphics::draw_rect_filled(&mut graphics.lcd, x, y, width, height, 0x0);
+        graphics.draw_text_in(x, y, width, line_height, "recent lookups", 0xaacc, 0x0);
+
+        let mut line_y = y + 2 * line_height;
+        for name in self.names.iter() {
+            if line_y + line_height > y + height {
+                break;
+            }
+            graphics.draw_text_in(x, line_y, width, line_height, name, 0xffff, 0x0);
+            line_y += line_height;
+        }
+    }
+}
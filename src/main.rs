@@ -4,6 +4,7 @@
 #![feature(asm)]
 #![feature(const_fn)]
 #![feature(alloc, collections)]
+#![feature(naked_functions)]
 
 #[macro_use]
 extern crate stm32f7_discovery as stm32f7;
@@ -20,28 +21,75 @@ use stm32f7::{random, audio, ethernet, sdram, system_clock, board, embedded, tou
 
 #[macro_use]
 mod semi_hosting;
+#[macro_use]
+mod log;
+#[macro_use]
+mod panic;
+mod addr;
+mod audio_dma;
+mod classify;
+mod command;
+mod conntrack;
+mod console;
+mod context;
+mod dhcp_client;
+mod exceptions;
+mod fault;
+mod filter;
 mod font;
 mod graphics;
+mod hosts;
+#[cfg(feature = "itm")]
+mod itm;
+mod log_level;
+mod lookups;
+mod neighbors;
+mod palette;
+mod pcapng;
+mod pcapng_blocks;
+mod ring_buffer;
+mod screen;
+mod sonify;
 mod sound;
+mod stats;
+mod synth;
 
 use random::{Rng,ErrorType};
 use graphics::Graphics;
-
-use collections::string::String;
+use classify::{ParseResult, ParseResultType};
+use console::Console;
+use context::Context;
+use dhcp_client::DhcpClient;
+use sonify::Sonifier;
+use synth::Frame;
+use screen::{Screen, Screens};
+use screen::grid::GridScreen;
+use screen::connections::ConnectionsScreen;
+use screen::hosts::HostsScreen;
+use screen::neighbors::NeighborsScreen;
+use screen::settings::SettingsScreen;
+use screen::sound::SoundScreen;
+use screen::stats::StatsScreen;
+
+use core::cell::RefCell;
 use collections::BTreeMap;
+use collections::boxed::Box;
+use collections::vec::Vec;
+use stm32f7::cortex_m::interrupt::{self, Mutex};
 
-use net::ethernet::{EthernetAddress, EthernetPacket, EthernetKind, EtherType};
-use net::ipv4::{Ipv4Address, Ipv4Packet, Ipv4Kind, IpProtocol};
+use net::ethernet::EthernetAddress;
+use net::ipv4::Ipv4Address;
 use net::TxPacket;
-use net::arp;
-use net::udp::{UdpPacket, UdpKind};
-use net::dns::{self, DnsPacket, };
-use net::dhcp::{self, DhcpPacket, DhcpType};
-use net::icmp::IcmpType;
 
 use ethernet::EthernetDevice;
 
-static sin440: [u16; 48000] = include!("sin.hex");
+// the notes are started in the main loop and mixed in the DMA interrupt, both
+// only take it inside a critical section
+static SONIFIER: Mutex<RefCell<Option<Sonifier>>> = Mutex::new(RefCell::new(None));
+
+// the address the driver configures the MAC with, our DHCP client needs it
+// for the frames it builds
+const ETH_ADDR: [u8; 6] = [0x00, 0x08, 0xdc, 0xab, 0xcd, 0xef];
 
 #[no_mangle]
 pub unsafe extern "C" fn reset() -> ! {
@@ -106,6 +154,8 @@ fn main(hw: board::Hardware) -> ! {
         ..
     } = hw;
 
+    exceptions::install();
+
     let mut gpio = Gpio::new(gpio_a,
                              gpio_b,
                              gpio_c,
@@ -142,6 +192,7 @@ fn main(hw: board::Hardware) -> ! {
     let mut i2c_3 = i2c::init(i2c_3);
 
     let mut graphics = Graphics::init(ltdc, rcc, &mut gpio, &mut i2c_3);
+    panic::set_graphics(&mut graphics);
 
     // original code - may be incompatible with our plans
     // audio::init_sai_2_pins(&mut gpio);
@@ -152,7 +203,7 @@ fn main(hw: board::Hardware) -> ! {
                                  gpio::OutputType::PushPull,
                                  gpio::OutputSpeed::Low,
                                  gpio::Resistor::NoPull,)
-        .expect("led pin already in use");
+        .unwrap_or_else(|_| fatal!("led pin already in use"));
 
 
     let mut eth_device = ethernet::EthernetDevice::new(
@@ -166,30 +217,36 @@ fn main(hw: board::Hardware) -> ! {
         );
 
     if let Err(e) = eth_device {
-        println!("ethernet init failed: {:?}", e);
+        error!("ethernet init failed: {:?}", e);
     } else {
-        println!("ethernet init successful");
+        info!("ethernet init successful");
     }
 
     //let mut random_gen = random::Rng::init(rng, rcc).expect("rng already enabled");
 
     let mut last_toggle_ticks = system_clock::ticks();
 
-    //graphics.prepare();
-    graphics.clear_screen();
-    println!("clear screen");
-
-    //let mut snd = sound::Sound::init(sai_2, &mut i2c_3, rcc, &mut gpio);
-
-    let square_len = 25 as u16;
-    let mut x = 0 as u16;
-    let mut y = 0 as u16;
-    let mut color = 0 as u16;
+    let mut context = Context::new(system_clock::ticks(), ETH_ADDR);
+    let mut screens = Screens::new(vec![
+        Box::new(GridScreen::new()) as Box<Screen>,
+        Box::new(StatsScreen::new()),
+        Box::new(HostsScreen::new()),
+        Box::new(ConnectionsScreen::new()),
+        Box::new(NeighborsScreen::new()),
+        Box::new(SoundScreen::new()),
+        Box::new(SettingsScreen::new()),
+    ]);
+    screens.draw(&mut graphics, &context);
+
+    let mut console = Console::new();
+
+    interrupt::free(|cs| *SONIFIER.borrow(cs).borrow_mut() = Some(Sonifier::new()));
+    // the codec is only set up once the sound is turned on, so a board
+    // without a working one runs fine as long as it stays off
+    let mut codec = None;
 
     loop {
 
-        //println!("tick foobar");
-
         let ticks = system_clock::ticks();
         if (ticks - last_toggle_ticks)  > 1500 {
             let current_led_state = led.get();
@@ -197,190 +254,150 @@ fn main(hw: board::Hardware) -> ! {
             last_toggle_ticks = ticks;
         }
 
-        // println!("result from random.tick() {}", random.tick());
-        //snd.tick();
+        context.tick(ticks);
+        screens.tick(&mut graphics, &mut context, &mut i2c_3, ticks);
 
-        /*
-         *if let Ok(number) = random_gen.poll_and_get() {
-         *    snd.put_data(sai_2, &mut i2c_3, number);
-         *} else {
-         *    println!("No random data ready");
-         *}
-         */
+        match console.poll(ticks) {
+            Some(Ok(command)) => console::execute(command, &mut graphics, &mut context, &mut screens),
+            Some(Err(error)) => console::print_error(error),
+            None => {}
+        }
+
+        if context.settings.sound && codec.is_none() {
+            match sound::Sound::init(sai_2, &mut i2c_3, rcc, &mut gpio) {
+                Ok(s) => {
+                    audio_dma::start(sai_2, rcc, fill_audio);
+                    codec = Some(s);
+                }
+                Err(e) => {
+                    error!("can't turn the sound on: {:?}", e);
+                    context.settings.sound = false;
+                    screens.draw(&mut graphics, &context);
+                }
+            }
+        }
+
+        // println!("result from random.tick() {}", random.tick());
 
         // this is the Ethernet tick
         if let Ok(ref mut eth_device) = eth_device {
-            let res = eth_device.handle_next_packet(&packets);
-            // draw some nice squares
+            let res = {
+                // the driver only hands out the raw frame to the handler
+                let capture = RefCell::new(&mut context.capture);
+                let dhcp = RefCell::new(&mut context.dhcp);
+                let arp_copy = RefCell::new(&mut context.arp_cache);
+                let handler = |data: &[u8],
+                               ipv4_addr: &mut Option<Ipv4Address>,
+                               requested_ipv4_addr: &mut Option<Ipv4Address>,
+                               arp_cache: &mut BTreeMap<Ipv4Address, EthernetAddress>| {
+                    capture.borrow_mut().write_frame(ticks, data);
+                    packets(data, ipv4_addr, requested_ipv4_addr, arp_cache, &mut arp_copy.borrow_mut(),
+                            &mut dhcp.borrow_mut(), ticks)
+                };
+                eth_device.handle_next_packet(&handler)
+            };
             if res.is_ok() {
                 let parse_res = res.unwrap();
-
-                // base color depending on type
-                match parse_res.pkt_type {
-                    ParseResultType::ARP => { color = 0xffff }
-                    ParseResultType::DHCP => { color = 0xff00 }
-                    ParseResultType::ICMP => { color = 0xfff0 }
-                    ParseResultType::DNS => { color = 0xaacc }
-                    ParseResultType::HTTP => { color = 0xccaa }
-                    ParseResultType::Unknown => { color = 0x0 }
-                }
-
-                fn eth_addr_as_u16(eth_addr: EthernetAddress) -> u16 {
-                    let mut sum: u16 = 0;
-                    for byte in eth_addr.as_bytes().iter() {
-                        sum = sum.wrapping_add(*byte as u16);
-                    }
-                    sum
-                }
-                fn ipv4_addr_as_u16(ipv4_addr: Ipv4Address) -> u16 {
-                    let mut sum: u16 = 0;
-                    for byte in ipv4_addr.as_bytes().iter() {
-                        sum = sum.wrapping_add(*byte as u16);
-                    }
-                    sum
-                }
-
-                // add mac and ip addresses
-                /*
-                 *if let Some(mac) = parse_res.mac_src {
-                 *    color = color.wrapping_add(eth_addr_as_u16(mac));
-                 *}
-                 *if let Some(mac) = parse_res.mac_dst {
-                 *    color = color.wrapping_add(eth_addr_as_u16(mac));
-                 *}
-                 *if let Some(ip) = parse_res.ipv4_src {
-                 *    color = color.wrapping_add(ipv4_addr_as_u16(ip));
-                 *}
-                 *if let Some(ip) = parse_res.ipv4_src {
-                 *    color = color.wrapping_add(ipv4_addr_as_u16(ip));
-                 *}
-                 */
-
-                Graphics::draw_square_filled(&mut graphics.lcd, x, y, square_len, color);
-                x = x + square_len;
-                //println!("I'll draw at ({},{}) with {}", x, y, color);
-                if x+square_len >= 480 {
-                    x = 0;
-                    y = y + square_len;
-                    if y+square_len >= 272 {
-                        y = 0;
-                    }
+                context.track(&parse_res);
+                if context.passes_filter(&parse_res) {
+                    context.on_packet(&parse_res);
+                    screens.on_packet(&mut graphics, &context, &parse_res);
+                    interrupt::free(|cs| {
+                        if let Some(ref mut sonifier) = *SONIFIER.borrow(cs).borrow_mut() {
+                            sonifier.on_packet(&parse_res, &context.settings);
+                        }
+                    });
                 }
-
             } else {
                 match res.unwrap_err() {
                     stm32f7::ethernet::Error::Exhausted => {}
-                    e => {println!("err {:?}", e);}
+                    e => {warn!("err {:?}", e);}
                 }
             }
-        }
 
-        //graphics.tick(&mut i2c_3);
+            // the DHCP client's frames go out on their own, not as replies
+            // to received ones, a lost frame is retransmitted by its timers
+            while let Some(frame) = context.dhcp.next_frame() {
+                if let Err(e) = eth_device.send(tx_packet(frame)) {
+                    warn!("could not send a DHCP frame: {:?}", e);
+                    break;
+                }
+            }
+        }
 
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ParseResultType {
-    Unknown,
-    ARP,
-    DHCP,
-    ICMP,
-    DNS,
-    HTTP
+pub fn packets(data: &[u8],
+               ipv4_addr: &mut Option<Ipv4Address>,
+               requested_ipv4_addr: &mut Option<Ipv4Address>,
+               arp_cache: &mut BTreeMap<Ipv4Address, EthernetAddress>,
+               arp_copy: &mut BTreeMap<[u8; 4], [u8; 6]>,
+               dhcp: &mut DhcpClient,
+               ticks: usize) -> (ParseResult, Option<TxPacket>) {
+    let parse_result = classify::classify(data);
+
+    // our own client instead of the driver's, which never renews its lease
+    if let Some(ref info) = parse_result.dhcp {
+        dhcp.on_dhcp(info, ticks);
+    }
+    if parse_result.pkt_type == ParseResultType::ARP {
+        if let (Some(mac), Some(sender), Some(target)) =
+            (parse_result.arp_sender_mac, parse_result.ipv4_src, parse_result.ipv4_dst) {
+            dhcp.on_arp(mac, sender, target, ticks);
+        }
+    }
+    *ipv4_addr = dhcp.address().map(|a| Ipv4Address::from_bytes(&a));
+    *requested_ipv4_addr = dhcp.requested_address().map(|a| Ipv4Address::from_bytes(&a));
+
+    // frames the net crate can't handle (VLAN tags, jumbo frames, truncated
+    // captures, ...) are still shown, but never answered
+    let eth_packet = match net::parse(data) {
+        Ok(eth_packet) => eth_packet,
+        Err(_) => return (parse_result, None),
+    };
+
+    // This uses methods of the driver to create reply packets.
+    let reply = if let Some(res) = EthernetDevice::handle_arp(&eth_packet, ipv4_addr, arp_cache) {
+        res.unwrap_or_else(|e| fatal!("can't reply: {:?}", e))
+    } else if let Some(res) = EthernetDevice::handle_icmp(&eth_packet, ipv4_addr, arp_cache) {
+        res.unwrap_or_else(|e| fatal!("can't reply: {:?}", e))
+    } else {
+        None
+    };
+    // the handlers above learn the addresses
+    copy_arp_cache(arp_cache, arp_copy);
+    (parse_result, reply)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ParseResultDirection {
-    Request,
-    Response
+fn fill_audio(frames: &mut [Frame]) {
+    interrupt::free(|cs| {
+        if let Some(ref mut sonifier) = *SONIFIER.borrow(cs).borrow_mut() {
+            sonifier.fill(frames);
+        }
+    });
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ParseResult {
-    mac_src: Option<EthernetAddress>,
-    mac_dst: Option<EthernetAddress>,
-    ipv4_src: Option<Ipv4Address>,
-    ipv4_dst: Option<Ipv4Address>,
-    port_src: Option<u16>,
-    port_dst: Option<u16>,
-    pkt_type: ParseResultType,
-    direction: Option<ParseResultDirection>,
-    hostname: Option<String>
+fn tx_packet(frame: Vec<u8>) -> TxPacket {
+    let mut packet = TxPacket::new(frame.len());
+    packet.push_bytes(&frame);
+    packet
 }
 
-pub fn packets(data: &[u8], ipv4_addr: &mut Option<Ipv4Address>, requested_ipv4_addr: &mut Option<Ipv4Address>, arp_cache: &mut BTreeMap<Ipv4Address, EthernetAddress>) -> (ParseResult, Option<TxPacket>) {
-    let mut parse_result = ParseResult {mac_src: None, mac_dst: None, ipv4_src: None, ipv4_dst: None, port_src: None, port_dst: None, pkt_type: ParseResultType::Unknown, direction: None, hostname: None};
-
-    let eth_packet = net::parse(data).unwrap();
-
-    // extract some high level packet information
-    {
-        let EthernetPacket {ref header, ref payload} = eth_packet;
-        // TODO: does this copy? maybe use .clone()
-        parse_result.mac_src = Some(header.src_addr);
-        parse_result.mac_dst = Some(header.dst_addr);
-        match *payload {
-            EthernetKind::Ipv4(Ipv4Packet {header: ref ipv4_header, payload: ref ipv4_payload}) => {
-
-                parse_result.ipv4_src = Some(ipv4_header.src_addr);
-                parse_result.ipv4_dst = Some(ipv4_header.dst_addr);
-
-                match *ipv4_payload {
-                    Ipv4Kind::Udp(UdpPacket {header: ref udp_header, payload: ref udp_payload}) => {
-                        parse_result.port_src = Some(udp_header.src_port);
-                        parse_result.port_dst = Some(udp_header.dst_port);
-
-                        match *udp_payload {
-                            UdpKind::Dhcp(DhcpPacket {mac, operation, .. }) => {
-
-                                parse_result.pkt_type = ParseResultType::DHCP;
-
-                                match operation {
-                                    DhcpType::Offer { ip, dhcp_server_ip } => {
-                                        parse_result.ipv4_dst = Some(ip);
-                                    }
-                                    DhcpType::Ack { ip } => {
-                                        parse_result.ipv4_src = Some(ip);
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            UdpKind::Dns(DnsPacket {header: dns_header, hostname: _}) => {
-                                parse_result.pkt_type = ParseResultType::DNS;
-                                //parse_result.hostname = Some(hostname);
-                            }
-                            UdpKind::Unknown(_) => {
-                                parse_result.pkt_type = ParseResultType::Unknown;
-                            }
-                        }
-                    }
-                    Ipv4Kind::Icmp(icmp) => {
-                        parse_result.pkt_type = ParseResultType::ICMP;
-                    }
-                    Ipv4Kind::Unknown(_, _) => {
-                        parse_result.pkt_type = ParseResultType::Unknown;
-                    }
-                }
-            }
-            EthernetKind::Arp(_) => {
-                // TODO: maybe look at the ARP type to extract the direction, or IP addresses
-                parse_result.pkt_type = ParseResultType::ARP;
-            }
-            EthernetKind::Unknown(_) => {
-                parse_result.pkt_type = ParseResultType::Unknown;
-            }
-        }
+fn copy_arp_cache(arp_cache: &BTreeMap<Ipv4Address, EthernetAddress>, copy: &mut BTreeMap<[u8; 4], [u8; 6]>) {
+    let unchanged = arp_cache.len() == copy.len() &&
+        arp_cache.iter().zip(copy.iter()).all(|((ip, mac), (ip_copy, mac_copy))| {
+            &ip.as_bytes()[..] == &ip_copy[..] && &mac.as_bytes()[..] == &mac_copy[..]
+        });
+    if unchanged {
+        return;
     }
-
-    // This uses methods of the driver to create reply packets
-    if let Some(res) = EthernetDevice::handle_dhcp(&eth_packet, ipv4_addr, requested_ipv4_addr) {
-        return (parse_result, res.unwrap());
-    } else if let Some(res) = EthernetDevice::handle_arp(&eth_packet, ipv4_addr, arp_cache) {
-        return (parse_result, res.unwrap());
-    } else if let Some(res) = EthernetDevice::handle_icmp(&eth_packet, ipv4_addr, arp_cache) {
-        return (parse_result, res.unwrap());
-    } else {
-        return (parse_result, None);
+    copy.clear();
+    for (ip, mac) in arp_cache.iter() {
+        let mut ip_bytes = [0; 4];
+        ip_bytes.copy_from_slice(&ip.as_bytes()[..]);
+        let mut mac_bytes = [0; 6];
+        mac_bytes.copy_from_slice(&mac.as_bytes()[..]);
+        copy.insert(ip_bytes, mac_bytes);
     }
 }
@@ -0,0 +1,191 @@
+use collections::vec::Vec;
+
+use classify::{NeighborInfo, ParseResult, ParseResultType};
+
+// a port only ever sees its switch, a few more in a lab with hubs
+const MAX_NEIGHBORS: usize = 8;
+
+/// A switch (or anything else) announcing itself with LLDP or CDP.
+#[derive(Debug, Clone)]
+pub struct Neighbor {
+    /// The source address of the announcements
+    pub mac: [u8; 6],
+    /// `ParseResultType::LLDP` or `ParseResultType::CDP`
+    pub protocol: ParseResultType,
+    pub info: NeighborInfo,
+    /// The VLAN the announcements are tagged with
+    pub vlan_id: Option<u16>,
+    pub last_seen: usize,
+    pub announcements: u32
+}
+
+impl Neighbor {
+    /// Ticks until the last announcement is no longer valid.
+    pub fn remaining(&self, ticks: usize) -> usize {
+        // the TTL is in seconds, the ticks are milliseconds
+        let ttl = self.info.ttl as usize * 1000;
+        ttl.saturating_sub(ticks.wrapping_sub(self.last_seen))
+    }
+}
+
+/// The link neighbors, kept until the TTL of their last announcement runs out.
+pub struct NeighborTable {
+    neighbors: Vec<Neighbor>
+}
+
+impl NeighborTable {
+    pub fn new() -> Self {
+        NeighborTable { neighbors: Vec::with_capacity(MAX_NEIGHBORS) }
+    }
+
+    /// Ordered by the time they were first seen.
+    pub fn neighbors(&self) -> &[Neighbor] {
+        &self.neighbors
+    }
+
+    pub fn len(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    pub fn on_packet(&mut self, parse_result: &ParseResult, ticks: usize) {
+        let (mac, info) = match (parse_result.mac_src, parse_result.neighbor.as_ref()) {
+            (Some(mac), Some(info)) => (mac, info),
+            _ => return,
+        };
+        let protocol = &parse_result.pkt_type;
+
+        // a switch may speak both protocols, each gets a row of its own
+        if let Some(neighbor) = self.neighbors.iter_mut().find(|n| n.mac == mac && n.protocol == *protocol) {
+            neighbor.info = info.clone();
+            neighbor.vlan_id = parse_result.vlan_id;
+            neighbor.last_seen = ticks;
+            neighbor.announcements = neighbor.announcements.wrapping_add(1);
+            return;
+        }
+
+        if self.neighbors.len() >= MAX_NEIGHBORS {
+            let oldest = self.neighbors.iter().enumerate()
+                .min_by_key(|&(_, n)| n.last_seen)
+                .map(|(i, _)| i);
+            if let Some(oldest) = oldest {
+                self.neighbors.remove(oldest);
+            }
+        }
+        self.neighbors.push(Neighbor {
+            mac: mac,
+            protocol: protocol.clone(),
+            info: info.clone(),
+            vlan_id: parse_result.vlan_id,
+            last_seen: ticks,
+            announcements: 1
+        });
+    }
+
+    /// Removes the neighbors whose last announcement expired. A TTL of zero
+    /// (sent on shutdown) removes a neighbor on the next call.
+    pub fn expire(&mut self, ticks: usize) {
+        self.neighbors.retain(|neighbor| neighbor.remaining(ticks) > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use classify::{classify, NeighborInfo, ParseResult, ParseResultType};
+    use collections::String;
+    use super::{NeighborTable, MAX_NEIGHBORS};
+
+    const LLDP: &'static [u8] = include_bytes!("../frames/lldp.bin");
+    const ARP_REQUEST: &'static [u8] = include_bytes!("../frames/arp_request.bin");
+    const NEIGHBOR_SOLICITATION: &'static [u8] = include_bytes!("../frames/neighbor_solicitation.bin");
+    // the sender of the captured announcement
+    const SWITCH_MAC: [u8; 6] = [0x3a, 0x29, 0x08, 0x38, 0xf9, 0xe7];
+
+    fn cdp(mac: [u8; 6], ttl: u16) -> ParseResult {
+        let mut result = ParseResult::new();
+        result.pkt_type = ParseResultType::CDP;
+        result.mac_src = Some(mac);
+        result.neighbor = Some(NeighborInfo {
+            name: Some(String::from("switch")),
+            port: Some(String::from("GigabitEthernet0/1")),
+            management: Some([10, 0, 0, 2]),
+            ttl: ttl
+        });
+        result
+    }
+
+    #[test]
+    fn learns_from_lldp() {
+        let mut table = NeighborTable::new();
+        table.on_packet(&classify(LLDP), 1000);
+        table.on_packet(&classify(LLDP), 31_000);
+        assert_eq!(table.len(), 1);
+
+        let neighbor = &table.neighbors()[0];
+        assert_eq!(neighbor.mac, SWITCH_MAC);
+        assert_eq!(neighbor.protocol, ParseResultType::LLDP);
+        assert_eq!(neighbor.info.name, Some(String::from("vm")));
+        assert_eq!(neighbor.info.port, Some(String::from("fxs")));
+        assert_eq!((neighbor.last_seen, neighbor.announcements), (31_000, 2));
+        assert_eq!(neighbor.vlan_id, None);
+        // 120 seconds from the last announcement
+        assert_eq!(neighbor.remaining(41_000), 110_000);
+    }
+
+    #[test]
+    fn arp_and_ndp_are_no_announcements() {
+        let mut table = NeighborTable::new();
+        table.on_packet(&classify(ARP_REQUEST), 1000);
+        table.on_packet(&classify(NEIGHBOR_SOLICITATION), 1000);
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn one_row_per_protocol() {
+        let mut table = NeighborTable::new();
+        table.on_packet(&classify(LLDP), 1000);
+        let mut tagged = cdp(SWITCH_MAC, 180);
+        tagged.vlan_id = Some(42);
+        table.on_packet(&tagged, 2000);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.neighbors()[1].protocol, ParseResultType::CDP);
+        assert_eq!(table.neighbors()[1].vlan_id, Some(42));
+        assert_eq!(table.neighbors()[1].info.management, Some([10, 0, 0, 2]));
+    }
+
+    #[test]
+    fn expires_with_the_ttl() {
+        let mut table = NeighborTable::new();
+        table.on_packet(&classify(LLDP), 1000);
+        table.on_packet(&cdp([0x02, 0, 0, 0, 0, 1], 60), 1000);
+        table.expire(60_999);
+        assert_eq!(table.len(), 2);
+        table.expire(61_000);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.neighbors()[0].protocol, ParseResultType::LLDP);
+        table.expire(121_000);
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn ttl_zero_removes_right_away() {
+        let mut table = NeighborTable::new();
+        table.on_packet(&cdp([0x02, 0, 0, 0, 0, 1], 180), 1000);
+        table.on_packet(&cdp([0x02, 0, 0, 0, 0, 1], 0), 2000);
+        table.expire(2000);
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen() {
+        let mut table = NeighborTable::new();
+        for n in 0..MAX_NEIGHBORS {
+            table.on_packet(&cdp([0x02, 0, 0, 0, 0, n as u8], 180), 1000 + n);
+        }
+        table.on_packet(&cdp([0x02, 0, 0, 0, 0, 0], 180), 2000);
+        table.on_packet(&classify(LLDP), 3000);
+        assert_eq!(table.len(), MAX_NEIGHBORS);
+        assert!(table.neighbors().iter().any(|n| n.mac == [0x02, 0, 0, 0, 0, 0]));
+        assert!(!table.neighbors().iter().any(|n| n.mac == [0x02, 0, 0, 0, 0, 1]));
+        assert_eq!(table.neighbors()[MAX_NEIGHBORS - 1].mac, SWITCH_MAC);
+    }
+}
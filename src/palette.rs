@@ -0,0 +1,143 @@
+// Maps addresses to colors, so that every host keeps its hue on the packet grid.
+
+use classify::{self, ParseResult, TYPE_COUNT};
+use graphics::Graphics;
+
+/// Kelly's colors of maximum contrast (without black, white and the grays),
+/// as RGB565.
+static PALETTE: [u16; 16] = [
+    0xf600, // #F3C300 vivid yellow
+    0x82b2, // #875692 strong purple
+    0xf420, // #F38400 vivid orange
+    0xa65e, // #A1CAF1 very light blue
+    0xb806, // #BE0032 vivid red
+    0xc590, // #C2B280 grayish yellow
+    0x044a, // #008856 vivid green
+    0xe475, // #E68FAC strong purplish pink
+    0x0334, // #0067A5 strong blue
+    0xfc8f, // #F99379 strong yellowish pink
+    0x6272, // #604E97 strong violet
+    0xf520, // #F6A600 vivid orange yellow
+    0xb22d, // #B3446C strong purplish red
+    0xde80, // #DCD300 vivid greenish yellow
+    0x8da0, // #8DB600 vivid yellowish green
+    0xe2c4, // #E25822 vivid reddish orange
+];
+
+/// Which address determines the hue of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only the protocol base color
+    Protocol,
+    MacSource,
+    /// Falls back to the MAC address for packets without IPv4
+    Ipv4Source,
+    /// Source and destination together, both directions get the same color
+    Conversation,
+    /// The 802.1Q VLAN id, untagged frames keep the protocol base color
+    Vlan
+}
+
+impl ColorMode {
+    pub fn next(&self) -> ColorMode {
+        match *self {
+            ColorMode::Protocol => ColorMode::MacSource,
+            ColorMode::MacSource => ColorMode::Ipv4Source,
+            ColorMode::Ipv4Source => ColorMode::Conversation,
+            ColorMode::Conversation => ColorMode::Vlan,
+            ColorMode::Vlan => ColorMode::Protocol,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ColorMode::Protocol => "proto",
+            ColorMode::MacSource => "MAC src",
+            ColorMode::Ipv4Source => "IP src",
+            ColorMode::Conversation => "pair",
+            ColorMode::Vlan => "VLAN",
+        }
+    }
+}
+
+/// The palette color for an address, always the same for the same bytes.
+pub fn address_color(address: &[u8]) -> u16 {
+    PALETTE[(hash(address) % PALETTE.len() as u32) as usize]
+}
+
+/// The color of a host in the given mode, the same hue its packets are
+/// blended with.
+pub fn host_color(mac: &[u8; 6], ipv4: Option<&[u8; 4]>, mode: ColorMode) -> u16 {
+    match (mode, ipv4) {
+        (ColorMode::Ipv4Source, Some(ip)) => address_color(ip),
+        _ => address_color(mac),
+    }
+}
+
+/// The color a packet is drawn with: its protocol base color blended with
+/// the color of the address selected by `mode`.
+pub fn packet_color(parse_result: &ParseResult, mode: ColorMode) -> u16 {
+    let base = parse_result.pkt_type.color();
+
+    let address_hash = match mode {
+        ColorMode::Protocol => None,
+        ColorMode::MacSource => parse_result.mac_src.map(|mac| hash(&mac)),
+        ColorMode::Ipv4Source => match parse_result.ipv4_src {
+            Some(ip) => Some(hash(&ip)),
+            None => parse_result.mac_src.map(|mac| hash(&mac)),
+        },
+        ColorMode::Conversation => match (parse_result.mac_src, parse_result.mac_dst) {
+            // xor is symmetric, so both directions end up with the same color
+            (Some(src), Some(dst)) => Some(hash(&src) ^ hash(&dst)),
+            _ => None,
+        },
+        ColorMode::Vlan => parse_result.vlan_id.map(|id| hash(&[(id >> 8) as u8, id as u8])),
+    };
+
+    match address_hash {
+        Some(h) => blend(base, PALETTE[(h % PALETTE.len() as u32) as usize]),
+        None => base,
+    }
+}
+
+/// Draws the color mode and a swatch per protocol base color, two columns
+/// wide. Returns the height used.
+pub fn draw_legend(graphics: &mut Graphics, mode: ColorMode, x: u16, y: u16, width: u16) -> u16 {
+    let line_height = graphics.font().line_height();
+    let swatch_len = line_height - 2;
+    let column_width = width / 2;
+    let rows = (TYPE_COUNT as u16 + 1) / 2;
+    let height = (rows + 1) * line_height;
+    Graphics::draw_rect_filled(&mut graphics.lcd, x, y, width, height, 0x0);
+
+    let header = format!("colors: {}", mode.name());
+    graphics.draw_text_in(x, y, width, line_height, &header, 0xaacc, 0x0);
+
+    for index in 0..TYPE_COUNT {
+        let swatch_x = x + (index as u16 % 2) * column_width;
+        let swatch_y = y + (1 + index as u16 / 2) * line_height;
+        Graphics::draw_square_filled(&mut graphics.lcd, swatch_x, swatch_y, swatch_len, classify::type_color(index));
+        // the outline keeps the black swatch visible on the background
+        Graphics::draw_square(&mut graphics.lcd, swatch_x, swatch_y, swatch_len, 0x8410);
+        graphics.draw_text_in(swatch_x + line_height, swatch_y, column_width - line_height, line_height,
+                              classify::type_name(index), 0xffff, 0x0);
+    }
+    height
+}
+
+/// Mixes two RGB565 colors half and half.
+pub fn blend(a: u16, b: u16) -> u16 {
+    // clearing the lowest bit of every channel keeps the halves from
+    // spilling into the neighbouring channel
+    ((a & 0xf7de) >> 1) + ((b & 0xf7de) >> 1)
+}
+
+/// FNV-1a, spreads similar addresses (10.0.0.1, 10.0.0.2) over the palette.
+pub fn hash(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for &byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
@@ -0,0 +1,165 @@
+// What happens when the firmware gives up (or faults, see `exceptions`): the
+// message goes to stderr and onto a red crash screen, then the LED blinks an
+// error pattern. With the `reset_on_panic` cargo feature the board restarts
+// after a few blinks.
+//
+// The `panic_fmt` lang item belongs to the board support crate, which knows
+// nothing of our buffered stdout and stderr or the LCD. The errors we can't
+// recover from go through `fatal!` instead.
+
+use core::{fmt, ptr, str};
+
+use font;
+use graphics::{self, Graphics};
+use semi_hosting;
+
+/// `panic!` for our own code, reports the message with the output buffered
+/// before it and on the crash screen.
+macro_rules! fatal {
+    ($($arg:tt)*) => ($crate::panic::on_panic(format_args!($($arg)*), file!(), line!()))
+}
+
+const CRASH_COLOR: u16 = 0xf800;
+const TEXT_COLOR: u16 = 0xffff;
+const MARGIN: u16 = 10;
+
+// the LED on PortI Pin1, written directly because the `Gpio` is owned by
+// `main`
+const GPIOI_BSRR: *mut u32 = (0x4002_2000 + 0x18) as *mut u32;
+const LED_PIN: u32 = 1;
+
+// the same as the `reset` macro in .gdbinit: SYSRESETREQ in the AIRCR
+const AIRCR: *mut u32 = 0xe000_ed0c as *mut u32;
+const AIRCR_SYSRESETREQ: u32 = 0x05fa_0004;
+#[cfg(feature = "reset_on_panic")]
+const BLINKS_BEFORE_RESET: Option<u32> = Some(5);
+#[cfg(not(feature = "reset_on_panic"))]
+const BLINKS_BEFORE_RESET: Option<u32> = None;
+
+// the SysTick may be dead, so the blinking is timed by busy loops, roughly
+// calibrated for 216 MHz
+const LOOPS_PER_MS: u32 = 216_000 / 4;
+
+static mut GRAPHICS: *mut Graphics = 0 as *mut Graphics;
+static mut CRASHED: bool = false;
+
+/// Lets the panic handler draw the crash screen. `graphics` must live until
+/// the end, which it does as it is created in the never returning `main`.
+pub fn set_graphics(graphics: &mut Graphics) {
+    unsafe { GRAPHICS = graphics as *mut Graphics; }
+}
+
+pub fn on_panic(msg: fmt::Arguments, file: &'static str, line: u32) -> ! {
+    if unsafe { CRASHED } {
+        blink_forever();
+    }
+    // the output before the panic may still be buffered, it explains what
+    // led up to it
+    semi_hosting::flush_on_crash();
+    println_err!("panicked at '{}', {}:{}", msg, file, line);
+    crash("panic", format_args!("{}:{}\n\n{}", file, line, msg));
+}
+
+/// Shows `text` on the crash screen and blinks the LED until the board is
+/// reset. Also used by the fault handlers.
+pub fn crash(title: &str, text: fmt::Arguments) -> ! {
+    // crashing while drawing the crash screen, just blink
+    if unsafe { CRASHED } {
+        blink_forever();
+    }
+    unsafe { CRASHED = true; }
+
+    semi_hosting::flush_on_crash();
+    if let Some(graphics) = unsafe { GRAPHICS.as_mut() } {
+        draw_crash_screen(graphics, title, text);
+    }
+    blink_forever();
+}
+
+fn draw_crash_screen(graphics: &mut Graphics, title: &str, text: fmt::Arguments) {
+    use core::fmt::Write;
+
+    Graphics::draw_rect_filled(&mut graphics.lcd, 0, 0, graphics::WIDTH, graphics::HEIGHT, CRASH_COLOR);
+
+    graphics.set_font(font::LARGE);
+    let (_, y) = graphics.draw_text(MARGIN, MARGIN, title, TEXT_COLOR, CRASH_COLOR);
+
+    // the heap might be what broke, so no `format!`
+    let mut buffer = TextBuffer::new();
+    let _ = buffer.write_fmt(text);
+
+    graphics.set_font(font::SMALL);
+    let y = y + graphics.font().line_height() + MARGIN;
+    graphics.draw_text_in(MARGIN, y, graphics::WIDTH - 2 * MARGIN, graphics::HEIGHT - y - MARGIN,
+                          buffer.as_str(), TEXT_COLOR, CRASH_COLOR);
+}
+
+/// Three short and one long blink, repeated.
+fn blink_forever() -> ! {
+    let mut blinks = 0u32;
+    loop {
+        for _ in 0..3 {
+            set_led(true);
+            delay_ms(150);
+            set_led(false);
+            delay_ms(150);
+        }
+        set_led(true);
+        delay_ms(600);
+        set_led(false);
+        delay_ms(1000);
+
+        blinks = blinks.wrapping_add(1);
+        if Some(blinks) == BLINKS_BEFORE_RESET {
+            reset();
+        }
+    }
+}
+
+fn set_led(on: bool) {
+    // the lower half of BSRR sets pins, the upper half resets them
+    let bit = if on { 1 << LED_PIN } else { 1 << (LED_PIN + 16) };
+    unsafe { ptr::write_volatile(GPIOI_BSRR, bit); }
+}
+
+fn delay_ms(ms: u32) {
+    for _ in 0..ms * LOOPS_PER_MS {
+        unsafe { asm!("nop" :::: "volatile"); }
+    }
+}
+
+fn reset() {
+    semi_hosting::flush();
+    unsafe { ptr::write_volatile(AIRCR, AIRCR_SYSRESETREQ); }
+}
+
+/// A fixed size `fmt::Write` target, text that doesn't fit is dropped.
+struct TextBuffer {
+    data: [u8; 512],
+    len: usize
+}
+
+impl TextBuffer {
+    fn new() -> Self {
+        TextBuffer { data: [0; 512], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // only whole characters are copied in
+        str::from_utf8(&self.data[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for TextBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.len + len > self.data.len() {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.data[self.len..]);
+            self.len += len;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+// Writes the received frames to a pcapng file on the host, the blocks are
+// put together in `pcapng_blocks`.
+
+use pcapng_blocks;
+use semi_hosting::{Error, File};
+
+/// The host file, relative to the directory gdb was started in.
+pub const CAPTURE_FILE: &'static str = "capture.pcapng";
+
+/// Frames received while the capture is running are appended to
+/// `CAPTURE_FILE`.
+pub struct Capture {
+    file: Option<File>,
+    frames: u32,
+    failed_writes: u32
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Capture {
+            file: None,
+            frames: 0,
+            failed_writes: 0
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Frames written since the capture was started.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Truncates `CAPTURE_FILE` and writes the section and interface headers.
+    /// Without them the file can't be opened, so the capture doesn't start
+    /// if they can't be written.
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.is_running() {
+            return Ok(());
+        }
+        let mut file = File::create(CAPTURE_FILE)?;
+        pcapng_blocks::write_headers(&mut |data: &[u8]| file.write_all(data))?;
+        self.file = Some(file);
+        self.frames = 0;
+        self.failed_writes = 0;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        // dropping the file closes it
+        if self.file.take().is_some() {
+            info!("capture stopped, {} frames written to {} ({} failed writes)",
+                  self.frames, CAPTURE_FILE, self.failed_writes);
+        }
+    }
+
+    /// Appends `data` as an enhanced packet block, does nothing if the
+    /// capture isn't running.
+    pub fn write_frame(&mut self, ticks: usize, data: &[u8]) {
+        if let Some(ref mut file) = self.file {
+            if pcapng_blocks::write_packet(&mut |data: &[u8]| file.write_all(data), ticks, data).is_err() {
+                self.failed_writes = self.failed_writes.wrapping_add(1);
+            }
+            self.frames = self.frames.wrapping_add(1);
+        }
+    }
+}
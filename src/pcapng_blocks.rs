@@ -0,0 +1,150 @@
+// The pcapng blocks of a capture, apart from `pcapng` so that they build on
+// the host. See https://github.com/pcapng/pcapng for the format.
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const OPTION_END: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+// the ticks are milliseconds, 10^-3 seconds
+const TSRESOL_MILLISECONDS: u8 = 3;
+
+/// Writes the section header and the description of the one interface the
+/// packets are from to `sink`.
+pub fn write_headers<F, E>(sink: &mut F) -> Result<(), E> where F: FnMut(&[u8]) -> Result<(), E> {
+    let mut section = [0; 28];
+    put_u32(&mut section, 0, SECTION_HEADER_BLOCK);
+    put_u32(&mut section, 4, 28);
+    put_u32(&mut section, 8, BYTE_ORDER_MAGIC);
+    put_u16(&mut section, 12, 1);
+    put_u16(&mut section, 14, 0);
+    // the section length is unknown
+    put_u32(&mut section, 16, 0xffff_ffff);
+    put_u32(&mut section, 20, 0xffff_ffff);
+    put_u32(&mut section, 24, 28);
+    sink(&section)?;
+
+    let mut interface = [0; 32];
+    put_u32(&mut interface, 0, INTERFACE_DESCRIPTION_BLOCK);
+    put_u32(&mut interface, 4, 32);
+    put_u16(&mut interface, 8, LINKTYPE_ETHERNET);
+    // reserved, then snaplen 0 for no limit
+    put_u16(&mut interface, 10, 0);
+    put_u32(&mut interface, 12, 0);
+    put_u16(&mut interface, 16, OPTION_IF_TSRESOL);
+    put_u16(&mut interface, 18, 1);
+    interface[20] = TSRESOL_MILLISECONDS;
+    put_u16(&mut interface, 24, OPTION_END);
+    put_u16(&mut interface, 26, 0);
+    put_u32(&mut interface, 28, 32);
+    sink(&interface)
+}
+
+/// Writes `data` received at `ticks` as an enhanced packet block to `sink`.
+pub fn write_packet<F, E>(sink: &mut F, ticks: usize, data: &[u8]) -> Result<(), E>
+    where F: FnMut(&[u8]) -> Result<(), E> {
+    let padding = (4 - data.len() % 4) % 4;
+    let total_len = (32 + data.len() + padding) as u32;
+    let timestamp = ticks as u64;
+
+    let mut header = [0; 28];
+    put_u32(&mut header, 0, ENHANCED_PACKET_BLOCK);
+    put_u32(&mut header, 4, total_len);
+    // interface 0, the one from `write_headers`
+    put_u32(&mut header, 8, 0);
+    put_u32(&mut header, 12, (timestamp >> 32) as u32);
+    put_u32(&mut header, 16, timestamp as u32);
+    put_u32(&mut header, 20, data.len() as u32);
+    put_u32(&mut header, 24, data.len() as u32);
+
+    // the padding and the trailing copy of the length
+    let mut trailer = [0; 7];
+    put_u32(&mut trailer, padding, total_len);
+
+    sink(&header)?;
+    sink(data)?;
+    sink(&trailer[..padding + 4])
+}
+
+// pcapng files are written in the byte order of the writer, which is
+// little endian on the Cortex-M7
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset] = value as u8;
+    buf[offset + 1] = (value >> 8) as u8;
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    put_u16(buf, offset, value as u16);
+    put_u16(buf, offset + 2, (value >> 16) as u16);
+}
+
+#[cfg(test)]
+mod tests {
+    use collections::Vec;
+    use super::{write_headers, write_packet};
+
+    fn append(bytes: &mut Vec<u8>, data: &[u8]) -> Result<(), ()> {
+        bytes.extend_from_slice(data);
+        Ok(())
+    }
+
+    #[test]
+    fn headers() {
+        let mut bytes = Vec::new();
+        write_headers(&mut |data: &[u8]| append(&mut bytes, data)).unwrap();
+        assert_eq!(bytes, vec![
+            // section header block, 28 bytes, byte order magic, version 1.0
+            0x0a, 0x0d, 0x0d, 0x0a, 28, 0, 0, 0, 0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0,
+            // unknown section length, the length again
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 28, 0, 0, 0,
+            // interface description block, 32 bytes, ethernet, no snap length
+            1, 0, 0, 0, 32, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+            // if_tsresol of 10^-3 padded to 4 bytes, end of options, the length again
+            9, 0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn packet_is_padded() {
+        let data = [0xaa; 13];
+        let mut bytes = Vec::new();
+        write_packet(&mut |d: &[u8]| append(&mut bytes, d), 0x1_0000_0002, &data).unwrap();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(&bytes[..28], &[
+            // enhanced packet block, 48 bytes, interface 0
+            6, 0, 0, 0, 48, 0, 0, 0, 0, 0, 0, 0,
+            // the timestamp, high word first
+            1, 0, 0, 0, 2, 0, 0, 0,
+            // captured and original length
+            13, 0, 0, 0, 13, 0, 0, 0,
+        ][..]);
+        assert_eq!(&bytes[28..41], &data[..]);
+        // three bytes of padding and the length again
+        assert_eq!(&bytes[41..], &[0, 0, 0, 48, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn aligned_packet_has_no_padding() {
+        let data = [0x55; 60];
+        let mut bytes = Vec::new();
+        write_packet(&mut |d: &[u8]| append(&mut bytes, d), 1234, &data).unwrap();
+        assert_eq!(bytes.len(), 92);
+        assert_eq!(&bytes[4..8], &[92, 0, 0, 0]);
+        assert_eq!(&bytes[16..20], &[0xd2, 0x04, 0, 0]);
+        assert_eq!(&bytes[88..], &[92, 0, 0, 0]);
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let mut writes = 0;
+        let result = write_packet(&mut |_: &[u8]| {
+            writes += 1;
+            Err("disk full")
+        }, 0, &[0; 10]);
+        assert_eq!(result, Err("disk full"));
+        assert_eq!(writes, 1);
+    }
+}
@@ -0,0 +1,159 @@
+// The byte queue behind stdout and stderr.
+
+pub const CAPACITY: usize = 256;
+
+/// A fixed size FIFO of bytes that can live in a `static`.
+pub struct RingBuffer {
+    data: [u8; CAPACITY],
+    // index of the oldest byte
+    start: usize,
+    len: usize
+}
+
+impl RingBuffer {
+    pub const fn new() -> Self {
+        RingBuffer {
+            data: [0; CAPACITY],
+            start: 0,
+            len: 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == CAPACITY
+    }
+
+    /// Appends a byte, returns `false` if the buffer is full.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let end = (self.start + self.len) % CAPACITY;
+        self.data[end] = byte;
+        self.len += 1;
+        true
+    }
+
+    /// The oldest bytes that are stored in one piece. If the content wraps
+    /// around the end of the array, the rest follows after `consume`.
+    pub fn front(&self) -> &[u8] {
+        let end = if self.start + self.len > CAPACITY { CAPACITY } else { self.start + self.len };
+        &self.data[self.start..end]
+    }
+
+    /// Drops the `count` oldest bytes.
+    pub fn consume(&mut self, count: usize) {
+        let count = if count > self.len { self.len } else { count };
+        self.start = (self.start + count) % CAPACITY;
+        self.len -= count;
+        if self.len == 0 {
+            // keeps the next lines in one piece
+            self.start = 0;
+        }
+    }
+
+    /// Moves the oldest bytes that are stored in one piece into `out`, as
+    /// many as fit. Returns how many were moved, 0 once the buffer is empty.
+    pub fn pop(&mut self, out: &mut [u8]) -> usize {
+        let count = {
+            let front = self.front();
+            let count = if front.len() > out.len() { out.len() } else { front.len() };
+            out[..count].copy_from_slice(&front[..count]);
+            count
+        };
+        self.consume(count);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RingBuffer, CAPACITY};
+
+    fn drained(buffer: &mut RingBuffer, chunk_len: usize) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        let mut chunk = vec![0; chunk_len];
+        loop {
+            let count = buffer.pop(&mut chunk);
+            if count == 0 {
+                return chunks;
+            }
+            chunks.push(chunk[..count].to_vec());
+        }
+    }
+
+    #[test]
+    fn keeps_the_order() {
+        let mut buffer = RingBuffer::new();
+        for &byte in b"hello" {
+            assert!(buffer.push(byte));
+        }
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(drained(&mut buffer, 64), vec![b"hello".to_vec()]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn refuses_bytes_when_full() {
+        let mut buffer = RingBuffer::new();
+        for i in 0..CAPACITY {
+            assert!(buffer.push(i as u8));
+        }
+        assert!(buffer.is_full());
+        assert!(!buffer.push(0));
+        assert_eq!(buffer.len(), CAPACITY);
+    }
+
+    #[test]
+    fn wraps_around_in_two_pieces() {
+        let mut buffer = RingBuffer::new();
+        for i in 0..CAPACITY {
+            buffer.push(i as u8);
+        }
+        buffer.consume(CAPACITY - 2);
+        buffer.push(b'a');
+        buffer.push(b'b');
+
+        let chunks = drained(&mut buffer, 64);
+        assert_eq!(chunks, vec![vec![(CAPACITY - 2) as u8, (CAPACITY - 1) as u8], b"ab".to_vec()]);
+    }
+
+    #[test]
+    fn pops_in_chunks() {
+        let mut buffer = RingBuffer::new();
+        for &byte in b"hello world" {
+            buffer.push(byte);
+        }
+        assert_eq!(drained(&mut buffer, 4), vec![b"hell".to_vec(), b"o wo".to_vec(), b"rld".to_vec()]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn starts_over_when_emptied() {
+        let mut buffer = RingBuffer::new();
+        buffer.push(b'x');
+        buffer.consume(1);
+        for _ in 0..CAPACITY {
+            buffer.push(b'y');
+        }
+        // one piece, not wrapped
+        assert_eq!(buffer.front().len(), CAPACITY);
+    }
+
+    #[test]
+    fn consume_is_clamped() {
+        let mut buffer = RingBuffer::new();
+        buffer.push(b'x');
+        buffer.consume(10);
+        assert!(buffer.is_empty());
+        assert!(buffer.front().is_empty());
+    }
+}
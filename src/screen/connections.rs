@@ -0,0 +1,136 @@
+use core::cmp;
+use collections::string::String;
+
+use addr;
+use classify::ParseResult;
+use conntrack::{Connection, Counter, State};
+use context::{Context, TICKS_PER_SECOND};
+use graphics::{self, Button, Graphics};
+use super::{Screen, CONTENT_Y};
+
+const MARGIN: u16 = 4;
+const BUTTON_HEIGHT: u16 = 24;
+const BUTTON_Y: u16 = graphics::HEIGHT - BUTTON_HEIGHT - 2;
+const BUTTON_COLOR: u16 = 0x8410;
+const HEADER_COLOR: u16 = 0xaacc;
+
+const UP_BUTTON: usize = 0;
+const DOWN_BUTTON: usize = 1;
+
+/// The TCP connections in the `ConnectionTable`, newest first.
+pub struct ConnectionsScreen {
+    buttons: [Button; 2],
+    // index of the topmost connection shown
+    scroll: usize,
+    last_draw: usize
+}
+
+impl ConnectionsScreen {
+    pub fn new() -> Self {
+        let button = |index: u16, label| Button::LabelButton {
+            x: MARGIN + index * 120,
+            y: BUTTON_Y,
+            width: 110,
+            height: BUTTON_HEIGHT,
+            color: BUTTON_COLOR,
+            label: label
+        };
+        ConnectionsScreen {
+            buttons: [button(0, "Up"), button(1, "Down")],
+            scroll: 0,
+            last_draw: 0
+        }
+    }
+
+    fn rows(graphics: &Graphics) -> usize {
+        let list_y = CONTENT_Y + MARGIN + 2 * graphics.font().line_height();
+        ((BUTTON_Y - list_y) / graphics.font().line_height()) as usize
+    }
+
+    fn format_connection(connection: &Connection) -> String {
+        let rtt = match connection.handshake_rtt {
+            Some(rtt) => format!("{}ms", rtt * 1000 / TICKS_PER_SECOND),
+            None => String::from("-"),
+        };
+        format!("{:21} {:21} {:12} {:>5} {:>8} {:>8}",
+                addr::format_endpoint(&connection.client), addr::format_endpoint(&connection.server),
+                connection.state.name(), rtt,
+                format_counter(&connection.from_client), format_counter(&connection.from_server))
+    }
+
+    fn draw_content(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.last_draw = context.ticks;
+        let line_height = graphics.font().line_height();
+        Graphics::draw_rect_filled(&mut graphics.lcd, 0, CONTENT_Y, graphics::WIDTH, BUTTON_Y - CONTENT_Y, 0x0);
+
+        let connections = context.connections.connections();
+        self.scroll = cmp::min(self.scroll, connections.len().saturating_sub(1));
+
+        let header = format!("{} TCP connections, {} evicted\n{:21} {:21} {:12} {:>5} {:>8} {:>8}",
+                             connections.len(), context.connections.evicted(),
+                             "client", "server", "state", "rtt", "sent", "received");
+        graphics.draw_text(MARGIN, CONTENT_Y + MARGIN, &header, HEADER_COLOR, 0x0);
+
+        let list_y = CONTENT_Y + MARGIN + 2 * line_height;
+        for (row, connection) in connections.iter().rev().skip(self.scroll).take(Self::rows(graphics)).enumerate() {
+            let text = Self::format_connection(connection);
+            let y = list_y + row as u16 * line_height;
+            graphics.draw_text_in(MARGIN, y, graphics::WIDTH - 2 * MARGIN, line_height,
+                                  &text, state_color(connection.state), 0x0);
+        }
+    }
+}
+
+impl Screen for ConnectionsScreen {
+    fn name(&self) -> &'static str {
+        "TCP"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        for button in self.buttons.iter() {
+            button.draw(graphics);
+        }
+        self.draw_content(graphics, context);
+    }
+
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, x: u16, y: u16) -> bool {
+        let rows = Self::rows(graphics);
+        match self.buttons.iter().position(|b| b.touched_inside(x, y)) {
+            Some(UP_BUTTON) => self.scroll = self.scroll.saturating_sub(rows),
+            Some(DOWN_BUTTON) => self.scroll += rows,
+            _ => return false,
+        }
+        self.draw_content(graphics, context);
+        false
+    }
+
+    fn on_packet(&mut self, _graphics: &mut Graphics, _context: &Context, _parse_result: &ParseResult, _visible: bool) {}
+
+    fn tick(&mut self, graphics: &mut Graphics, context: &Context, ticks: usize) {
+        // busy connections change with every frame, a redraw per second is enough
+        if ticks.wrapping_sub(self.last_draw) >= TICKS_PER_SECOND {
+            self.draw_content(graphics, context);
+        }
+    }
+}
+
+fn state_color(state: State) -> u16 {
+    match state {
+        State::SynSent | State::SynReceived => 0xffe0,
+        State::Established => 0x07e0,
+        State::Closing | State::Closed => 0x8410,
+        State::Reset => 0xf800,
+    }
+}
+
+/// `12/3k`, packets and bytes
+fn format_counter(counter: &Counter) -> String {
+    let bytes = counter.bytes;
+    if bytes < 10 * 1000 {
+        format!("{}/{}", counter.packets, bytes)
+    } else if bytes < 10 * 1000 * 1000 {
+        format!("{}/{}k", counter.packets, bytes / 1000)
+    } else {
+        format!("{}/{}M", counter.packets, bytes / (1000 * 1000))
+    }
+}
@@ -0,0 +1,108 @@
+use collections::vec::Vec;
+
+use classify::{ParseResult, ParseResultDirection, TYPE_COUNT};
+use context::Context;
+use graphics::{self, Graphics};
+use palette;
+use super::{Screen, CONTENT_Y, CONTENT_HEIGHT};
+
+const SQUARE_LEN: u16 = 25;
+// the right part of the screen shows the color legend and the recent DNS
+// lookups below it
+const GRID_WIDTH: u16 = 325;
+const PANEL_X: u16 = GRID_WIDTH + 5;
+const COLUMNS: u16 = GRID_WIDTH / SQUARE_LEN;
+const ROWS: u16 = CONTENT_HEIGHT / SQUARE_LEN;
+
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    color: u16,
+    // requests are drawn as outlines, responses (and everything without a
+    // direction) as filled squares
+    outlined: bool
+}
+
+/// One square per packet, filling the grid row by row and starting over at
+/// the top when it is full.
+pub struct GridScreen {
+    cells: Vec<Option<Cell>>,
+    next: usize
+}
+
+impl GridScreen {
+    pub fn new() -> Self {
+        GridScreen {
+            cells: vec![None; (COLUMNS * ROWS) as usize],
+            next: 0
+        }
+    }
+
+    fn draw_cell(graphics: &mut Graphics, index: usize, cell: Option<Cell>) {
+        let x = (index as u16 % COLUMNS) * SQUARE_LEN;
+        let y = CONTENT_Y + (index as u16 / COLUMNS) * SQUARE_LEN;
+        match cell {
+            Some(Cell { color, outlined: true }) => {
+                Graphics::draw_square_filled(&mut graphics.lcd, x, y, SQUARE_LEN, 0x0);
+                Graphics::draw_square(&mut graphics.lcd, x, y, SQUARE_LEN, color);
+            }
+            Some(Cell { color, outlined: false }) => {
+                Graphics::draw_square_filled(&mut graphics.lcd, x, y, SQUARE_LEN, color);
+            }
+            None => Graphics::draw_square_filled(&mut graphics.lcd, x, y, SQUARE_LEN, 0x0),
+        }
+    }
+
+    fn legend_height(graphics: &Graphics) -> u16 {
+        // mode line, the rows of swatches and a blank line
+        (2 + (TYPE_COUNT as u16 + 1) / 2) * graphics.font().line_height()
+    }
+
+    fn draw_legend(graphics: &mut Graphics, context: &Context) {
+        palette::draw_legend(graphics, context.settings.color_mode, PANEL_X, CONTENT_Y, graphics::WIDTH - PANEL_X);
+    }
+
+    fn draw_lookups(graphics: &mut Graphics, context: &Context) {
+        let legend_height = Self::legend_height(graphics);
+        context.recent_lookups.draw(graphics, PANEL_X, CONTENT_Y + legend_height,
+                                    graphics::WIDTH - PANEL_X, CONTENT_HEIGHT - legend_height);
+    }
+}
+
+impl Screen for GridScreen {
+    fn name(&self) -> &'static str {
+        "Packets"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        for (index, cell) in self.cells.iter().enumerate() {
+            Self::draw_cell(graphics, index, *cell);
+        }
+        Self::draw_legend(graphics, context);
+        Self::draw_lookups(graphics, context);
+    }
+
+    fn on_touch(&mut self, _graphics: &mut Graphics, _context: &mut Context, _x: u16, _y: u16) -> bool {
+        false
+    }
+
+    fn on_packet(&mut self, graphics: &mut Graphics, context: &Context, parse_result: &ParseResult, visible: bool) {
+        if context.settings.paused {
+            return;
+        }
+
+        let cell = Cell {
+            color: palette::packet_color(parse_result, context.settings.color_mode),
+            outlined: parse_result.direction == Some(ParseResultDirection::Request)
+        };
+        let index = self.next;
+        self.cells[index] = Some(cell);
+        self.next = (index + 1) % self.cells.len();
+
+        if visible {
+            Self::draw_cell(graphics, index, Some(cell));
+            if parse_result.hostname.is_some() && parse_result.direction == Some(ParseResultDirection::Request) {
+                Self::draw_lookups(graphics, context);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use core::cmp;
+use collections::string::String;
+use collections::vec::Vec;
+
+use addr;
+use classify::{self, ParseResult, TYPE_COUNT};
+use context::{format_age, Context, TICKS_PER_SECOND};
+use graphics::{self, Button, Graphics};
+use hosts::Host;
+use palette;
+use super::{Screen, CONTENT_Y};
+
+const MARGIN: u16 = 4;
+const BUTTON_HEIGHT: u16 = 24;
+const BUTTON_Y: u16 = graphics::HEIGHT - BUTTON_HEIGHT - 2;
+const BUTTON_COLOR: u16 = 0x8410;
+const TEXT_COLOR: u16 = 0xffff;
+const HEADER_COLOR: u16 = 0xaacc;
+
+// the hosts are aged out after one of these, in seconds, `None` is never
+const TIMEOUTS: [Option<usize>; 4] = [Some(60), Some(5 * 60), Some(30 * 60), None];
+
+const SORT_BUTTON: usize = 0;
+const TIMEOUT_BUTTON: usize = 1;
+const UP_BUTTON: usize = 2;
+const DOWN_BUTTON: usize = 3;
+
+/// A scrollable list of all hosts in the `HostTable`.
+pub struct HostsScreen {
+    buttons: [Button; 4],
+    // index of the topmost host shown
+    scroll: usize,
+    last_draw: usize,
+    // what is on the screen, to redraw only the rows that changed
+    drawn_header: String,
+    drawn_rows: Vec<Option<(String, u16)>>
+}
+
+impl HostsScreen {
+    pub fn new() -> Self {
+        let button = |index: u16, label| Button::LabelButton {
+            x: MARGIN + index * 120,
+            y: BUTTON_Y,
+            width: 110,
+            height: BUTTON_HEIGHT,
+            color: BUTTON_COLOR,
+            label: label
+        };
+        HostsScreen {
+            buttons: [button(0, "Sort"), button(1, "Timeout"), button(2, "Up"), button(3, "Down")],
+            scroll: 0,
+            last_draw: 0,
+            drawn_header: String::new(),
+            drawn_rows: Vec::new()
+        }
+    }
+
+    fn rows(graphics: &Graphics) -> usize {
+        let list_y = CONTENT_Y + MARGIN + 2 * graphics.font().line_height();
+        ((BUTTON_Y - list_y) / graphics.font().line_height()) as usize
+    }
+
+    fn format_host(host: &Host, ticks: usize) -> String {
+        let mut protocols = String::with_capacity(TYPE_COUNT);
+        for index in 0..TYPE_COUNT {
+            protocols.push(if host.used_protocol(index) { classify::type_letter(index) } else { '.' });
+        }
+        let ip = host.ipv4.as_ref().map(addr::format_ipv4).unwrap_or_else(String::new);
+        format!("{:17} {:15} {:>6} {:>4} {} {}",
+                addr::format_mac(&host.mac), ip, host.frames,
+                format_age(ticks.wrapping_sub(host.last_seen)), protocols,
+                host.name.as_ref().map(|n| n.as_str()).unwrap_or(""))
+    }
+
+    fn draw_content(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.last_draw = context.ticks;
+        let line_height = graphics.font().line_height();
+        let text_width = graphics::WIDTH - 2 * MARGIN - line_height;
+        // padded to the width, so the lines overwrite longer ones before
+        let columns = (text_width / graphics.font().char_width()) as usize;
+
+        let hosts = context.hosts.sorted();
+        self.scroll = cmp::min(self.scroll, hosts.len().saturating_sub(1));
+
+        let timeout = match context.hosts.timeout() {
+            Some(timeout) => format_age(timeout),
+            None => String::from("never"),
+        };
+        // the protocol column has a letter per type
+        let header = format!("{:columns$}\n{:17} {:15} {:>6} {:>4} {:proto_width$} {}",
+                             format!("{} hosts, sorted by {}, timeout {}", hosts.len(), context.hosts.order().name(), timeout),
+                             "MAC", "IPv4", "frames", "age", "proto", "name",
+                             columns = columns, proto_width = TYPE_COUNT);
+        if header != self.drawn_header {
+            graphics.draw_text_in(MARGIN + line_height, CONTENT_Y + MARGIN, text_width, 2 * line_height,
+                                  &header, HEADER_COLOR, 0x0);
+            self.drawn_header = header;
+        }
+
+        // each row starts with the color the host's packets are blended with
+        let list_y = CONTENT_Y + MARGIN + 2 * line_height;
+        let mode = context.settings.color_mode;
+        let rows = Self::rows(graphics);
+        self.drawn_rows.resize(rows, None);
+        for row in 0..rows {
+            let new = hosts.get(self.scroll + row).map(|host| {
+                (format!("{:1$}", Self::format_host(host, context.ticks), columns),
+                 palette::host_color(&host.mac, host.ipv4.as_ref(), mode))
+            });
+            if new == self.drawn_rows[row] {
+                continue;
+            }
+            let y = list_y + row as u16 * line_height;
+            match new {
+                Some((ref text, color)) => {
+                    Graphics::draw_square_filled(&mut graphics.lcd, MARGIN, y, line_height - 2, color);
+                    graphics.draw_text_in(MARGIN + line_height, y, text_width, line_height, text, TEXT_COLOR, 0x0);
+                }
+                None => Graphics::draw_rect_filled(&mut graphics.lcd, 0, y, graphics::WIDTH, line_height, 0x0),
+            }
+            self.drawn_rows[row] = new;
+        }
+    }
+}
+
+impl Screen for HostsScreen {
+    fn name(&self) -> &'static str {
+        "Hosts"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        // the screen was cleared
+        self.drawn_header.clear();
+        self.drawn_rows.clear();
+        for button in self.buttons.iter() {
+            button.draw(graphics);
+        }
+        self.draw_content(graphics, context);
+    }
+
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, x: u16, y: u16) -> bool {
+        let rows = Self::rows(graphics);
+        match self.buttons.iter().position(|b| b.touched_inside(x, y)) {
+            Some(SORT_BUTTON) => {
+                let order = context.hosts.order().next();
+                context.hosts.set_order(order);
+            }
+            Some(TIMEOUT_BUTTON) => {
+                let current = context.hosts.timeout().map(|t| t / TICKS_PER_SECOND);
+                let index = TIMEOUTS.iter().position(|&t| t == current).unwrap_or(0);
+                let next = TIMEOUTS[(index + 1) % TIMEOUTS.len()];
+                context.hosts.set_timeout(next.map(|t| t * TICKS_PER_SECOND));
+            }
+            Some(UP_BUTTON) => self.scroll = self.scroll.saturating_sub(rows),
+            Some(DOWN_BUTTON) => self.scroll += rows,
+            _ => return false,
+        }
+        self.draw_content(graphics, context);
+        false
+    }
+
+    fn on_packet(&mut self, _graphics: &mut Graphics, _context: &Context, _parse_result: &ParseResult, _visible: bool) {}
+
+    fn tick(&mut self, graphics: &mut Graphics, context: &Context, ticks: usize) {
+        // the ages change every second
+        if ticks.wrapping_sub(self.last_draw) >= TICKS_PER_SECOND {
+            self.draw_content(graphics, context);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use collections::boxed::Box;
+use collections::string::String;
+use collections::vec::Vec;
+use stm32f7::touch;
+use i2c::I2C;
+
+use addr;
+use classify::ParseResult;
+use context::{format_age, Context, TICKS_PER_SECOND};
+use dhcp_client::Source;
+use font;
+use graphics::{self, Button, Graphics};
+
+pub mod connections;
+pub mod grid;
+pub mod hosts;
+pub mod neighbors;
+pub mod settings;
+pub mod sound;
+pub mod stats;
+
+pub const TAB_BAR_HEIGHT: u16 = 20;
+/// Our address and the DHCP lease, in the small font below the tab bar.
+pub const STATUS_BAR_HEIGHT: u16 = 10;
+/// Screens draw below the status bar, into the rest of the LCD.
+pub const CONTENT_Y: u16 = TAB_BAR_HEIGHT + 2 + STATUS_BAR_HEIGHT;
+pub const CONTENT_HEIGHT: u16 = graphics::HEIGHT - CONTENT_Y;
+
+const TAB_COLOR: u16 = 0x8410;
+const ACTIVE_TAB_COLOR: u16 = 0xffff;
+const STATUS_COLOR: u16 = 0xc618;
+// no address at all
+const STATUS_WARNING_COLOR: u16 = 0xfd20;
+
+// the touch controller is polled over I2C, don't do that on every loop
+const TOUCH_POLL_TICKS: usize = 30;
+
+pub trait Screen {
+    /// The name shown on the tab bar.
+    fn name(&self) -> &'static str;
+
+    /// Redraws the whole content area. Called when the screen becomes visible.
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context);
+
+    /// Called for every new touch inside the content area of the visible
+    /// screen. Returns true if the tab bar and the screen need to be redrawn.
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, x: u16, y: u16) -> bool;
+
+    /// Called for every received packet, after `context` is updated. Screens
+    /// may only draw if `visible` is set.
+    fn on_packet(&mut self, graphics: &mut Graphics, context: &Context, parse_result: &ParseResult, visible: bool);
+
+    /// Called on every main loop iteration while the screen is visible.
+    fn tick(&mut self, _graphics: &mut Graphics, _context: &Context, _ticks: usize) {}
+}
+
+/// All screens plus the tab bar to switch between them.
+pub struct Screens {
+    screens: Vec<Box<Screen>>,
+    tabs: Vec<Button>,
+    active: usize,
+    // a touch only counts once, when the finger goes down
+    touching: bool,
+    last_touch_poll: usize,
+    // the lease counts down, the status bar is redrawn every second
+    last_status_draw: usize
+}
+
+impl Screens {
+    pub fn new(screens: Vec<Box<Screen>>) -> Self {
+        let tab_width = graphics::WIDTH / screens.len() as u16;
+        let tabs = screens.iter().enumerate().map(|(i, screen)| {
+            Button::LabelButton {
+                x: i as u16 * tab_width,
+                y: 0,
+                width: tab_width - 2,
+                height: TAB_BAR_HEIGHT,
+                color: TAB_COLOR,
+                label: screen.name()
+            }
+        }).collect();
+
+        Screens {
+            screens: screens,
+            tabs: tabs,
+            active: 0,
+            touching: false,
+            last_touch_poll: 0,
+            last_status_draw: 0
+        }
+    }
+
+    /// Redraws the tab bar and the visible screen.
+    pub fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        graphics.clear_screen();
+        self.draw_tabs(graphics);
+        self.draw_status(graphics, context);
+        self.screens[self.active].draw(graphics, context);
+    }
+
+    pub fn active_name(&self) -> &'static str {
+        self.screens[self.active].name()
+    }
+
+    pub fn switch_to(&mut self, graphics: &mut Graphics, context: &Context, index: usize) {
+        if index < self.screens.len() && index != self.active {
+            self.active = index;
+            self.draw(graphics, context);
+        }
+    }
+
+    /// Switches to the screen with the given (case insensitive) name. Returns
+    /// false if there is no such screen.
+    pub fn switch_to_name(&mut self, graphics: &mut Graphics, context: &Context, name: &str) -> bool {
+        let index = self.screens.iter().position(|s| eq_ignore_case(s.name(), name));
+        match index {
+            Some(index) => {
+                self.switch_to(graphics, context, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn on_packet(&mut self, graphics: &mut Graphics, context: &Context, parse_result: &ParseResult) {
+        let active = self.active;
+        for (i, screen) in self.screens.iter_mut().enumerate() {
+            screen.on_packet(graphics, context, parse_result, i == active);
+        }
+    }
+
+    /// Polls the touch screen and ticks the visible screen.
+    pub fn tick(&mut self, graphics: &mut Graphics, context: &mut Context, i2c_3: &mut I2C, ticks: usize) {
+        if ticks.wrapping_sub(self.last_touch_poll) >= TOUCH_POLL_TICKS {
+            self.last_touch_poll = ticks;
+            self.poll_touch(graphics, context, i2c_3);
+        }
+        if ticks.wrapping_sub(self.last_status_draw) >= TICKS_PER_SECOND {
+            self.draw_status(graphics, context);
+        }
+        self.screens[self.active].tick(graphics, context, ticks);
+    }
+
+    fn poll_touch(&mut self, graphics: &mut Graphics, context: &mut Context, i2c_3: &mut I2C) {
+        let touches = match touch::touches(i2c_3) {
+            Ok(touches) => touches,
+            Err(_) => return,
+        };
+        let mut first = None;
+        for touch in &touches {
+            first = Some((touch.x, touch.y));
+            break;
+        }
+        let was_touching = self.touching;
+        self.touching = first.is_some();
+
+        let (x, y) = match first {
+            Some(position) if !was_touching => position,
+            _ => return,
+        };
+
+        if y < TAB_BAR_HEIGHT {
+            if let Some(index) = self.tabs.iter().position(|tab| tab.touched_inside(x, y)) {
+                self.switch_to(graphics, context, index);
+            }
+        } else if y >= CONTENT_Y {
+            if self.screens[self.active].on_touch(graphics, context, x, y) {
+                self.draw(graphics, context);
+            }
+        }
+    }
+
+    fn draw_tabs(&self, graphics: &mut Graphics) {
+        for tab in self.tabs.iter() {
+            tab.draw(graphics);
+        }
+        // underline the active tab
+        if let Button::LabelButton { x, width, .. } = self.tabs[self.active] {
+            Graphics::draw_rect_filled(&mut graphics.lcd, x, TAB_BAR_HEIGHT, width, 2, ACTIVE_TAB_COLOR);
+        }
+    }
+
+    fn draw_status(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.last_status_draw = context.ticks;
+        let y = TAB_BAR_HEIGHT + 2;
+        let color = if context.dhcp.address().is_some() { STATUS_COLOR } else { STATUS_WARNING_COLOR };
+        Graphics::draw_rect_filled(&mut graphics.lcd, 0, y, graphics::WIDTH, STATUS_BAR_HEIGHT, 0x0);
+
+        // always the small font, the large one doesn't fit
+        let font = graphics.font();
+        graphics.set_font(font::SMALL);
+        graphics.draw_text_in(4, y + 1, graphics::WIDTH - 8, font::SMALL.line_height(),
+                              &status_text(context), color, 0x0);
+        graphics.set_font(font);
+    }
+}
+
+/// `10.0.0.23/24 via 10.0.0.1, DHCP bound, lease 23h left`, or the fallback
+/// address while no DHCP server answers.
+pub fn status_text(context: &Context) -> String {
+    let dhcp = &context.dhcp;
+
+    let mut text = match (dhcp.address(), dhcp.netmask()) {
+        (Some(address), Some(netmask)) => addr::format_ipv4_network(&address, &netmask),
+        (Some(address), None) => addr::format_ipv4(&address),
+        (None, _) => String::from("no address"),
+    };
+    if let Some(gateway) = dhcp.gateway() {
+        text.push_str(" via ");
+        text.push_str(&addr::format_ipv4(&gateway));
+    }
+    match dhcp.source() {
+        Some(Source::Static) => text.push_str(", static"),
+        Some(Source::LinkLocal) => text.push_str(", link-local"),
+        _ => {}
+    }
+    text.push_str(", DHCP ");
+    text.push_str(dhcp.state().name());
+    if let Some(lease) = dhcp.lease() {
+        text.push_str(", lease ");
+        text.push_str(&format_age(lease.remaining(context.ticks)));
+        text.push_str(" left");
+    }
+    text
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    fn lower(c: u8) -> u8 {
+        if c >= b'A' && c <= b'Z' { c + (b'a' - b'A') } else { c }
+    }
+    a.len() == b.len() && a.bytes().zip(b.bytes()).all(|(x, y)| lower(x) == lower(y))
+}
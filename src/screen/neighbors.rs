@@ -0,0 +1,101 @@
+use collections::string::String;
+
+use addr;
+use classify::ParseResult;
+use context::{format_age, Context, TICKS_PER_SECOND};
+use graphics::{self, Graphics};
+use neighbors::Neighbor;
+use super::{Screen, CONTENT_Y};
+
+const MARGIN: u16 = 4;
+const TEXT_COLOR: u16 = 0xffff;
+const DETAIL_COLOR: u16 = 0xc618;
+const HEADER_COLOR: u16 = 0xaacc;
+
+/// The switches (and whatever else sends LLDP or CDP) on our link, with the
+/// port we are plugged into.
+pub struct NeighborsScreen {
+    last_draw: usize
+}
+
+impl NeighborsScreen {
+    pub fn new() -> Self {
+        NeighborsScreen { last_draw: 0 }
+    }
+
+    fn format_neighbor(neighbor: &Neighbor, ticks: usize) -> (String, String) {
+        let name = match neighbor.info.name {
+            Some(ref name) => name.clone(),
+            None => addr::format_mac(&neighbor.mac),
+        };
+        let title = format!("{} ({}, {})", name, neighbor.protocol.name(), addr::format_mac(&neighbor.mac));
+
+        let management = neighbor.info.management.as_ref().map(addr::format_ipv4)
+            .unwrap_or_else(|| String::from("-"));
+        let vlan = match neighbor.vlan_id {
+            Some(id) => format!("{}", id),
+            None => String::from("-"),
+        };
+        let details = format!("port {}  mgmt {}  vlan {}  expires in {}",
+                              neighbor.info.port.as_ref().map(|p| p.as_str()).unwrap_or("-"),
+                              management, vlan, format_age(neighbor.remaining(ticks)));
+        (title, details)
+    }
+
+    fn draw_content(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.last_draw = context.ticks;
+        let line_height = graphics.font().line_height();
+        Graphics::draw_rect_filled(&mut graphics.lcd, 0, CONTENT_Y, graphics::WIDTH, graphics::HEIGHT - CONTENT_Y, 0x0);
+
+        let neighbors = context.neighbors.neighbors();
+        let header = if neighbors.is_empty() {
+            String::from("No LLDP or CDP announcements yet, switches send them every 30 to 60 seconds")
+        } else {
+            format!("{} link neighbors", neighbors.len())
+        };
+        graphics.draw_text_in(MARGIN, CONTENT_Y + MARGIN, graphics::WIDTH - 2 * MARGIN, line_height,
+                              &header, HEADER_COLOR, 0x0);
+
+        // two lines each, as many as fit
+        let mut y = CONTENT_Y + MARGIN + 2 * line_height;
+        let text_width = graphics::WIDTH - 2 * MARGIN - line_height;
+        for neighbor in neighbors {
+            if y + 2 * line_height > graphics::HEIGHT {
+                break;
+            }
+            let (title, details) = Self::format_neighbor(neighbor, context.ticks);
+            Graphics::draw_square_filled(&mut graphics.lcd, MARGIN, y, line_height - 2, neighbor.protocol.color());
+            graphics.draw_text_in(MARGIN + line_height, y, text_width, line_height, &title, TEXT_COLOR, 0x0);
+            graphics.draw_text_in(MARGIN + line_height, y + line_height, text_width, line_height,
+                                  &details, DETAIL_COLOR, 0x0);
+            y += 2 * line_height + MARGIN;
+        }
+    }
+}
+
+impl Screen for NeighborsScreen {
+    fn name(&self) -> &'static str {
+        "Links"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.draw_content(graphics, context);
+    }
+
+    fn on_touch(&mut self, _graphics: &mut Graphics, _context: &mut Context, _x: u16, _y: u16) -> bool {
+        false
+    }
+
+    fn on_packet(&mut self, graphics: &mut Graphics, context: &Context, parse_result: &ParseResult, visible: bool) {
+        if visible && parse_result.neighbor.is_some() {
+            self.draw_content(graphics, context);
+        }
+    }
+
+    fn tick(&mut self, graphics: &mut Graphics, context: &Context, ticks: usize) {
+        // the remaining TTLs count down every second
+        if ticks.wrapping_sub(self.last_draw) >= TICKS_PER_SECOND {
+            self.draw_content(graphics, context);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use collections::vec::Vec;
+
+use classify::ParseResult;
+use context::{Context, Settings};
+use font;
+use graphics::{Button, Graphics};
+use super::{Screen, CONTENT_Y};
+
+const ROW_HEIGHT: u16 = 30;
+const BUTTON_WIDTH: u16 = 90;
+const LABEL_X: u16 = 10 + BUTTON_WIDTH + 10;
+
+const ON_COLOR: u16 = 0x07e0;
+const OFF_COLOR: u16 = 0x8410;
+
+/// A setting whose button shows the current value, touching it switches to
+/// the next one.
+struct Row {
+    label: &'static str,
+    value: fn(&Settings) -> &'static str,
+    next: fn(&mut Settings)
+}
+
+pub struct SettingsScreen {
+    rows: Vec<Row>
+}
+
+impl SettingsScreen {
+    pub fn new() -> Self {
+        let mut rows = Vec::new();
+        rows.push(Row {
+            label: "Pause the packet grid",
+            value: |s| on_off(s.paused),
+            next: |s| s.paused = !s.paused
+        });
+        rows.push(Row {
+            label: "Large text",
+            value: |s| on_off(s.large_font),
+            next: |s| s.large_font = !s.large_font
+        });
+        rows.push(Row {
+            label: "Capture to capture.pcapng",
+            value: |s| on_off(s.capture),
+            next: |s| s.capture = !s.capture
+        });
+        rows.push(Row {
+            label: "Color squares by",
+            value: |s| s.color_mode.name(),
+            next: |s| s.color_mode = s.color_mode.next()
+        });
+        SettingsScreen { rows: rows }
+    }
+
+    fn button(index: usize, value: &'static str) -> Button {
+        Button::LabelButton {
+            x: 10,
+            y: CONTENT_Y + 5 + index as u16 * ROW_HEIGHT,
+            width: BUTTON_WIDTH,
+            height: ROW_HEIGHT - 10,
+            color: if value == "off" { OFF_COLOR } else { ON_COLOR },
+            label: value
+        }
+    }
+}
+
+impl Screen for SettingsScreen {
+    fn name(&self) -> &'static str {
+        "Settings"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        for (index, row) in self.rows.iter().enumerate() {
+            Self::button(index, (row.value)(&context.settings)).draw(graphics);
+            let y = CONTENT_Y + 5 + index as u16 * ROW_HEIGHT + 5;
+            graphics.draw_text(LABEL_X, y, row.label, 0xffff, 0x0);
+        }
+    }
+
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, x: u16, y: u16) -> bool {
+        let touched = (0..self.rows.len()).position(|index| {
+            let value = (self.rows[index].value)(&context.settings);
+            Self::button(index, value).touched_inside(x, y)
+        });
+        if let Some(index) = touched {
+            (self.rows[index].next)(&mut context.settings);
+
+            // the font changes the tab bar as well
+            graphics.set_font(if context.settings.large_font { font::LARGE } else { font::SMALL });
+            return true;
+        }
+        false
+    }
+
+    fn on_packet(&mut self, _graphics: &mut Graphics, _context: &Context, _parse_result: &ParseResult, _visible: bool) {}
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
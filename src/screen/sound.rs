@@ -0,0 +1,94 @@
+use classify::{self, ParseResult, TYPE_COUNT};
+use context::{Context, Settings};
+use graphics::{Button, Graphics};
+use sonify;
+use super::{Screen, CONTENT_Y};
+
+const ROW_HEIGHT: u16 = 30;
+const BUTTON_WIDTH: u16 = 90;
+const LABEL_X: u16 = 10 + BUTTON_WIDTH + 10;
+
+// the mute toggles, one per frame type
+const TYPE_COLUMNS: u16 = 4;
+const TYPE_BUTTON_WIDTH: u16 = 110;
+const TYPES_Y: u16 = CONTENT_Y + 5 + 3 * ROW_HEIGHT;
+
+const ON_COLOR: u16 = 0x07e0;
+const OFF_COLOR: u16 = 0x8410;
+
+pub struct SoundScreen;
+
+impl SoundScreen {
+    pub fn new() -> Self {
+        SoundScreen
+    }
+
+    fn sound_button(settings: &Settings) -> Button {
+        let label = if settings.sound { "on" } else { "off" };
+        Self::row_button(0, label, settings.sound)
+    }
+
+    fn volume_button(settings: &Settings) -> Button {
+        Self::row_button(1, sonify::volume_name(settings.volume), settings.volume != 0)
+    }
+
+    fn row_button(row: u16, label: &'static str, on: bool) -> Button {
+        Button::LabelButton {
+            x: 10,
+            y: CONTENT_Y + 5 + row * ROW_HEIGHT,
+            width: BUTTON_WIDTH,
+            height: ROW_HEIGHT - 10,
+            color: if on { ON_COLOR } else { OFF_COLOR },
+            label: label
+        }
+    }
+
+    fn type_button(settings: &Settings, index: usize) -> Button {
+        Button::LabelButton {
+            x: 10 + (index as u16 % TYPE_COLUMNS) * (TYPE_BUTTON_WIDTH + 5),
+            y: TYPES_Y + (index as u16 / TYPE_COLUMNS) * ROW_HEIGHT,
+            width: TYPE_BUTTON_WIDTH,
+            height: ROW_HEIGHT - 10,
+            color: if settings.muted[index] { OFF_COLOR } else { ON_COLOR },
+            label: classify::type_name(index)
+        }
+    }
+}
+
+impl Screen for SoundScreen {
+    fn name(&self) -> &'static str {
+        "Sound"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        let settings = &context.settings;
+        Self::sound_button(settings).draw(graphics);
+        graphics.draw_text(LABEL_X, CONTENT_Y + 10, "Play a tone per frame", 0xffff, 0x0);
+        Self::volume_button(settings).draw(graphics);
+        graphics.draw_text(LABEL_X, CONTENT_Y + 10 + ROW_HEIGHT, "Volume", 0xffff, 0x0);
+
+        graphics.draw_text(10, TYPES_Y - ROW_HEIGHT + 5, "Touch a type to mute it", 0xffff, 0x0);
+        for index in 0..TYPE_COUNT {
+            Self::type_button(settings, index).draw(graphics);
+        }
+    }
+
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, x: u16, y: u16) -> bool {
+        let settings = &mut context.settings;
+        let touched_type = (0..TYPE_COUNT).position(|i| Self::type_button(settings, i).touched_inside(x, y));
+
+        if Self::sound_button(settings).touched_inside(x, y) {
+            settings.sound = !settings.sound;
+            Self::sound_button(settings).draw(graphics);
+        } else if Self::volume_button(settings).touched_inside(x, y) {
+            settings.volume = sonify::next_volume(settings.volume);
+            Self::volume_button(settings).draw(graphics);
+        } else if let Some(index) = touched_type {
+            settings.muted[index] = !settings.muted[index];
+            Self::type_button(settings, index).draw(graphics);
+        }
+        false
+    }
+
+    fn on_packet(&mut self, _graphics: &mut Graphics, _context: &Context, _parse_result: &ParseResult, _visible: bool) {}
+}
@@ -0,0 +1,187 @@
+use core::cmp;
+use collections::string::String;
+use collections::vec::Vec;
+
+use classify::{self, ParseResult, TYPE_COUNT};
+use context::Context;
+use graphics::{self, Graphics};
+use stats::Stats;
+use super::{Screen, CONTENT_Y, CONTENT_HEIGHT};
+
+const MARGIN: u16 = 10;
+const LABEL_COLOR: u16 = 0xffff;
+const BACKGROUND_COLOR: u16 = 0x0;
+
+// what a row of the screen shows, to redraw only the rows that changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    type_index: usize,
+    frames: u32,
+    bar_len: u16
+}
+
+pub struct StatsScreen {
+    // `Stats::last_update` at the time of the last redraw
+    drawn_update: usize,
+    // the type shown in the first row, touching the screen shows the next
+    // rows if they don't all fit
+    first_row: usize,
+    drawn_totals: String,
+    drawn_rows: Vec<Option<Row>>
+}
+
+impl StatsScreen {
+    pub fn new() -> Self {
+        StatsScreen { drawn_update: 0, first_row: 0, drawn_totals: String::new(), drawn_rows: Vec::new() }
+    }
+
+    // the type rows below the two lines of totals and a blank line
+    fn rows(graphics: &Graphics) -> usize {
+        let line_height = graphics.font().line_height();
+        ((CONTENT_HEIGHT - 2 * MARGIN).saturating_sub(3 * line_height) / line_height) as usize
+    }
+
+    fn update(&mut self, graphics: &mut Graphics, context: &Context) {
+        self.drawn_update = context.stats.last_update();
+        self.draw_stats(graphics, &context.stats, MARGIN, CONTENT_Y + MARGIN, graphics::WIDTH - 2 * MARGIN);
+    }
+
+    /// Draws the totals and one bar per type below `y`, where they differ
+    /// from what was drawn before. The bars are scaled relative to the most
+    /// frequent type.
+    fn draw_stats(&mut self, graphics: &mut Graphics, stats: &Stats, x: u16, y: u16, width: u16) {
+        let font = graphics.font();
+        let line_height = font.line_height();
+        let rows = Self::rows(graphics);
+
+        // padded to the width, so the lines overwrite longer ones before
+        let columns = (width / font.char_width()) as usize;
+        let mut rates = format!("{} frames/s, {} B/s", stats.frames_per_second(), stats.bytes_per_second());
+        if rows < TYPE_COUNT {
+            let last = cmp::min(self.first_row + rows, TYPE_COUNT);
+            rates.push_str(&format!(", types {}-{} of {}", self.first_row + 1, last, TYPE_COUNT));
+        }
+        let totals = format!("{:columns$}\n{:columns$}",
+                             format!("{} frames, {} bytes", stats.total_frames(), stats.total_bytes()),
+                             rates, columns = columns);
+        if totals != self.drawn_totals {
+            graphics.draw_text_in(x, y, width, 2 * line_height, &totals, LABEL_COLOR, BACKGROUND_COLOR);
+            self.drawn_totals = totals;
+        }
+        let mut row_y = y + 3 * line_height;
+
+        // type name left, bar in the middle, frame count right
+        let label_width = 10 * font.char_width();
+        let count_width = 7 * font.char_width();
+        if width <= label_width + count_width {
+            return;
+        }
+        let bar_width = width - label_width - count_width;
+        let max_frames = (0..TYPE_COUNT).map(|index| stats.frames(index)).max().unwrap_or(0);
+
+        self.drawn_rows.resize(rows, None);
+        for row in 0..rows {
+            let index = self.first_row + row;
+            let new = if index < TYPE_COUNT {
+                let frames = stats.frames(index);
+                let bar_len = if max_frames == 0 {
+                    0
+                } else {
+                    (frames as u64 * bar_width as u64 / max_frames as u64) as u16
+                };
+                Some(Row { type_index: index, frames: frames, bar_len: bar_len })
+            } else {
+                None
+            };
+            let old = self.drawn_rows[row];
+            if new != old {
+                Self::draw_row(graphics, x, row_y, width, label_width, bar_width, new, old);
+                self.drawn_rows[row] = new;
+            }
+            row_y += line_height;
+        }
+    }
+
+    // `old` is what the row shows now
+    fn draw_row(graphics: &mut Graphics, x: u16, y: u16, width: u16, label_width: u16, bar_width: u16,
+                new: Option<Row>, old: Option<Row>) {
+        let line_height = graphics.font().line_height();
+        let new = match new {
+            Some(new) => new,
+            None => {
+                Graphics::draw_rect_filled(&mut graphics.lcd, x, y, width, line_height, BACKGROUND_COLOR);
+                return;
+            }
+        };
+        let bar_x = x + label_width;
+        // another type is drawn from scratch
+        let (old_len, old_frames) = match old {
+            Some(old) if old.type_index == new.type_index => (old.bar_len, Some(old.frames)),
+            _ => {
+                graphics.draw_text_in(x, y, label_width, line_height, &format!("{:10}", classify::type_name(new.type_index)),
+                                      LABEL_COLOR, BACKGROUND_COLOR);
+                Graphics::draw_rect_filled(&mut graphics.lcd, bar_x + new.bar_len, y, bar_width - new.bar_len, line_height,
+                                           BACKGROUND_COLOR);
+                (0, None)
+            }
+        };
+
+        // only the part of the bar that grew or shrank
+        if new.bar_len > old_len {
+            // black types would be invisible on the background
+            let color = match classify::type_color(new.type_index) {
+                BACKGROUND_COLOR => 0x4208,
+                color => color,
+            };
+            Graphics::draw_rect_filled(&mut graphics.lcd, bar_x + old_len, y + 1, new.bar_len - old_len, line_height - 2, color);
+        } else if new.bar_len < old_len {
+            Graphics::draw_rect_filled(&mut graphics.lcd, bar_x + new.bar_len, y + 1, old_len - new.bar_len, line_height - 2,
+                                       BACKGROUND_COLOR);
+        }
+
+        if old_frames != Some(new.frames) {
+            let count = format!("{:>6}", new.frames);
+            graphics.draw_text_in(bar_x + bar_width, y, width - label_width - bar_width, line_height, &count,
+                                  LABEL_COLOR, BACKGROUND_COLOR);
+        }
+    }
+}
+
+impl Screen for StatsScreen {
+    fn name(&self) -> &'static str {
+        "Stats"
+    }
+
+    fn draw(&mut self, graphics: &mut Graphics, context: &Context) {
+        // the screen was cleared
+        self.drawn_totals.clear();
+        self.drawn_rows.clear();
+        // the font may have changed since the last page was picked
+        if self.first_row >= TYPE_COUNT || Self::rows(graphics) >= TYPE_COUNT {
+            self.first_row = 0;
+        }
+        self.update(graphics, context);
+    }
+
+    fn on_touch(&mut self, graphics: &mut Graphics, context: &mut Context, _x: u16, _y: u16) -> bool {
+        let rows = Self::rows(graphics);
+        if rows >= TYPE_COUNT || rows == 0 {
+            return false;
+        }
+        self.first_row += rows;
+        if self.first_row >= TYPE_COUNT {
+            self.first_row = 0;
+        }
+        self.update(graphics, context);
+        false
+    }
+
+    fn on_packet(&mut self, _graphics: &mut Graphics, _context: &Context, _parse_result: &ParseResult, _visible: bool) {}
+
+    fn tick(&mut self, graphics: &mut Graphics, context: &Context, _ticks: usize) {
+        // the rates change once per interval, that's when we redraw
+        if context.stats.last_update() != self.drawn_update {
+            self.update(graphics, context);
+        }
+    }
+}
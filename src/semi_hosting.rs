@@ -1,6 +1,12 @@
 // see http://embed.rs/articles/2016/semi-hosting-rust/
 
-use core::fmt;
+use core::{fmt, mem};
+use collections::string::String;
+use stm32f7::cortex_m::interrupt;
+
+#[cfg(feature = "itm")]
+use itm;
+use ring_buffer::{RingBuffer, CAPACITY};
 
 unsafe fn call_svc(num: usize, addr: *const ()) -> usize {
     // allocate stack space for the possible result
@@ -32,12 +38,131 @@ struct SvcWriteCall {
     len: usize,
 }
 
+#[repr(C)]
+struct SvcReadCall {
+    fd: usize,
+    // pointer to the buffer the host writes to
+    addr: *mut u8,
+    len: usize,
+}
+
+#[repr(C)]
+struct SvcOpenCall {
+    // pointer to the null terminated file name
+    name: *const u8,
+    // one of the fopen modes, see `OpenMode`
+    mode: usize,
+    // length of the file name without the terminator
+    len: usize,
+}
+
+#[repr(C)]
+struct SvcFdCall {
+    fd: usize,
+}
+
+#[repr(C)]
+struct SvcSeekCall {
+    fd: usize,
+    // absolute position from the start of the file
+    pos: usize,
+}
+
+#[repr(C)]
+struct SvcCmdlineCall {
+    addr: *mut u8,
+    // the size of the buffer, the host replaces it with the length of the
+    // command line
+    len: usize,
+}
+
+const SYS_OPEN: usize = 0x01;
+const SYS_CLOSE: usize = 0x02;
+const SYS_WRITEC: usize = 0x03;
+const SYS_WRITE0: usize = 0x04;
 const SYS_WRITE: usize = 0x05;
+const SYS_READ: usize = 0x06;
+const SYS_READC: usize = 0x07;
+const SYS_SEEK: usize = 0x0a;
+const SYS_FLEN: usize = 0x0c;
+const SYS_CLOCK: usize = 0x10;
+const SYS_TIME: usize = 0x11;
+const SYS_ERRNO: usize = 0x13;
+const SYS_GET_CMDLINE: usize = 0x15;
+const SYS_EXIT: usize = 0x18;
+
+// the reason passed to `SYS_EXIT` for a normal exit
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x20026;
+
+// the host returns -1 for most failures
+fn failed(result: usize) -> bool {
+    result as isize == -1
+}
+
+/// The `fopen` modes of `SYS_OPEN`, in the order of the ARM specification.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum OpenMode {
+    Read = 0,
+    ReadBinary = 1,
+    ReadUpdate = 2,
+    ReadUpdateBinary = 3,
+    Write = 4,
+    WriteBinary = 5,
+    WriteUpdate = 6,
+    WriteUpdateBinary = 7,
+    Append = 8,
+    AppendBinary = 9,
+    AppendUpdate = 10,
+    AppendUpdateBinary = 11,
+}
+
+/// Semi-hosting: `SYS_OPEN`. Opens the file `name` on the host. Returns
+/// the file descriptor or `None` if the host couldn't open it.
+pub fn svc_sys_open(name: &str, mode: OpenMode) -> Option<usize> {
+    let mut terminated = String::with_capacity(name.len() + 1);
+    terminated.push_str(name);
+    terminated.push('\0');
+
+    let args = SvcOpenCall {
+        name: terminated.as_ptr(),
+        mode: mode as usize,
+        len: name.len(),
+    };
+
+    match unsafe { call_svc(SYS_OPEN, &args as *const SvcOpenCall as *const ()) } {
+        fd if failed(fd) => None,
+        fd => Some(fd),
+    }
+}
+
+/// Semi-hosting: `SYS_CLOSE`. Closes a file descriptor returned by
+/// `svc_sys_open`. Returns `true` on success.
+pub fn svc_sys_close(fd: usize) -> bool {
+    let args = SvcFdCall { fd: fd };
+
+    unsafe { call_svc(SYS_CLOSE, &args as *const SvcFdCall as *const ()) == 0 }
+}
+
+/// Semi-hosting: `SYS_WRITEC`. Writes a single byte to the debug console.
+pub fn svc_sys_writec(c: u8) {
+    unsafe { call_svc(SYS_WRITEC, &c as *const u8 as *const ()) };
+}
+
+/// Semi-hosting: `SYS_WRITE0`. Writes `s` to the debug console. `s` must
+/// not contain a null byte, everything after it is dropped.
+pub fn svc_sys_write0(s: &str) {
+    let mut terminated = String::with_capacity(s.len() + 1);
+    terminated.push_str(s);
+    terminated.push('\0');
+
+    unsafe { call_svc(SYS_WRITE0, terminated.as_ptr() as *const ()) };
+}
 
 /// Semi-hosting: `SYS_WRITE`. Writes `data` to file descriptor `fd`
 /// on the host. Returns `0` on success or number of unwritten bytes
 /// otherwise.
-fn svc_sys_write(fd: usize, data: &[u8]) -> usize {
+pub fn svc_sys_write(fd: usize, data: &[u8]) -> usize {
     let args = SvcWriteCall {
         fd: fd,
         addr: data.as_ptr(),
@@ -47,6 +172,178 @@ fn svc_sys_write(fd: usize, data: &[u8]) -> usize {
     unsafe { call_svc(SYS_WRITE, &args as *const SvcWriteCall as *const ()) }
 }
 
+/// Semi-hosting: `SYS_READ`. Reads up to `buf.len()` bytes from file
+/// descriptor `fd`. Returns the number of bytes *not* read, so `0` is a
+/// full buffer and `buf.len()` the end of the file, or `None` on errors.
+pub fn svc_sys_read(fd: usize, buf: &mut [u8]) -> Option<usize> {
+    let args = SvcReadCall {
+        fd: fd,
+        addr: buf.as_mut_ptr(),
+        len: buf.len(),
+    };
+
+    match unsafe { call_svc(SYS_READ, &args as *const SvcReadCall as *const ()) } {
+        result if failed(result) => None,
+        result => Some(result),
+    }
+}
+
+/// Semi-hosting: `SYS_READC`. Blocks until a byte arrives on the debug
+/// console.
+pub fn svc_sys_readc() -> u8 {
+    unsafe { call_svc(SYS_READC, 0 as *const ()) as u8 }
+}
+
+/// Semi-hosting: `SYS_SEEK`. Moves to the absolute position `pos`.
+/// Returns `true` on success.
+pub fn svc_sys_seek(fd: usize, pos: usize) -> bool {
+    let args = SvcSeekCall { fd: fd, pos: pos };
+
+    unsafe { call_svc(SYS_SEEK, &args as *const SvcSeekCall as *const ()) == 0 }
+}
+
+/// Semi-hosting: `SYS_FLEN`. Returns the length of the file in bytes.
+pub fn svc_sys_flen(fd: usize) -> Option<usize> {
+    let args = SvcFdCall { fd: fd };
+
+    match unsafe { call_svc(SYS_FLEN, &args as *const SvcFdCall as *const ()) } {
+        result if failed(result) => None,
+        result => Some(result),
+    }
+}
+
+/// Semi-hosting: `SYS_CLOCK`. Centiseconds since the debugger started.
+pub fn svc_sys_clock() -> Option<usize> {
+    match unsafe { call_svc(SYS_CLOCK, 0 as *const ()) } {
+        result if failed(result) => None,
+        result => Some(result),
+    }
+}
+
+/// Semi-hosting: `SYS_TIME`. Seconds since 1970-01-01 on the host.
+pub fn svc_sys_time() -> usize {
+    unsafe { call_svc(SYS_TIME, 0 as *const ()) }
+}
+
+/// Semi-hosting: `SYS_ERRNO`. The host's `errno` after the last failed
+/// call.
+pub fn svc_sys_errno() -> usize {
+    unsafe { call_svc(SYS_ERRNO, 0 as *const ()) }
+}
+
+/// Semi-hosting: `SYS_GET_CMDLINE`. Writes the command line set on the host
+/// into `buf`, null terminated. Returns its length without the terminator.
+pub fn svc_sys_get_cmdline(buf: &mut [u8]) -> Option<usize> {
+    let mut args = SvcCmdlineCall {
+        addr: buf.as_mut_ptr(),
+        len: buf.len(),
+    };
+
+    let result = unsafe { call_svc(SYS_GET_CMDLINE, &mut args as *mut SvcCmdlineCall as *const ()) };
+    if result == 0 { Some(args.len) } else { None }
+}
+
+/// Semi-hosting: `SYS_EXIT`. Tells the debugger that the firmware is done,
+/// it stops the board.
+pub fn svc_sys_exit() {
+    unsafe { call_svc(SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT as *const ()) };
+}
+
+/// A failed semi-hosting call, with the host's `errno`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    pub errno: usize
+}
+
+impl Error {
+    fn last() -> Error {
+        Error { errno: svc_sys_errno() }
+    }
+}
+
+/// Byte sources, like `std::io::Read`.
+pub trait Read {
+    /// Reads up to `buf.len()` bytes, returns how many were read. `0` is the
+    /// end of the input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Reads until `buf` is full or the input ends, returns how many bytes
+    /// were read.
+    fn read_all(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+/// A file on the host, closed when dropped.
+pub struct File {
+    fd: usize
+}
+
+impl File {
+    pub fn open(name: &str, mode: OpenMode) -> Result<File, Error> {
+        match svc_sys_open(name, mode) {
+            Some(fd) => Ok(File { fd: fd }),
+            None => Err(Error::last()),
+        }
+    }
+
+    /// Shortcut for `open` with `OpenMode::WriteBinary`, truncates the file.
+    pub fn create(name: &str) -> Result<File, Error> {
+        File::open(name, OpenMode::WriteBinary)
+    }
+
+    /// The debugger's stdin, see `svc_sys_readc` for single bytes.
+    pub fn stdin() -> Result<File, Error> {
+        File::open(":tt", OpenMode::Read)
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        if svc_sys_write(self.fd, data) == 0 {
+            Ok(())
+        } else {
+            Err(Error::last())
+        }
+    }
+
+    pub fn seek(&mut self, pos: usize) -> Result<(), Error> {
+        if svc_sys_seek(self.fd, pos) { Ok(()) } else { Err(Error::last()) }
+    }
+
+    pub fn len(&self) -> Result<usize, Error> {
+        svc_sys_flen(self.fd).ok_or_else(Error::last)
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        match svc_sys_read(self.fd, buf) {
+            // a host claiming more unread bytes than asked for read nothing
+            Some(not_read) => Ok(buf.len().saturating_sub(not_read)),
+            None => Err(Error::last()),
+        }
+    }
+}
+
+impl fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        svc_sys_close(self.fd);
+    }
+}
+
 #[macro_export]
 macro_rules! println {
     ($fmt:expr) => (print!(concat!($fmt, "\n")));
@@ -61,27 +358,11 @@ macro_rules! print {
 }
 
 pub fn print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    Stdout.write_fmt(args).unwrap();
-}
-
-static mut STDOUT_BUFFER: ([u8; 100], usize) = ([0; 100], 0);
-
-struct Stdout;
-
-impl fmt::Write for Stdout {
-    fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
-        unsafe {
-            for &byte in s.as_bytes() {
-                STDOUT_BUFFER.0[STDOUT_BUFFER.1] = byte;
-                STDOUT_BUFFER.1 += 1;
-                if STDOUT_BUFFER.1 >= 100 || byte == b'\n' {
-                    svc_sys_write(1, &STDOUT_BUFFER.0[..STDOUT_BUFFER.1]);
-                    STDOUT_BUFFER.1 = 0;
-                }
-            }
-        }
-        Ok(())
+    // the whole message goes into the buffer at once, so lines printed from
+    // an interrupt handler don't end up in the middle of other lines. Only
+    // that happens with interrupts disabled, not the slow write to the host.
+    if interrupt::free(|_| unsafe { STDOUT.append(args) }) {
+        flush();
     }
 }
 
@@ -99,15 +380,107 @@ macro_rules! print_err {
 }
 
 pub fn print_err(args: fmt::Arguments) {
-    use core::fmt::Write;
-    Stderr.write_fmt(args).unwrap();
+    if interrupt::free(|_| unsafe { STDERR.append(args) }) {
+        flush();
+    }
 }
 
-struct Stderr;
+/// Writes out everything that is still buffered, e.g. a line without a
+/// newline at the end.
+pub fn flush() {
+    // an interrupt handler printing while the main loop flushes leaves its
+    // output to the main loop, which keeps the order
+    if interrupt::free(|_| unsafe { mem::replace(&mut FLUSHING, true) }) {
+        return;
+    }
+    let mut chunk = [0; FLUSH_CHUNK_LEN];
+    loop {
+        let (fd, count) = interrupt::free(|_| unsafe {
+            let (fd, count) = match STDOUT.buffer.pop(&mut chunk) {
+                0 => (STDERR.fd, STDERR.buffer.pop(&mut chunk)),
+                count => (STDOUT.fd, count),
+            };
+            if count == 0 {
+                FLUSHING = false;
+            }
+            (fd, count)
+        });
+        if count == 0 {
+            break;
+        }
+        sink_write(fd, &chunk[..count]);
+    }
+}
+
+/// `flush` for the crash handlers, also when they interrupted a flush that
+/// will never finish.
+pub fn flush_on_crash() {
+    interrupt::free(|_| unsafe { FLUSHING = false; });
+    flush();
+}
+
+/// The output that didn't fit into the buffers and was dropped, in bytes.
+pub fn dropped() -> usize {
+    interrupt::free(|_| unsafe { STDOUT.dropped + STDERR.dropped })
+}
 
-impl fmt::Write for Stderr {
+const FLUSH_CHUNK_LEN: usize = 64;
+
+// only touched inside `interrupt::free`
+static mut STDOUT: BufferedWriter = BufferedWriter::new(1);
+static mut STDERR: BufferedWriter = BufferedWriter::new(2);
+static mut FLUSHING: bool = false;
+
+/// Collects output until a newline, every write to the host stops the board
+/// until the debugger handled it.
+struct BufferedWriter {
+    fd: usize,
+    buffer: RingBuffer,
+    // set by a newline, cleared when it was seen by `append`
+    line_complete: bool,
+    dropped: usize
+}
+
+impl BufferedWriter {
+    const fn new(fd: usize) -> Self {
+        BufferedWriter {
+            fd: fd,
+            buffer: RingBuffer::new(),
+            line_complete: false,
+            dropped: 0
+        }
+    }
+
+    /// Formats `args` into the buffer, returns whether it should be flushed.
+    fn append(&mut self, args: fmt::Arguments) -> bool {
+        use core::fmt::Write;
+        // a failing `Display` impl only cuts its own output short
+        let _ = self.write_fmt(args);
+        mem::replace(&mut self.line_complete, false) || self.buffer.len() > CAPACITY / 2
+    }
+}
+
+impl fmt::Write for BufferedWriter {
     fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
-        svc_sys_write(2, s.as_bytes());
+        for &byte in s.as_bytes() {
+            if !self.buffer.push(byte) {
+                self.dropped += 1;
+            } else if byte == b'\n' {
+                self.line_complete = true;
+            }
+        }
         Ok(())
     }
 }
+
+#[cfg(not(feature = "itm"))]
+fn sink_write(fd: usize, data: &[u8]) {
+    svc_sys_write(fd, data);
+}
+
+// with the `itm` feature stdout and stderr both go to stimulus port 0,
+// which doesn't stop the board
+#[cfg(feature = "itm")]
+fn sink_write(_fd: usize, data: &[u8]) {
+    itm::write_all(data);
+}
@@ -0,0 +1,142 @@
+// Makes the packet stream audible: every frame starts a short note whose
+// waveform, base pitch and length depend on its type, so ARP storms buzz and
+// DHCP exchanges play a little melody. The source address picks a step of a
+// pentatonic scale above the base pitch, a chatty host keeps its tone.
+
+use classify::{ParseResult, TYPE_COUNT};
+use context::Settings;
+use palette;
+use synth::{Adsr, Frame, Note, Synth, Waveform};
+
+/// The volume steps the sound screen cycles through, in percent.
+pub static VOLUMES: [(u8, &'static str); 5] = [(0, "0%"), (25, "25%"), (50, "50%"), (75, "75%"), (100, "100%")];
+
+struct Instrument {
+    waveform: Waveform,
+    frequency: u32,
+    envelope: Adsr,
+    hold_ms: u32
+}
+
+const fn percussive(attack_ms: u32, release_ms: u32) -> Adsr {
+    Adsr { attack_ms: attack_ms, decay_ms: 0, sustain_percent: 100, release_ms: release_ms }
+}
+
+// indexed by `ParseResultType::index`
+static INSTRUMENTS: [Instrument; TYPE_COUNT] = [
+    // Unknown
+    Instrument { waveform: Waveform::Triangle, frequency: 110, envelope: percussive(2, 40), hold_ms: 0 },
+    // Malformed, a crackle
+    Instrument { waveform: Waveform::Noise, frequency: 0, envelope: percussive(1, 60), hold_ms: 0 },
+    // ARP
+    Instrument { waveform: Waveform::Square, frequency: 440, envelope: percussive(1, 50), hold_ms: 0 },
+    // DHCP, long notes
+    Instrument {
+        waveform: Waveform::Sine,
+        frequency: 330,
+        envelope: Adsr { attack_ms: 20, decay_ms: 50, sustain_percent: 60, release_ms: 150 },
+        hold_ms: 100
+    },
+    // ICMP, a high ping
+    Instrument { waveform: Waveform::Sine, frequency: 1320, envelope: percussive(1, 120), hold_ms: 0 },
+    // DNS
+    Instrument { waveform: Waveform::Saw, frequency: 660, envelope: percussive(2, 60), hold_ms: 0 },
+    // TCP, short ticks, there are a lot of them
+    Instrument { waveform: Waveform::Square, frequency: 220, envelope: percussive(1, 15), hold_ms: 0 },
+    // HTTP
+    Instrument {
+        waveform: Waveform::Saw,
+        frequency: 262,
+        envelope: Adsr { attack_ms: 5, decay_ms: 30, sustain_percent: 50, release_ms: 40 },
+        hold_ms: 30
+    },
+    // ICMPv6, an octave above ICMP
+    Instrument { waveform: Waveform::Sine, frequency: 2640, envelope: percussive(1, 100), hold_ms: 0 },
+    // NDP
+    Instrument { waveform: Waveform::Triangle, frequency: 880, envelope: percussive(1, 40), hold_ms: 0 },
+    // DHCPv6, like DHCP a fifth up
+    Instrument {
+        waveform: Waveform::Sine,
+        frequency: 495,
+        envelope: Adsr { attack_ms: 20, decay_ms: 50, sustain_percent: 60, release_ms: 150 },
+        hold_ms: 100
+    },
+    // LLDP, a bell every 30 seconds per switch
+    Instrument {
+        waveform: Waveform::Triangle,
+        frequency: 523,
+        envelope: Adsr { attack_ms: 2, decay_ms: 200, sustain_percent: 30, release_ms: 400 },
+        hold_ms: 50
+    },
+    // CDP, the same bell a tone higher
+    Instrument {
+        waveform: Waveform::Triangle,
+        frequency: 587,
+        envelope: Adsr { attack_ms: 2, decay_ms: 200, sustain_percent: 30, release_ms: 400 },
+        hold_ms: 50
+    },
+];
+
+// major pentatonic, in thousandths of the base frequency
+static SCALE: [u32; 5] = [1000, 1125, 1250, 1500, 1667];
+
+pub struct Sonifier {
+    synth: Synth
+}
+
+impl Sonifier {
+    pub fn new() -> Self {
+        Sonifier { synth: Synth::new() }
+    }
+
+    /// Starts the note of a received frame, unless the sound is off or the
+    /// frame type is muted.
+    pub fn on_packet(&mut self, parse_result: &ParseResult, settings: &Settings) {
+        let index = parse_result.pkt_type.index();
+        if !settings.sound || settings.muted[index] {
+            return;
+        }
+        let instrument = &INSTRUMENTS[index];
+
+        let address_hash = match (parse_result.ipv4_src, parse_result.mac_src) {
+            (Some(ip), _) => Some(palette::hash(&ip)),
+            (None, Some(mac)) => Some(palette::hash(&mac)),
+            (None, None) => None,
+        };
+        let step = match address_hash {
+            Some(h) => SCALE[(h % SCALE.len() as u32) as usize],
+            None => SCALE[0],
+        };
+
+        self.synth.set_volume(settings.volume);
+        self.synth.play(Note {
+            waveform: instrument.waveform,
+            frequency: instrument.frequency * step / 1000,
+            envelope: instrument.envelope,
+            hold_ms: Some(instrument.hold_ms),
+            // squares sound much louder than the rest at the same level
+            gain: if instrument.waveform == Waveform::Square { 40 } else { 100 },
+            pan: 0
+        });
+    }
+
+    /// Overwrites `frames` with the playing notes.
+    pub fn fill(&mut self, frames: &mut [Frame]) {
+        self.synth.fill(frames);
+    }
+}
+
+/// The volume step after `volume`, wrapping around to silence.
+pub fn next_volume(volume: u8) -> u8 {
+    match VOLUMES.iter().position(|&(v, _)| v == volume) {
+        Some(index) => VOLUMES[(index + 1) % VOLUMES.len()].0,
+        None => VOLUMES[0].0,
+    }
+}
+
+pub fn volume_name(volume: u8) -> &'static str {
+    match VOLUMES.iter().find(|&&(v, _)| v == volume) {
+        Some(&(_, name)) => name,
+        None => "?",
+    }
+}
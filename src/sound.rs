@@ -7,17 +7,22 @@ use bit_field::BitField;
 use stm32f7::system_clock;
 
 const WM8994_ADDRESS: i2c::Address = i2c::Address::bits_7(0b0011010);
+const WM8994_ID: u16 = 0x8994;
 
-pub struct Sound {
-    written: u32,
+pub struct Sound;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The codec didn't answer over I2C
+    I2c(i2c::Error),
+    /// Something else than a WM8994 answered, with this device ID
+    UnknownCodec(u16),
 }
 
 impl Sound {
 
     fn init_clock(sai: &mut Sai, i2c_3: &mut i2c::I2C, rcc: &mut Rcc) {
 
-        // println!("getting bit of pllsrc {}", rcc.pllcfgr.read().pllsrc());
-        // println!("getting factor n of pllsaicfgr {}", rcc.pllsaicfgr.read().pllsain());
         // TODO if we are using PLLSAIQ to drive this unit, is PLLI2S relevant?
         // // Disable the PLLI2S
         // rcc.cr.update(|r| r.set_plli2son(false));
@@ -36,7 +41,6 @@ impl Sound {
         rcc.dkcfgr1.update(|r| {
             r.set_sai2sel(0b01); // SET PLLSAI_Q / PLLSAIDIVQ
         });
-        // println!("clock config");
 
         // In case PLLSOURCE is HSE
         // then PLL_(VCO INPUT) = PLLSRC/PLLM
@@ -100,7 +104,7 @@ impl Sound {
             };
 
             sai.acr1.update(|r| r.set_mcjdiv(mckdiv as u8));
-            println!("Set MCKDIV to {}", mckdiv);
+            debug!("Set MCKDIV to {}", mckdiv);
         }
 
 
@@ -118,7 +122,7 @@ impl Sound {
 
     }
 
-    pub fn init(sai: &mut Sai, i2c_3: &mut i2c::I2C, rcc: &mut Rcc, gpio: &mut Gpio) -> Self {
+    pub fn init(sai: &mut Sai, i2c_3: &mut i2c::I2C, rcc: &mut Rcc, gpio: &mut Gpio) -> Result<Self, Error> {
 
         sai.acr1.update(|r| r.set_saiaen(false));
         sai.bcr1.update(|r| r.set_saiben(false));
@@ -199,31 +203,36 @@ impl Sound {
         });
 
         sai.acr1.update(|r| r.set_saiaen(true));
-        println!("before wait for saienable");
         while !sai.acr1.read().saiaen() {}
-        println!("after wait for saienable");
 
         // read status bits
         {
             let reg = sai.asr.read();
             if reg.wckcfg() {
-                println!("Configured clock is wrong!");
+                error!("Configured clock is wrong!");
             }
             if reg.ovrudr() {
-                println!("Fifo Overrun/Underrun detected");
+                warn!("Fifo Overrun/Underrun detected");
             }
 
-            println!("fifo threshhold is {}, should be 0 at this point", reg.flvl());
+            debug!("fifo threshhold is {}, should be 0 at this point", reg.flvl());
         }
 
 
         self::config_gpio(gpio);
 
-        let ret = i2c_3.connect::<u16, _>(WM8994_ADDRESS, |mut conn| {
-
+        // read and check device family ID
+        let mut id = 0;
+        i2c_3.connect::<u16, _>(WM8994_ADDRESS, |mut conn| {
             system_clock::wait(10);
-            // read and check device family ID
-            assert_eq!(conn.read(0).ok(), Some(0x8994));
+            id = conn.read(0)?;
+            Ok(())
+        }).map_err(Error::I2c)?;
+        if id != WM8994_ID {
+            return Err(Error::UnknownCodec(id));
+        }
+
+        i2c_3.connect::<u16, _>(WM8994_ADDRESS, |mut conn| {
             // reset device
             conn.write(0, 0)?;
 
@@ -404,51 +413,13 @@ impl Sound {
             }
 
             system_clock::wait(10);
-            println!("Status WM8994 Register: {:b}", conn.read(0x212)?);
+            debug!("Status WM8994 Register: {:b}", conn.read(0x212)?);
             Ok(())
-        });
+        }).map_err(Error::I2c)?;
 
 
-        Sound {
-        written: 0
-        }
-    }
-
-    pub fn tick(&mut self) {
-        // println!("Sound Tick");
-    }
-
-
-    #[inline(never)]
-    pub fn put_data(&mut self, sai: &mut Sai, i2c_3: &mut i2c::I2C, data: u32) -> u32 {
-
-        let mut fifo_data = data;
-
-        while sai.asr.read().flvl() != 0b101 {
-
-            // println!("writing in FIFO");
-            // sai.adr.update(|reg| reg.set_data(fifo_data & 0xFFFFFFFF));
-            let mut actual_data = sai::Adr::default();
-            actual_data.set_data(fifo_data);
-            let zero_data = sai::Adr::default();
-            sai.adr.write(actual_data);
-            sai.adr.write(zero_data);
-            sai.adr.write(zero_data);
-            sai.adr.write(zero_data);
-            self.written = self.written.wrapping_add(32 * 4);
-            fifo_data = fifo_data.wrapping_add(10000);
-
-            // self.written = self.written.wrapping_add(32);
-
-            // fifo_data = fifo_data.wrapping_add(10000);
-            // sai.acr2.update(|r| r.set_fflus(true));
-            // sai.acr1.update(|r| r.set_saiaen(false));
-            // sai.acr1.update(|r| r.set_saiaen(true));
-
-        }
-        // println!("FIFO full, {} written", self.written);
-        self.written = 0;
-        return fifo_data;
+        // the samples are written by `audio_dma`
+        Ok(Sound)
     }
 }
 
@@ -0,0 +1,91 @@
+use classify::{ParseResult, TYPE_COUNT};
+use context::TICKS_PER_SECOND;
+
+// rates are recomputed once per interval
+const RATE_INTERVAL_TICKS: usize = TICKS_PER_SECOND;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    frames: u32,
+    bytes: u32
+}
+
+/// Frame and byte counters per `ParseResultType`, plus overall rates.
+pub struct Stats {
+    per_type: [Counter; TYPE_COUNT],
+    total: Counter,
+    // totals at the start of the current rate interval
+    interval_start: Counter,
+    interval_start_ticks: usize,
+    frames_per_second: u32,
+    bytes_per_second: u32
+}
+
+impl Stats {
+    pub fn new(ticks: usize) -> Self {
+        Stats {
+            per_type: [Counter::default(); TYPE_COUNT],
+            total: Counter::default(),
+            interval_start: Counter::default(),
+            interval_start_ticks: ticks,
+            frames_per_second: 0,
+            bytes_per_second: 0
+        }
+    }
+
+    pub fn count(&mut self, parse_result: &ParseResult) {
+        let bytes = parse_result.len as u32;
+        {
+            let counter = &mut self.per_type[parse_result.pkt_type.index()];
+            counter.frames = counter.frames.wrapping_add(1);
+            counter.bytes = counter.bytes.wrapping_add(bytes);
+        }
+        self.total.frames = self.total.frames.wrapping_add(1);
+        self.total.bytes = self.total.bytes.wrapping_add(bytes);
+    }
+
+    /// Updates the rates once per interval.
+    pub fn tick(&mut self, ticks: usize) {
+        let elapsed = ticks.wrapping_sub(self.interval_start_ticks);
+        if elapsed < RATE_INTERVAL_TICKS {
+            return;
+        }
+
+        let frames = self.total.frames.wrapping_sub(self.interval_start.frames);
+        let bytes = self.total.bytes.wrapping_sub(self.interval_start.bytes);
+        self.frames_per_second = (frames as u64 * TICKS_PER_SECOND as u64 / elapsed as u64) as u32;
+        self.bytes_per_second = (bytes as u64 * TICKS_PER_SECOND as u64 / elapsed as u64) as u32;
+
+        self.interval_start = self.total;
+        self.interval_start_ticks = ticks;
+    }
+
+    /// The ticks at which the rates were last updated.
+    pub fn last_update(&self) -> usize {
+        self.interval_start_ticks
+    }
+
+    pub fn frames(&self, type_index: usize) -> u32 {
+        self.per_type[type_index].frames
+    }
+
+    pub fn bytes(&self, type_index: usize) -> u32 {
+        self.per_type[type_index].bytes
+    }
+
+    pub fn total_frames(&self) -> u32 {
+        self.total.frames
+    }
+
+    pub fn total_bytes(&self) -> u32 {
+        self.total.bytes
+    }
+
+    pub fn frames_per_second(&self) -> u32 {
+        self.frames_per_second
+    }
+
+    pub fn bytes_per_second(&self) -> u32 {
+        self.bytes_per_second
+    }
+}
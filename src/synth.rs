@@ -0,0 +1,613 @@
+// A small synthesizer for the audio output: phase accumulator oscillators,
+// ADSR envelopes and a mixer of a few voices into stereo frames.
+
+use core::cmp;
+
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Voices that can play at the same time.
+pub const VOICES: usize = 8;
+
+// envelope levels are fractions of this
+const FULL_LEVEL: u32 = 0x8000;
+
+// 2^32 = PHASE_STEP_PER_HZ * SAMPLE_RATE + PHASE_STEP_REMAINDER, splitting it
+// spares a 64 bit division per note
+const PHASE_STEP_PER_HZ: u32 = 89_478;
+const PHASE_STEP_REMAINDER: u32 = 23_296;
+
+// voices at full level that fit into the mix before it is clipped
+const HEADROOM: i32 = 3;
+
+// one period, generated by sin.py
+static SINE: [i16; 256] = [
+    0, 804, 1608, 2410, 3212, 4011, 4808, 5602, 6393, 7179, 7962, 8739, 9512, 10278, 11039, 11793,
+    12539, 13279, 14010, 14732, 15446, 16151, 16846, 17530, 18204, 18868, 19519, 20159, 20787, 21403, 22005, 22594,
+    23170, 23731, 24279, 24811, 25329, 25832, 26319, 26790, 27245, 27683, 28105, 28510, 28898, 29268, 29621, 29956,
+    30273, 30571, 30852, 31113, 31356, 31580, 31785, 31971, 32137, 32285, 32412, 32521, 32609, 32678, 32728, 32757,
+    32767, 32757, 32728, 32678, 32609, 32521, 32412, 32285, 32137, 31971, 31785, 31580, 31356, 31113, 30852, 30571,
+    30273, 29956, 29621, 29268, 28898, 28510, 28105, 27683, 27245, 26790, 26319, 25832, 25329, 24811, 24279, 23731,
+    23170, 22594, 22005, 21403, 20787, 20159, 19519, 18868, 18204, 17530, 16846, 16151, 15446, 14732, 14010, 13279,
+    12539, 11793, 11039, 10278, 9512, 8739, 7962, 7179, 6393, 5602, 4808, 4011, 3212, 2410, 1608, 804,
+    0, -804, -1608, -2410, -3212, -4011, -4808, -5602, -6393, -7179, -7962, -8739, -9512, -10278, -11039, -11793,
+    -12539, -13279, -14010, -14732, -15446, -16151, -16846, -17530, -18204, -18868, -19519, -20159, -20787, -21403, -22005, -22594,
+    -23170, -23731, -24279, -24811, -25329, -25832, -26319, -26790, -27245, -27683, -28105, -28510, -28898, -29268, -29621, -29956,
+    -30273, -30571, -30852, -31113, -31356, -31580, -31785, -31971, -32137, -32285, -32412, -32521, -32609, -32678, -32728, -32757,
+    -32767, -32757, -32728, -32678, -32609, -32521, -32412, -32285, -32137, -31971, -31785, -31580, -31356, -31113, -30852, -30571,
+    -30273, -29956, -29621, -29268, -28898, -28510, -28105, -27683, -27245, -26790, -26319, -25832, -25329, -24811, -24279, -23731,
+    -23170, -22594, -22005, -21403, -20787, -20159, -19519, -18868, -18204, -17530, -16846, -16151, -15446, -14732, -14010, -13279,
+    -12539, -11793, -11039, -10278, -9512, -8739, -7962, -7179, -6393, -5602, -4808, -4011, -3212, -2410, -1608, -804,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Triangle,
+    Saw,
+    /// White noise, ignores the frequency
+    Noise
+}
+
+/// A stereo sample, laid out as the SAI expects it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Frame {
+    pub left: i16,
+    pub right: i16
+}
+
+/// A waveform at a frequency. A full period is 2^32 of the phase.
+#[derive(Debug, Clone, Copy)]
+pub struct Oscillator {
+    waveform: Waveform,
+    phase: u32,
+    phase_step: u32,
+    // xorshift state of the noise, never zero
+    noise: u32
+}
+
+impl Oscillator {
+    pub fn new(waveform: Waveform, frequency: u32) -> Self {
+        let mut oscillator = Oscillator {
+            waveform: waveform,
+            phase: 0,
+            phase_step: 0,
+            noise: 0x1234_5678
+        };
+        oscillator.set_frequency(frequency);
+        oscillator
+    }
+
+    /// In Hz, clamped to what the sample rate can represent.
+    pub fn set_frequency(&mut self, frequency: u32) {
+        let frequency = cmp::min(frequency, SAMPLE_RATE / 2);
+        self.phase_step = frequency * PHASE_STEP_PER_HZ + frequency * PHASE_STEP_REMAINDER / SAMPLE_RATE;
+    }
+
+    /// The current sample, then advances by one sample.
+    pub fn next(&mut self) -> i16 {
+        let value = match self.waveform {
+            Waveform::Sine => {
+                // linear interpolation between the table entries
+                let index = (self.phase >> 24) as usize;
+                let fraction = ((self.phase >> 8) & 0xffff) as i32;
+                let a = SINE[index] as i32;
+                let b = SINE[(index + 1) % SINE.len()] as i32;
+                a + ((b - a) * fraction >> 16)
+            }
+            Waveform::Square => if self.phase < 0x8000_0000 { 0x7fff } else { -0x7fff },
+            Waveform::Triangle => {
+                let t = (self.phase >> 15) as i32;
+                if t < 0x1_0000 { t - 0x8000 } else { 0x1_7fff - t }
+            }
+            Waveform::Saw => (self.phase >> 16) as i32 - 0x8000,
+            Waveform::Noise => {
+                self.noise ^= self.noise << 13;
+                self.noise ^= self.noise >> 17;
+                self.noise ^= self.noise << 5;
+                (self.noise >> 16) as i32 - 0x8000
+            }
+        };
+        self.phase = self.phase.wrapping_add(self.phase_step);
+        value as i16
+    }
+}
+
+/// The shape of a note's loudness: it rises to full level during `attack`,
+/// falls to `sustain_percent` during `decay`, stays there until the note is
+/// released and then fades to silence during `release`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adsr {
+    pub attack_ms: u32,
+    pub decay_ms: u32,
+    pub sustain_percent: u8,
+    pub release_ms: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    adsr: Adsr,
+    stage: Stage,
+    // 16.16 fixed point, up to FULL_LEVEL
+    level: u32,
+    // per sample, for the current stage
+    step: u32
+}
+
+impl Envelope {
+    pub fn new(adsr: Adsr) -> Self {
+        Envelope { adsr: adsr, stage: Stage::Idle, level: 0, step: 0 }
+    }
+
+    /// Starts the attack, from the current level if the envelope is still
+    /// running.
+    pub fn trigger(&mut self) {
+        self.enter(Stage::Attack);
+    }
+
+    /// Starts the release, unless the envelope is already idle.
+    pub fn release(&mut self) {
+        if self.stage != Stage::Idle {
+            self.enter(Stage::Release);
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    /// The current level in `0..=0x8000`, then advances by one sample.
+    pub fn next(&mut self) -> u32 {
+        let level = self.level >> 16;
+        match self.stage {
+            Stage::Idle | Stage::Sustain => {}
+            Stage::Attack => {
+                self.level = cmp::min(self.level + self.step, FULL_LEVEL << 16);
+                if self.level == FULL_LEVEL << 16 {
+                    self.enter(Stage::Decay);
+                }
+            }
+            Stage::Decay => {
+                let sustain = self.sustain_level();
+                self.level = cmp::max(self.level.saturating_sub(self.step), sustain);
+                if self.level == sustain {
+                    self.enter(Stage::Sustain);
+                }
+            }
+            Stage::Release => {
+                self.level = self.level.saturating_sub(self.step);
+                if self.level == 0 {
+                    self.enter(Stage::Idle);
+                }
+            }
+        }
+        level
+    }
+
+    fn sustain_level(&self) -> u32 {
+        (FULL_LEVEL * cmp::min(self.adsr.sustain_percent, 100) as u32 / 100) << 16
+    }
+
+    // zero length stages are skipped right away
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        let (distance, ms) = match stage {
+            Stage::Idle => {
+                self.level = 0;
+                return;
+            }
+            Stage::Sustain => return,
+            Stage::Attack => ((FULL_LEVEL << 16) - self.level, self.adsr.attack_ms),
+            Stage::Decay => (self.level.saturating_sub(self.sustain_level()), self.adsr.decay_ms),
+            Stage::Release => (self.level, self.adsr.release_ms),
+        };
+        let samples = ms_to_samples(ms);
+        if samples == 0 || distance == 0 {
+            match stage {
+                Stage::Attack => {
+                    self.level = FULL_LEVEL << 16;
+                    self.enter(Stage::Decay);
+                }
+                Stage::Decay => {
+                    self.level = self.sustain_level();
+                    self.enter(Stage::Sustain);
+                }
+                _ => self.enter(Stage::Idle),
+            }
+        } else {
+            // rounded up, so the stage ends after at most `samples`
+            self.step = (distance + samples - 1) / samples;
+        }
+    }
+}
+
+/// Everything about a note to play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub waveform: Waveform,
+    /// In Hz
+    pub frequency: u32,
+    pub envelope: Adsr,
+    /// How long the note is held before the release starts, `None` holds it
+    /// until `Synth::release`
+    pub hold_ms: Option<u32>,
+    /// In percent
+    pub gain: u8,
+    /// From -100 (left only) to 100 (right only)
+    pub pan: i8
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    oscillator: Oscillator,
+    envelope: Envelope,
+    // samples until the release, counted from the trigger
+    hold: Option<u32>,
+    gain: u8,
+    pan: i8,
+    // `Synth::played` when the note started
+    started: u32
+}
+
+impl Voice {
+    fn silent() -> Self {
+        Voice {
+            oscillator: Oscillator::new(Waveform::Sine, 0),
+            envelope: Envelope::new(Adsr { attack_ms: 0, decay_ms: 0, sustain_percent: 0, release_ms: 0 }),
+            hold: None,
+            gain: 0,
+            pan: 0,
+            started: 0
+        }
+    }
+}
+
+/// Mixes up to `VOICES` notes into stereo frames.
+pub struct Synth {
+    voices: [Voice; VOICES],
+    // percent
+    volume: u8,
+    // notes played so far, wrapping
+    played: u32
+}
+
+impl Synth {
+    pub fn new() -> Self {
+        Synth {
+            voices: [Voice::silent(); VOICES],
+            volume: 100,
+            played: 0
+        }
+    }
+
+    /// The master volume in percent.
+    pub fn set_volume(&mut self, percent: u8) {
+        self.volume = cmp::min(percent, 100);
+    }
+
+    /// Starts a note on an idle voice, or on the voice that started playing
+    /// longest ago if none is idle. Returns the voice for `release`.
+    pub fn play(&mut self, note: Note) -> usize {
+        let played = self.played;
+        let index = match self.voices.iter().position(|v| v.envelope.is_idle()) {
+            Some(index) => index,
+            None => self.voices.iter().enumerate()
+                .max_by_key(|&(_, v)| played.wrapping_sub(v.started))
+                .map(|(index, _)| index)
+                .unwrap_or(0),
+        };
+        self.played = played.wrapping_add(1);
+        let voice = &mut self.voices[index];
+        voice.started = played;
+        voice.oscillator = Oscillator::new(note.waveform, note.frequency);
+        voice.envelope = Envelope::new(note.envelope);
+        voice.envelope.trigger();
+        voice.hold = note.hold_ms.map(ms_to_samples);
+        voice.gain = cmp::min(note.gain, 100);
+        voice.pan = cmp::max(cmp::min(note.pan, 100), -100);
+        index
+    }
+
+    /// Starts the release of a voice returned by `play`.
+    pub fn release(&mut self, voice: usize) {
+        if voice < VOICES {
+            self.voices[voice].envelope.release();
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.envelope.release();
+        }
+    }
+
+    /// The number of voices that are not idle.
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|v| !v.envelope.is_idle()).count()
+    }
+
+    /// Mixes the next frame of all voices.
+    pub fn next_frame(&mut self) -> Frame {
+        let mut left = 0i32;
+        let mut right = 0i32;
+        for voice in self.voices.iter_mut() {
+            if voice.envelope.is_idle() {
+                continue;
+            }
+            match voice.hold {
+                Some(0) => {
+                    voice.envelope.release();
+                    voice.hold = None;
+                }
+                Some(ref mut samples) => *samples -= 1,
+                None => {}
+            }
+            let level = voice.envelope.next() as i32;
+            let sample = voice.oscillator.next() as i32 * level / FULL_LEVEL as i32 * voice.gain as i32 / 100;
+            // full level on the near side, fading out on the far side
+            let pan = voice.pan as i32;
+            left += sample * cmp::min(100, 100 - pan) / 100;
+            right += sample * cmp::min(100, 100 + pan) / 100;
+        }
+        Frame {
+            left: self.output(left),
+            right: self.output(right)
+        }
+    }
+
+    /// Overwrites `frames` with the next frames.
+    pub fn fill(&mut self, frames: &mut [Frame]) {
+        for frame in frames.iter_mut() {
+            *frame = self.next_frame();
+        }
+    }
+
+    fn output(&self, mix: i32) -> i16 {
+        let sample = mix / HEADROOM * self.volume as i32 / 100;
+        cmp::max(cmp::min(sample, i16::max_value() as i32), i16::min_value() as i32) as i16
+    }
+}
+
+pub fn ms_to_samples(ms: u32) -> u32 {
+    ms * (SAMPLE_RATE / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adsr, Envelope, Frame, Note, Oscillator, Synth, Waveform, ms_to_samples, SAMPLE_RATE, VOICES};
+
+    fn samples(waveform: Waveform, frequency: u32, count: usize) -> Vec<i16> {
+        let mut oscillator = Oscillator::new(waveform, frequency);
+        (0..count).map(|_| oscillator.next()).collect()
+    }
+
+    // rising zero crossings, one per period
+    fn periods(samples: &[i16]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count()
+    }
+
+    const FLAT: Adsr = Adsr { attack_ms: 0, decay_ms: 0, sustain_percent: 100, release_ms: 0 };
+
+    fn note(waveform: Waveform, frequency: u32) -> Note {
+        Note { waveform: waveform, frequency: frequency, envelope: FLAT, hold_ms: None, gain: 100, pan: 0 }
+    }
+
+    #[test]
+    fn sine_has_the_frequency_and_amplitude() {
+        let one_second = samples(Waveform::Sine, 440, SAMPLE_RATE as usize);
+        let count = periods(&one_second);
+        assert!(count >= 439 && count <= 441, "{} periods", count);
+        assert!(*one_second.iter().max().unwrap() > 32_000);
+        assert!(*one_second.iter().min().unwrap() < -32_000);
+    }
+
+    #[test]
+    fn sine_starts_at_zero_and_peaks_after_a_quarter_period() {
+        // 1 kHz is 48 samples per period
+        let period = samples(Waveform::Sine, 1000, 48);
+        assert_eq!(period[0], 0);
+        assert!(period[12] > 32_000);
+        assert!(period[24].abs() < 200);
+        assert!(period[36] < -32_000);
+    }
+
+    #[test]
+    fn arbitrary_frequencies() {
+        for &frequency in &[50, 261, 1234, 9999] {
+            let count = periods(&samples(Waveform::Saw, frequency, SAMPLE_RATE as usize));
+            assert!(count as i64 - frequency as i64 <= 1 && frequency as i64 - count as i64 <= 1,
+                    "{} Hz gave {} periods", frequency, count);
+        }
+    }
+
+    #[test]
+    fn square_has_two_levels_and_half_duty() {
+        // the phase step is rounded down, so count over many periods
+        let second = samples(Waveform::Square, 1000, SAMPLE_RATE as usize);
+        assert!(second.iter().all(|&s| s == 0x7fff || s == -0x7fff));
+        let high = second.iter().filter(|&&s| s > 0).count();
+        assert!(high >= 23_990 && high <= 24_010, "{} high samples", high);
+    }
+
+    #[test]
+    fn triangle_rises_then_falls() {
+        let period = samples(Waveform::Triangle, 1000, 48);
+        assert_eq!(period[0], -0x8000);
+        assert!(period[..24].windows(2).all(|w| w[0] < w[1]));
+        assert!(period[24..].windows(2).all(|w| w[0] > w[1]));
+        assert!(period[24] > 32_000);
+    }
+
+    #[test]
+    fn saw_rises_and_drops() {
+        let periods = samples(Waveform::Saw, 1000, 96);
+        let drops: Vec<usize> = (1..periods.len()).filter(|&i| periods[i] < periods[i - 1]).collect();
+        assert_eq!(drops.len(), 1);
+        assert!(drops[0] == 48 || drops[0] == 49);
+    }
+
+    #[test]
+    fn noise_is_spread_out_and_repeatable() {
+        let noise = samples(Waveform::Noise, 0, 4800);
+        let positive = noise.iter().filter(|&&s| s > 0).count();
+        assert!(positive > 2000 && positive < 2800);
+        assert!(noise.windows(2).filter(|w| w[0] == w[1]).count() < 10);
+        assert_eq!(noise, samples(Waveform::Noise, 440, 4800));
+    }
+
+    #[test]
+    fn envelope_goes_through_the_stages() {
+        let mut envelope = Envelope::new(Adsr { attack_ms: 10, decay_ms: 10, sustain_percent: 50, release_ms: 20 });
+        assert!(envelope.is_idle());
+        envelope.trigger();
+
+        let attack: Vec<u32> = (0..ms_to_samples(10)).map(|_| envelope.next()).collect();
+        assert_eq!(attack[0], 0);
+        assert!(attack.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(envelope.next(), 0x8000);
+
+        for _ in 0..ms_to_samples(10) {
+            envelope.next();
+        }
+        assert_eq!(envelope.next(), 0x4000);
+        // sustains until released
+        for _ in 0..SAMPLE_RATE {
+            assert_eq!(envelope.next(), 0x4000);
+        }
+
+        envelope.release();
+        let release: Vec<u32> = (0..ms_to_samples(20)).map(|_| envelope.next()).collect();
+        assert!(release.windows(2).all(|w| w[0] > w[1]));
+        assert!(envelope.is_idle());
+        assert_eq!(envelope.next(), 0);
+    }
+
+    #[test]
+    fn zero_length_stages_are_skipped() {
+        let mut envelope = Envelope::new(Adsr { attack_ms: 0, decay_ms: 0, sustain_percent: 25, release_ms: 0 });
+        envelope.trigger();
+        assert_eq!(envelope.next(), 0x2000);
+        envelope.release();
+        assert!(envelope.is_idle());
+    }
+
+    #[test]
+    fn release_before_the_attack_ends() {
+        let mut envelope = Envelope::new(Adsr { attack_ms: 100, decay_ms: 0, sustain_percent: 100, release_ms: 1 });
+        envelope.trigger();
+        for _ in 0..100 {
+            envelope.next();
+        }
+        envelope.release();
+        for _ in 0..ms_to_samples(1) {
+            envelope.next();
+        }
+        assert!(envelope.is_idle());
+    }
+
+    #[test]
+    fn idle_synth_is_silent() {
+        let mut synth = Synth::new();
+        let mut frames = [Frame { left: 1, right: 1 }; 64];
+        synth.fill(&mut frames);
+        assert!(frames.iter().all(|f| *f == Frame::default()));
+        assert_eq!(synth.active_voices(), 0);
+    }
+
+    #[test]
+    fn held_notes_end_by_themselves() {
+        let mut synth = Synth::new();
+        let mut held = note(Waveform::Square, 1000);
+        held.hold_ms = Some(5);
+        held.envelope.release_ms = 5;
+        synth.play(held);
+        assert_eq!(synth.active_voices(), 1);
+
+        let mut frames = vec![Frame::default(); ms_to_samples(10) as usize + 1];
+        synth.fill(&mut frames);
+        assert!(frames[..ms_to_samples(5) as usize].iter().all(|f| f.left != 0));
+        assert_eq!(synth.active_voices(), 0);
+        assert_eq!(*frames.last().unwrap(), Frame::default());
+    }
+
+    #[test]
+    fn pans_and_mixes() {
+        let mut synth = Synth::new();
+        let mut left = note(Waveform::Square, 1000);
+        left.pan = -100;
+        synth.play(left);
+        let frame = synth.next_frame();
+        assert_eq!(frame.right, 0);
+        assert_eq!(frame.left, 0x7fff / 3);
+
+        // a second voice in the center adds to both sides
+        synth.play(note(Waveform::Square, 1000));
+        let frame = synth.next_frame();
+        assert_eq!(frame.left, (2 * 0x7fffi32 / 3) as i16);
+        assert_eq!(frame.right, 0x7fff / 3);
+    }
+
+    #[test]
+    fn clips_instead_of_wrapping() {
+        let mut synth = Synth::new();
+        for _ in 0..VOICES {
+            synth.play(note(Waveform::Square, 1000));
+        }
+        let frame = synth.next_frame();
+        assert_eq!(frame.left, i16::max_value());
+        assert_eq!(frame.right, i16::max_value());
+    }
+
+    #[test]
+    fn volume_and_gain_scale_the_output() {
+        let mut synth = Synth::new();
+        let mut quiet = note(Waveform::Square, 1000);
+        quiet.gain = 50;
+        synth.play(quiet);
+        synth.set_volume(50);
+        assert_eq!(synth.next_frame().left, 0x7fff / 2 / 3 / 2);
+
+        synth.set_volume(0);
+        assert_eq!(synth.next_frame(), Frame::default());
+    }
+
+    #[test]
+    fn steals_the_oldest_voice_when_full() {
+        let mut synth = Synth::new();
+        let voices: Vec<usize> = (0..VOICES).map(|_| synth.play(note(Waveform::Sine, 440))).collect();
+        assert_eq!(voices, (0..VOICES).collect::<Vec<_>>());
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 0);
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 1);
+        assert_eq!(synth.active_voices(), VOICES);
+
+        synth.release_all();
+        synth.next_frame();
+        assert_eq!(synth.active_voices(), 0);
+    }
+
+    #[test]
+    fn steals_by_start_not_in_turn() {
+        let mut synth = Synth::new();
+        for _ in 0..VOICES {
+            synth.play(note(Waveform::Sine, 440));
+        }
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 0);
+        // voice 1 ends early and is reused, so it is the newest now
+        synth.release(1);
+        synth.next_frame();
+        assert_eq!(synth.active_voices(), VOICES - 1);
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 1);
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 2);
+        assert_eq!(synth.play(note(Waveform::Sine, 880)), 3);
+    }
+}
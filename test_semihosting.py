@@ -0,0 +1,235 @@
+"""Tests for semihosting.py without gdb or a board.
+
+Run with `python3 -m unittest test_semihosting`. A fake `gdb` module stands
+in for the debugger: the board's memory is a bytearray and `bkpt 0xab` is
+always the current instruction.
+"""
+
+from __future__ import print_function
+import io
+import os
+import shutil
+import struct
+import sys
+import tempfile
+import types
+import unittest
+
+MEMORY_SIZE = 0x1000
+
+
+class FakeInferior(object):
+    def __init__(self):
+        self.memory = bytearray(MEMORY_SIZE)
+
+    def read_memory(self, addr, length):
+        # gdb reads past the end of a string, the fake memory just ends
+        return bytes(self.memory[addr:addr + length])
+
+    def write_memory(self, addr, data):
+        self.memory[addr:addr + len(data)] = data
+
+
+class FakeArchitecture(object):
+    def disassemble(self, pc):
+        return [{'asm': 'bkpt 0x00ab'}]
+
+
+class FakeFrame(object):
+    def architecture(self):
+        return FakeArchitecture()
+
+    def pc(self):
+        return 0
+
+
+class FakeGdb(types.ModuleType):
+    def __init__(self):
+        super(FakeGdb, self).__init__('gdb')
+        self.inferior = FakeInferior()
+        self.registers = {'$r0': 0, '$r1': 0}
+        self.convenience = {}
+        self.output = ''
+
+    def selected_frame(self):
+        return FakeFrame()
+
+    def selected_inferior(self):
+        return self.inferior
+
+    def parse_and_eval(self, expression):
+        return self.registers[expression]
+
+    def execute(self, command):
+        # only `set $name = value` is used
+        name, value = command[len('set '):].split(' = ')
+        if name in self.registers:
+            self.registers[name] = int(value)
+        else:
+            self.convenience[name] = int(value)
+
+    def write(self, text):
+        self.output += text
+
+
+gdb = FakeGdb()
+sys.modules['gdb'] = gdb
+from semihosting import SemiHostHelper
+
+ARGS = 0x100
+DATA = 0x200
+NAME = 0x400
+
+
+class SemiHostingTest(unittest.TestCase):
+    def setUp(self):
+        gdb.inferior.memory[:] = bytearray(MEMORY_SIZE)
+        gdb.output = ''
+        gdb.convenience.clear()
+        SemiHostHelper.files.clear()
+        SemiHostHelper.next_fd = 3
+        SemiHostHelper.errno = 0
+        SemiHostHelper.decoders.clear()
+        self.dir = tempfile.mkdtemp()
+
+    def tearDown(self):
+        for f in SemiHostHelper.files.values():
+            f.close()
+        shutil.rmtree(self.dir)
+
+    def call(self, call_type, *args):
+        """Does a semihosting call with `args` as the argument struct and
+        returns r0 as the board would see it (signed)."""
+        gdb.inferior.write_memory(ARGS, struct.pack('<' + 'L' * len(args), *args))
+        return self.call_raw(call_type, ARGS)
+
+    def call_raw(self, call_type, r1):
+        gdb.registers['$r0'] = call_type
+        gdb.registers['$r1'] = r1
+        SemiHostHelper.on_break()
+        return struct.unpack('<l', struct.pack('<L', gdb.registers['$r0']))[0]
+
+    def put(self, addr, data):
+        gdb.inferior.write_memory(addr, data)
+
+    def open(self, name, mode):
+        name = name.encode('utf8')
+        self.put(NAME, name + b'\0')
+        return self.call(0x01, NAME, mode, len(name))
+
+    def path(self, name):
+        return os.path.join(self.dir, name)
+
+    def test_write_stdout(self):
+        self.put(DATA, b'hello\n')
+        self.assertEqual(self.call(0x05, 1, DATA, 6), 0)
+        self.assertEqual(gdb.output, 'hello\n')
+        self.assertEqual(gdb.convenience['$do_continue'], 1)
+
+    def test_write_character_split_across_writes(self):
+        # the two bytes of an u umlaut in two flushes
+        self.put(DATA, b'gr\xc3')
+        self.assertEqual(self.call(0x05, 1, DATA, 3), 0)
+        self.put(DATA, b'\xbcn\n')
+        self.assertEqual(self.call(0x05, 1, DATA, 3), 0)
+        self.assertEqual(gdb.output, u'gr\u00fcn\n')
+
+    def test_write_invalid_utf8(self):
+        self.put(DATA, b'a\xffb')
+        self.assertEqual(self.call(0x05, 2, DATA, 3), 0)
+        self.assertEqual(gdb.output, u'a\ufffdb')
+
+    def test_write_refuses_unknown_fd(self):
+        with self.assertRaises(ValueError):
+            self.call(0x05, 7, DATA, 1)
+
+    def test_writec_and_write0(self):
+        self.put(DATA, b'x')
+        self.call_raw(0x03, DATA)
+        self.put(DATA, b'yz\0garbage')
+        self.call_raw(0x04, DATA)
+        self.assertEqual(gdb.output, 'xyz')
+
+    def test_open_write_close(self):
+        fd = self.open(self.path('out.bin'), 5)
+        self.assertEqual(fd, 3)
+
+        self.put(DATA, b'\x00\x01\x02')
+        self.assertEqual(self.call(0x05, fd, DATA, 3), 0)
+        self.assertEqual(self.call(0x0C, fd), 3)
+        self.assertEqual(self.call(0x02, fd), 0)
+
+        with open(self.path('out.bin'), 'rb') as f:
+            self.assertEqual(f.read(), b'\x00\x01\x02')
+
+    def test_open_missing_file_sets_errno(self):
+        self.assertEqual(self.open(self.path('missing'), 0), -1)
+        self.assertEqual(self.call_raw(0x13, 0), 2)  # ENOENT
+
+    def test_open_tt(self):
+        self.assertEqual(self.open(':tt', 0), 0)
+        self.assertEqual(self.open(':tt', 4), 1)
+
+    def test_read_and_seek(self):
+        with open(self.path('in.bin'), 'wb') as f:
+            f.write(b'abcdef')
+        fd = self.open(self.path('in.bin'), 1)
+
+        # four requested, four read, none left over
+        self.assertEqual(self.call(0x06, fd, DATA, 4), 0)
+        self.assertEqual(bytes(gdb.inferior.memory[DATA:DATA + 4]), b'abcd')
+        # only two left, so two of four are not read
+        self.assertEqual(self.call(0x06, fd, DATA, 4), 2)
+        self.assertEqual(bytes(gdb.inferior.memory[DATA:DATA + 2]), b'ef')
+
+        self.assertEqual(self.call(0x0A, fd, 1), 0)
+        self.assertEqual(self.call(0x06, fd, DATA, 1), 0)
+        self.assertEqual(bytes(gdb.inferior.memory[DATA:DATA + 1]), b'b')
+
+    def test_read_stdin(self):
+        stdin = sys.stdin
+        sys.stdin = io.StringIO(u'stats\nhosts\n')
+        try:
+            self.assertEqual(self.call(0x06, 0, DATA, 16), 10)
+            self.assertEqual(bytes(gdb.inferior.memory[DATA:DATA + 6]), b'stats\n')
+            self.assertEqual(self.call_raw(0x07, 0), ord('h'))
+        finally:
+            sys.stdin = stdin
+
+    def test_read_stdin_without_input_does_not_block(self):
+        ready = SemiHostHelper.stdin_ready
+        SemiHostHelper.stdin_ready = classmethod(lambda cls: False)
+        try:
+            self.assertEqual(self.call(0x06, 0, DATA, 16), 16)
+        finally:
+            SemiHostHelper.stdin_ready = ready
+
+    def test_close_unknown_fd(self):
+        self.assertEqual(self.call(0x02, 42), -1)
+        self.assertEqual(self.call_raw(0x13, 0), 9)  # EBADF
+
+    def test_clock_and_time(self):
+        self.assertGreaterEqual(self.call_raw(0x10, 0), 0)
+        self.assertGreater(self.call_raw(0x11, 0), 0)
+
+    def test_get_cmdline(self):
+        SemiHostHelper.cmdline = 'capture'
+        self.assertEqual(self.call(0x15, DATA, 32), 0)
+        self.assertEqual(bytes(gdb.inferior.memory[DATA:DATA + 8]), b'capture\0')
+        self.assertEqual(struct.unpack('<L', bytes(gdb.inferior.memory[ARGS + 4:ARGS + 8]))[0], 7)
+
+        # too small for the terminator
+        self.assertEqual(self.call(0x15, DATA, 7), -1)
+
+    def test_exit_stops(self):
+        self.call_raw(0x18, SemiHostHelper.ADP_STOPPED_APPLICATION_EXIT)
+        self.assertEqual(gdb.convenience['$do_continue'], 0)
+        self.assertIn('exited', gdb.output)
+
+    def test_unknown_call(self):
+        with self.assertRaises(NotImplementedError):
+            self.call_raw(0x30, 0)
+
+
+if __name__ == '__main__':
+    unittest.main()